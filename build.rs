@@ -0,0 +1,22 @@
+// Messages and service stubs of proto/invoice_conversion.proto for bin/grpc_server.rs.
+// The .proto is parsed by protobuf-parse's pure Rust parser, so the build does not need protoc.
+use prost::Message as _;
+use protobuf::Message as _;
+
+const PROTO: &str = "proto/invoice_conversion.proto";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let parsed = protobuf_parse::Parser::new()
+        .pure()
+        .include("proto")
+        .input(PROTO)
+        .file_descriptor_set()?;
+    let fds = prost_types::FileDescriptorSet::decode(parsed.write_to_bytes()?.as_slice())?;
+
+    tonic_build::configure()
+        .build_client(false)
+        .bytes([".invoice_conversion.ConvertInvoicesChunk.data"])
+        .compile_fds(fds)?;
+    println!("cargo:rerun-if-changed={PROTO}");
+    Ok(())
+}
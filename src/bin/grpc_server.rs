@@ -1,3 +1,26 @@
-fn main() {
+use libs::utils::appstate::appstate::init_app_state;
+use libs::utils::config::app_config::AppConfig;
+use libs::utils::errors::log_error::init_logger;
+use libs::utils::grpc::invoice_conversion_proto::invoice_conversion_server::InvoiceConversionServer;
+use libs::utils::grpc::invoice_conversion_service::InvoiceConversionService;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
     println!("grpc server");
+    init_logger();
+
+    let config = AppConfig::load()?;
+    println!("Config: {config}");
+
+    let app_state = init_app_state(&config).await?;
+
+    let addr = config.server.grpc_bind.parse()?;
+    tonic::transport::Server::builder()
+        .add_service(InvoiceConversionServer::new(InvoiceConversionService::new(
+            app_state,
+        )))
+        .serve(addr)
+        .await?;
+
+    Ok(())
 }
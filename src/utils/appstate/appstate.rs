@@ -1,6 +1,20 @@
+use crate::utils::common::xslt_cache::XsltCache;
+use crate::utils::common::xslt_disk_cache::XsltDiskCache;
+use crate::utils::config::app_config::{AppConfig, ObjectStoreBackend};
 use crate::utils::database_manager::init_database;
+use crate::utils::errors::app_errors::{AppError, ErrCtx};
+use crate::utils::incoming_invoice::tenant_databases::TenantDatabases;
+use crate::utils::jobs::conversion_jobs::JobManager;
+use crate::utils::object_store::fs_store::FsStore;
 use crate::utils::object_store::object_store::Store;
-use crate::utils::rest_handlers::get_invoices_handler;
+use crate::utils::object_store::opendal_minio_wrapper::DalStore;
+use crate::utils::object_store::opendal_mssql_wrapper::MssqlStore;
+use crate::utils::rest_handlers::{
+    download_docs_handler, get_invoices_handler, jobs_handler, stream_invoices_handler,
+    xslt_cache_stats_handler,
+};
+use crate::utils::scheduler::fair_scheduler::FairScheduler;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
 use axum::routing::*;
 use tokio::sync::Semaphore;
 
@@ -13,13 +27,132 @@ pub struct AppState {
     pub db_pools: init_database::DbPools,
     pub object_store: Store,
     pub blocking_limiter: Arc<Semaphore>, // NEW
+    /// Hands out `blocking_limiter` permits per `client_id`, see `[scheduler]`
+    pub scheduler: FairScheduler,
+    /// Invoices a conversion prepares ahead of its worker, `server.prefetch_per_request`
+    pub prefetch_per_request: usize,
+    /// Upper bound of a conversion's transform threads, `server.transform_workers_per_request`
+    pub transform_workers_per_request: usize,
+    /// Ordered XSLT engines, the next one is tried when the previous fails
+    pub xslt_engine_chain: Vec<XsltEngineKind>,
+    /// Stylesheets shared by all requests (bytes, and compiled where the engine allows)
+    pub xslt_cache: Arc<XsltCache>,
+    /// Persistent layer behind `xslt_cache`, `None` when disabled
+    pub xslt_disk_cache: Option<Arc<XsltDiskCache>>,
+    /// Detached conversion jobs (`/api/v1/jobs`)
+    pub jobs: Arc<JobManager>,
+    /// INCOMING_INVOICE database of each receiver
+    pub tenant_databases: TenantDatabases,
 }
 
-pub fn create_app(state: SharedState) -> Router {
-    let api_v1 = Router::new().route(
-        "/docs_from_objstore",
-        get(get_invoices_handler::get_invoices_handler),
+/// Pools, object store and XSLT caches shared by the REST and gRPC servers.
+/// Fails when the database, the object store or the job result directory cannot be initialized.
+pub async fn init_app_state(config: &AppConfig) -> Result<SharedState, AppError> {
+    let routes = config.pools.clone();
+    let db_pools = match config.object_store.backend {
+        // Only INCOMING_INVOICE queries need the database, connect when they come
+        ObjectStoreBackend::Fs | ObjectStoreBackend::S3 if !config.needs_database() => {
+            println!("DB pools connect on first use (no [database] configured).");
+            Ok(init_database::lazy_db_connection_pools(
+                &config.database,
+                routes,
+            ))
+        }
+        ObjectStoreBackend::Fs => {
+            println!("DB pools connect on first use (fs object store).");
+            Ok(init_database::lazy_db_connection_pools(
+                &config.database,
+                routes,
+            ))
+        }
+        _ => init_database::init_db_connection_pools(&config.database, routes).await,
+    };
+    let db_pools = db_pools.ctx("init_app_state/db")?;
+    println!(
+        "✅ Default DB pool initialized, {} routed pools created on first use.",
+        db_pools.registry.len()
+    );
+    let object_store = match (config.object_store.backend, &config.minio) {
+        (ObjectStoreBackend::S3, Some(minio)) => {
+            Store::Dal(DalStore::new_minio(minio).ctx("init_app_state/object_store")?)
+        }
+        (ObjectStoreBackend::Fs, _) => Store::Fs(FsStore::new(&config.object_store.dir)),
+        _ => Store::Mssql(MssqlStore::new_mssql(db_pools.clone())),
+    };
+    println!("Object store: {}", config.object_store.backend.as_str());
+
+    // Requests may still move their preferred engine to the front
+    let xslt_engine_chain = config.xslt.engines.clone();
+    println!("XSLT engine chain: {xslt_engine_chain:?}");
+
+    // Stylesheets survive restarts in the disk cache, warm the memory cache from it
+    let xslt_cache = Arc::new(XsltCache::new(config.xslt_cache));
+    let xslt_disk_cache = match XsltDiskCache::new(config.xslt_disk_cache.clone()) {
+        Ok(disk_cache) => {
+            match disk_cache.warm_up(&xslt_cache) {
+                Ok(loaded) => println!("✅ {loaded} stylesheets loaded from {}", disk_cache.dir()),
+                Err(err) => eprintln!("⚠️ xslt disk cache warm-up failed: {err}"),
+            }
+            Some(Arc::new(disk_cache))
+        }
+        Err(err) => {
+            eprintln!("⚠️ xslt disk cache disabled: {err}");
+            None
+        }
+    };
+
+    let jobs = Arc::new(JobManager::new(config.jobs.clone()).ctx("init_app_state/jobs")?);
+    jobs.start_expiry_sweeper(std::time::Duration::from_secs(60));
+
+    let tenant_databases = config.tenant_databases();
+    println!(
+        "Tenant databases: default {}, {} mapped receivers",
+        tenant_databases.default_db,
+        tenant_databases.by_vkntckn.len()
     );
+
+    let blocking_limiter = Arc::new(Semaphore::new(config.server.max_blocking_tasks));
+    let scheduler = FairScheduler::new(blocking_limiter.clone(), config.scheduler.clone());
+
+    Ok(Arc::new(AppState {
+        db_pools,
+        object_store,
+        blocking_limiter,
+        scheduler,
+        prefetch_per_request: config.server.prefetch_per_request,
+        transform_workers_per_request: config.server.transform_workers_per_request,
+        xslt_engine_chain,
+        xslt_cache,
+        xslt_disk_cache,
+        jobs,
+        tenant_databases,
+    }))
+}
+
+pub fn create_app(state: SharedState) -> Router {
+    let api_v1 = Router::new()
+        .route(
+            "/docs_from_objstore",
+            get(get_invoices_handler::get_invoices_handler),
+        )
+        .route(
+            "/docs_from_objstore/stream",
+            get(stream_invoices_handler::stream_invoices_handler),
+        )
+        .route(
+            "/download_docs",
+            get(download_docs_handler::download_docs_handler),
+        )
+        .route("/jobs", post(jobs_handler::submit_job_handler))
+        .route(
+            "/jobs/{id}",
+            get(jobs_handler::job_status_handler).delete(jobs_handler::cancel_job_handler),
+        )
+        .route("/jobs/{id}/result", get(jobs_handler::job_result_handler))
+        .route(
+            "/xslt_cache/stats",
+            get(xslt_cache_stats_handler::xslt_cache_stats_handler),
+        );
     //.route("/upload", post(upload_handler));
     // Main router
     Router::new()
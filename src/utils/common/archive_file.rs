@@ -0,0 +1,143 @@
+use crate::utils::common::archive_stream::{ChunkSender, StreamingZipSink};
+use crate::utils::common::target_types_and_formats::TargetCompressionType;
+use crate::utils::common::zip_utils::ZipFile;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use tar::Builder as TarBuilder;
+use tokio::sync::mpsc;
+use tokio_util::bytes;
+use xz2::write::XzEncoder;
+use zip::ZipWriter;
+use zip::result::ZipError;
+use zip::write::FileOptions;
+
+/// Streaming archive writer used by the blocking worker.
+///
+/// Every backend writes its entries straight into an anonymous temp file,
+/// so only the finished archive is held in memory (on `close`).
+/// The `*Stream` variants send the archive out in chunks while it is written
+/// instead, see `new_streaming`.
+pub enum ArchiveFile {
+    Zip(ZipFile),
+    TarXz(TarBuilder<XzEncoder<File>>),
+    TarGz(TarBuilder<GzEncoder<File>>),
+    ZipStream {
+        zip: ZipWriter<StreamingZipSink>,
+        sink: StreamingZipSink,
+        opts: FileOptions,
+    },
+    TarXzStream(TarBuilder<XzEncoder<ChunkSender>>),
+    TarGzStream(TarBuilder<GzEncoder<ChunkSender>>),
+}
+
+impl ArchiveFile {
+    pub fn new(compression_type: TargetCompressionType) -> io::Result<Self> {
+        match compression_type {
+            TargetCompressionType::Zip => Ok(ArchiveFile::Zip(ZipFile::new()?)),
+            TargetCompressionType::Tzip => {
+                let xz = XzEncoder::new(tempfile::tempfile()?, 6); // level 6 is a good default
+                Ok(ArchiveFile::TarXz(TarBuilder::new(xz)))
+            }
+            TargetCompressionType::Gzip => {
+                let gz = GzEncoder::new(tempfile::tempfile()?, Compression::default());
+                Ok(ArchiveFile::TarGz(TarBuilder::new(gz)))
+            }
+        }
+    }
+
+    /// Archive whose bytes are sent to `tx` as they are produced.
+    /// `close` then returns an empty buffer. Must be written from a blocking thread.
+    pub fn new_streaming(
+        compression_type: TargetCompressionType,
+        tx: mpsc::Sender<bytes::Bytes>,
+    ) -> Self {
+        match compression_type {
+            TargetCompressionType::Zip => {
+                let sink = StreamingZipSink::new(tx);
+                ArchiveFile::ZipStream {
+                    zip: ZipWriter::new(sink.clone()),
+                    sink,
+                    opts: FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                }
+            }
+            TargetCompressionType::Tzip => {
+                ArchiveFile::TarXzStream(TarBuilder::new(XzEncoder::new(ChunkSender::new(tx), 6)))
+            }
+            TargetCompressionType::Gzip => ArchiveFile::TarGzStream(TarBuilder::new(
+                GzEncoder::new(ChunkSender::new(tx), Compression::default()),
+            )),
+        }
+    }
+
+    pub fn write_entry(&mut self, filename: &str, content: bytes::Bytes) -> io::Result<()> {
+        match self {
+            ArchiveFile::Zip(zip) => zip.write_to_zip(filename, content),
+            ArchiveFile::TarXz(tar) => append_tar_entry(tar, filename, &content),
+            ArchiveFile::TarGz(tar) => append_tar_entry(tar, filename, &content),
+            ArchiveFile::ZipStream { zip, sink, opts } => {
+                // start_file patches the previous entry's header, after that it is final
+                let entry_start = sink.len();
+                zip.start_file(filename, *opts)?;
+                sink.commit_upto(entry_start)?;
+                zip.write_all(&content)
+            }
+            ArchiveFile::TarXzStream(tar) => append_tar_entry(tar, filename, &content),
+            ArchiveFile::TarGzStream(tar) => append_tar_entry(tar, filename, &content),
+        }
+    }
+
+    /// Finalize the archive (footer / encoder trailer) and read it back.
+    /// Streaming archives flush their last chunk and return an empty buffer.
+    pub fn close(self) -> Result<Vec<u8>, ZipError> {
+        match self {
+            ArchiveFile::Zip(zip) => zip.close_zip(),
+            ArchiveFile::TarXz(tar) => {
+                // Close TAR, then XZ, then read the temp file back
+                let file = tar.into_inner()?.finish()?;
+                Ok(read_back(file)?)
+            }
+            ArchiveFile::TarGz(tar) => {
+                let file = tar.into_inner()?.finish()?;
+                Ok(read_back(file)?)
+            }
+            ArchiveFile::ZipStream { mut zip, sink, .. } => {
+                zip.finish()?;
+                sink.finish()?;
+                Ok(Vec::new())
+            }
+            ArchiveFile::TarXzStream(tar) => {
+                tar.into_inner()?.finish()?.flush()?;
+                Ok(Vec::new())
+            }
+            ArchiveFile::TarGzStream(tar) => {
+                tar.into_inner()?.finish()?.flush()?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+fn append_tar_entry<W: io::Write>(
+    tar: &mut TarBuilder<W>,
+    filename: &str,
+    content: &[u8],
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(content.len() as u64);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, filename, content)
+}
+
+fn read_back(mut file: File) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(0))?;
+    let len = file.metadata()?.len() as usize;
+    let mut buffer = Vec::with_capacity(len);
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
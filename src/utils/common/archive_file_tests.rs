@@ -0,0 +1,70 @@
+use super::archive_file::ArchiveFile;
+use super::target_types_and_formats::TargetCompressionType;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use tokio_util::bytes::Bytes;
+use xz2::read::XzDecoder;
+
+fn entries() -> Vec<(&'static str, Bytes)> {
+    vec![
+        ("Fat_0.html", Bytes::from_static(b"<html>first</html>")),
+        ("Fat_1.html", Bytes::from_static(b"<html>second</html>")),
+    ]
+}
+
+fn build(compression_type: TargetCompressionType) -> Vec<u8> {
+    let mut archive = ArchiveFile::new(compression_type).expect("archive create failed");
+    for (name, content) in entries() {
+        archive
+            .write_entry(name, content)
+            .expect("archive write failed");
+    }
+    archive.close().expect("archive close failed")
+}
+
+fn tar_entries<R: Read>(reader: R) -> Vec<(String, Vec<u8>)> {
+    let mut tar = tar::Archive::new(reader);
+    tar.entries()
+        .unwrap()
+        .map(|e| {
+            let mut e = e.unwrap();
+            let name = e.path().unwrap().to_string_lossy().to_string();
+            let mut content = Vec::new();
+            e.read_to_end(&mut content).unwrap();
+            (name, content)
+        })
+        .collect()
+}
+
+#[test]
+fn zip_archive_roundtrip() {
+    let data = build(TargetCompressionType::Zip);
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).expect("not a zip");
+    assert_eq!(zip.len(), 2);
+    let mut content = String::new();
+    zip.by_name("Fat_1.html")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "<html>second</html>");
+}
+
+#[test]
+fn tar_xz_archive_roundtrip() {
+    let data = build(TargetCompressionType::Tzip);
+    let got = tar_entries(XzDecoder::new(Cursor::new(data)));
+    let expected: Vec<(String, Vec<u8>)> = entries()
+        .into_iter()
+        .map(|(n, c)| (n.to_string(), c.to_vec()))
+        .collect();
+    assert_eq!(got, expected);
+}
+
+#[test]
+fn tar_gz_archive_roundtrip() {
+    let data = build(TargetCompressionType::Gzip);
+    let got = tar_entries(GzDecoder::new(Cursor::new(data)));
+    assert_eq!(got.len(), 2);
+    assert_eq!(got[0].0, "Fat_0.html");
+    assert_eq!(got[0].1, b"<html>first</html>");
+}
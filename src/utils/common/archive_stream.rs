@@ -0,0 +1,157 @@
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::bytes::Bytes;
+
+/// Chunks smaller than this are buffered before being sent.
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `Write` end of a streamed archive: buffers and forwards chunks to the receiver
+/// (the HTTP / gRPC body). Must be used from a blocking thread.
+pub struct ChunkSender {
+    tx: mpsc::Sender<Bytes>,
+    buf: Vec<u8>,
+}
+
+impl ChunkSender {
+    pub fn new(tx: mpsc::Sender<Bytes>) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        }
+    }
+
+    fn send_buffered(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(STREAM_CHUNK_SIZE),
+        ));
+        self.tx.blocking_send(chunk).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "archive stream receiver dropped")
+        })
+    }
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= STREAM_CHUNK_SIZE {
+            self.send_buffered()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffered()
+    }
+}
+
+struct ZipTail {
+    out: ChunkSender,
+    /// Bytes from `tail_start` on, still patchable by the zip writer
+    tail: Vec<u8>,
+    tail_start: u64,
+    pos: u64,
+}
+
+/// Zip writers seek back to patch the local header of the entry they just
+/// finished. Everything before the current entry is final, so only that
+/// tail is kept in memory and the rest is streamed.
+///
+/// The tail is the whole compressed entry: nothing of it is sent before the
+/// next entry starts (or `finish`), so a large PDF is held twice, as the
+/// document and as its deflated copy. zip 0.6 can not write data descriptors
+/// to avoid the seek; the tar.xz / tar.gz streams have no such tail.
+///
+/// Cloned handles share the same state: one goes into the `ZipWriter`,
+/// the other is used by the archive to `commit_upto` between entries.
+#[derive(Clone)]
+pub struct StreamingZipSink {
+    inner: Arc<Mutex<ZipTail>>,
+}
+
+impl StreamingZipSink {
+    pub fn new(tx: mpsc::Sender<Bytes>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ZipTail {
+                out: ChunkSender::new(tx),
+                tail: Vec::new(),
+                tail_start: 0,
+                pos: 0,
+            })),
+        }
+    }
+
+    /// Total bytes written so far (streamed + kept).
+    pub fn len(&self) -> u64 {
+        let t = self.lock();
+        t.tail_start + t.tail.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stream everything before `offset`; the writer must not seek before it anymore.
+    pub fn commit_upto(&self, offset: u64) -> io::Result<()> {
+        let mut t = self.lock();
+        let end = t.tail_start + t.tail.len() as u64;
+        let offset = offset.clamp(t.tail_start, end);
+        let n = (offset - t.tail_start) as usize;
+        let committed: Vec<u8> = t.tail.drain(..n).collect();
+        t.out.write_all(&committed)?;
+        t.tail_start = offset;
+        Ok(())
+    }
+
+    /// Stream the rest and flush, called once the archive is finished.
+    pub fn finish(&self) -> io::Result<()> {
+        self.commit_upto(u64::MAX)?;
+        self.lock().out.flush()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ZipTail> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for StreamingZipSink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut t = self.lock();
+        let at = (t.pos - t.tail_start) as usize;
+        let overlap = data.len().min(t.tail.len().saturating_sub(at));
+        t.tail[at..at + overlap].copy_from_slice(&data[..overlap]);
+        t.tail.extend_from_slice(&data[overlap..]);
+        t.pos += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(()) // bytes leave on commit only, the tail may still be patched
+    }
+}
+
+impl Seek for StreamingZipSink {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let mut t = self.lock();
+        let end = t.tail_start + t.tail.len() as u64;
+        let target = match to {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => end.checked_add_signed(d),
+            SeekFrom::Current(d) => t.pos.checked_add_signed(d),
+        };
+        match target {
+            Some(p) if p >= t.tail_start && p <= end => {
+                t.pos = p;
+                Ok(p)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek outside the uncommitted part of a streamed zip",
+            )),
+        }
+    }
+}
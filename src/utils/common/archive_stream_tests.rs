@@ -0,0 +1,84 @@
+use super::archive_file::ArchiveFile;
+use super::archive_stream::STREAM_CHUNK_SIZE;
+use super::target_types_and_formats::TargetCompressionType;
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+use tokio::sync::mpsc;
+use tokio_util::bytes::Bytes;
+
+fn entries() -> Vec<(String, Bytes)> {
+    // Pseudo random content so the compressed archive still spans several chunks
+    let mut seed: u32 = 0x9E37_79B9;
+    (0..6)
+        .map(|i| {
+            let content: Vec<u8> = (0..40_000)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed as u8
+                })
+                .collect();
+            (format!("Fat_{i}.html"), Bytes::from(content))
+        })
+        .collect()
+}
+
+/// Write on a separate thread, collect the chunks here. Returns (archive, chunk count).
+fn build_streamed(compression_type: TargetCompressionType) -> (Vec<u8>, usize) {
+    let (tx, mut rx) = mpsc::channel::<Bytes>(2);
+    let writer = std::thread::spawn(move || {
+        let mut archive = ArchiveFile::new_streaming(compression_type, tx);
+        for (name, content) in entries() {
+            archive.write_entry(&name, content).expect("write failed");
+        }
+        archive.close().expect("close failed")
+    });
+
+    let mut data = Vec::new();
+    let mut chunks = 0;
+    while let Some(chunk) = rx.blocking_recv() {
+        data.extend_from_slice(&chunk);
+        chunks += 1;
+    }
+    assert!(writer.join().unwrap().is_empty());
+    (data, chunks)
+}
+
+#[test]
+fn streamed_zip_is_readable() {
+    let (data, chunks) = build_streamed(TargetCompressionType::Zip);
+    assert!(chunks > 1, "expected the zip to leave in several chunks");
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).expect("not a zip");
+    assert_eq!(zip.len(), 6);
+    for (name, content) in entries() {
+        let mut got = Vec::new();
+        zip.by_name(&name).unwrap().read_to_end(&mut got).unwrap();
+        assert_eq!(got, content.to_vec(), "{name}");
+    }
+}
+
+#[test]
+fn streamed_tar_gz_is_readable() {
+    let (data, _) = build_streamed(TargetCompressionType::Gzip);
+    let mut tar = tar::Archive::new(GzDecoder::new(Cursor::new(data)));
+    let names: Vec<String> = tar
+        .entries()
+        .unwrap()
+        .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+        .collect();
+    let expected: Vec<String> = entries().into_iter().map(|(n, _)| n).collect();
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn dropped_receiver_fails_the_writer() {
+    let (tx, rx) = mpsc::channel::<Bytes>(1);
+    drop(rx);
+    let mut archive = ArchiveFile::new_streaming(TargetCompressionType::Gzip, tx);
+    let big = Bytes::from(vec![7u8; STREAM_CHUNK_SIZE * 4]);
+    let res = archive
+        .write_entry("Fat_0.html", big)
+        .and_then(|_| archive.close().map(|_| ()).map_err(std::io::Error::other));
+    assert!(res.is_err());
+}
@@ -1,8 +1,9 @@
 use crate::utils::errors::invoice_conversion_errors::InvConvError;
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 use std::time::Duration;
 use tokio_util::bytes;
 use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
 
 pub const DECOMPRESS_ASYNC_THRESHOLD: i32 = 2 * 1024 * 1024; // 2MB
 pub async fn xz_decompress(
@@ -64,3 +65,80 @@ fn decompress_sync(
 
     Ok(decompressed_vec.into())
 }
+
+/// Xz at level 6, as the archives are written
+pub fn compress_sync(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = XzEncoder::new(Vec::with_capacity(data.len() / 4 + 64), 6);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Content as the object store keeps it and its original size:
+/// xz data is kept as is, anything else is compressed.
+pub fn ensure_xz(data: Vec<u8>) -> std::io::Result<(Vec<u8>, u64)> {
+    match xz_uncompressed_size(&data) {
+        Some(size) => Ok((data, size)),
+        None => Ok((compress_sync(&data)?, data.len() as u64)),
+    }
+}
+
+/// Uncompressed size recorded in the index of each xz stream, `None` when `data` is not
+/// a well formed xz file. Reads only the stream footers and indexes, nothing is decoded.
+pub fn xz_uncompressed_size(data: &[u8]) -> Option<u64> {
+    const HEADER_LEN: usize = 12;
+    const FOOTER_LEN: usize = 12;
+
+    let mut end = data.len();
+    let mut total = 0u64;
+    let mut streams = 0;
+    while end > 0 {
+        // Stream padding: null bytes in groups of four
+        if data[..end].ends_with(&[0; 4]) {
+            end -= 4;
+            continue;
+        }
+        let footer = data.get(end.checked_sub(FOOTER_LEN)?..end)?;
+        if &footer[10..] != b"YZ" {
+            return None;
+        }
+        let backward_size = (u32::from_le_bytes(footer[4..8].try_into().ok()?) as usize + 1) * 4;
+        let index_start = (end - FOOTER_LEN).checked_sub(backward_size)?;
+        let mut index = data.get(index_start..end - FOOTER_LEN)?;
+
+        // Index indicator, record count, then (unpadded size, uncompressed size) per block
+        if index.first() != Some(&0) {
+            return None;
+        }
+        index = &index[1..];
+        let records = read_xz_varint(&mut index)?;
+        let mut blocks_len = 0usize;
+        for _ in 0..records {
+            let unpadded = read_xz_varint(&mut index)?;
+            total = total.checked_add(read_xz_varint(&mut index)?)?;
+            blocks_len = blocks_len.checked_add(usize::try_from(unpadded.div_ceil(4) * 4).ok()?)?;
+        }
+
+        let stream_start = index_start
+            .checked_sub(blocks_len)?
+            .checked_sub(HEADER_LEN)?;
+        if !data[stream_start..].starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            return None;
+        }
+        end = stream_start;
+        streams += 1;
+    }
+    (streams > 0).then_some(total)
+}
+
+fn read_xz_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
@@ -0,0 +1,40 @@
+use super::comp_decompress::{xz_decompress, xz_uncompressed_size};
+use std::io::Write;
+use xz2::write::XzEncoder;
+
+fn xz(data: &[u8]) -> Vec<u8> {
+    let mut encoder = XzEncoder::new(Vec::new(), 6);
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn size_comes_from_the_xz_index() {
+    let xml = "<Invoice>çğüşöı</Invoice>".repeat(5000);
+    let compressed = xz(xml.as_bytes());
+    let size = xz_uncompressed_size(&compressed).unwrap();
+    assert_eq!(size, xml.len() as u64);
+
+    let out = xz_decompress(compressed.into(), size as usize, "o1")
+        .await
+        .unwrap();
+    assert_eq!(out, xml.as_bytes());
+}
+
+#[test]
+fn concatenated_and_padded_streams_add_up() {
+    let mut data = xz(b"first stream");
+    data.extend_from_slice(&[0; 8]);
+    data.extend(xz(b""));
+    data.extend(xz(b"second"));
+    assert_eq!(xz_uncompressed_size(&data), Some(18));
+}
+
+#[test]
+fn not_xz_is_none() {
+    assert_eq!(xz_uncompressed_size(b""), None);
+    assert_eq!(xz_uncompressed_size(b"<Invoice/>"), None);
+    let mut truncated = xz(b"payload");
+    truncated.drain(..4);
+    assert_eq!(xz_uncompressed_size(&truncated), None);
+}
@@ -0,0 +1,73 @@
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use printpdf::{Mm, PdfDocument};
+use tokio_util::bytes::Bytes;
+
+// A4 portrait, monospaced layout
+const PAGE_WIDTH_MM: f32 = 210.0;
+const PAGE_HEIGHT_MM: f32 = 297.0;
+const MARGIN_MM: f32 = 12.0;
+const FONT_SIZE_PT: f32 = 7.0;
+const LINE_HEIGHT_MM: f32 = 3.2;
+/// Monospaced glyphs are 0.6em wide: 7pt ~ 1.48mm per column, 186mm usable width.
+pub const COLUMNS_PER_LINE: usize = 124;
+
+/// Embedded so Turkish letters and box drawing render the same on every host
+static MONO_FONT: &[u8] = include_bytes!("../../../assets/fonts/DejaVuSansMono.ttf");
+
+/// Render the XSLT output (HTML) into a PDF document.
+///
+/// - The PDF is a text rendering: `html2text` lays the HTML out as text (tables keep their
+///   columns, styling and images are lost), written line by line onto A4 pages in DejaVu Sans Mono.
+/// - Pure Rust, no headless browser or system libraries required.
+pub fn html_to_pdf(html: &Bytes, title: &str) -> Result<Bytes, InvConvError> {
+    let text = html2text::config::plain()
+        .string_from_read(html.as_ref(), COLUMNS_PER_LINE)
+        .map_err(|e| InvConvError::PdfRenderError(format!("{title}: html layout: {e}")))?;
+
+    let lines = wrap_lines(&text, COLUMNS_PER_LINE);
+    let lines_per_page = ((PAGE_HEIGHT_MM - 2.0 * MARGIN_MM) / LINE_HEIGHT_MM) as usize;
+
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(title, Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1");
+    let font = doc
+        .add_external_font(MONO_FONT)
+        .map_err(|e| InvConvError::PdfRenderError(format!("{title}: font: {e}")))?;
+
+    let mut page_layer = Some((first_page, first_layer));
+    // An empty document still produces one (blank) page.
+    for chunk in lines.chunks(lines_per_page.max(1)) {
+        let (page, layer) = page_layer
+            .take()
+            .unwrap_or_else(|| doc.add_page(Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1"));
+        let layer = doc.get_page(page).get_layer(layer);
+
+        let mut y = PAGE_HEIGHT_MM - MARGIN_MM;
+        for line in chunk {
+            if !line.trim().is_empty() {
+                layer.use_text(line.as_str(), FONT_SIZE_PT, Mm(MARGIN_MM), Mm(y), &font);
+            }
+            y -= LINE_HEIGHT_MM;
+        }
+    }
+
+    let pdf = doc
+        .save_to_bytes()
+        .map_err(|e| InvConvError::PdfRenderError(format!("{title}: save: {e}")))?;
+    Ok(Bytes::from(pdf))
+}
+
+/// Hard-wrap lines that html2text could not fit (very wide tables).
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            out.push(String::new());
+            continue;
+        }
+        for chunk in chars.chunks(width) {
+            out.push(chunk.iter().collect());
+        }
+    }
+    out
+}
@@ -0,0 +1,69 @@
+use super::html_to_pdf::html_to_pdf;
+use regex::Regex;
+use std::collections::HashMap;
+use tokio_util::bytes::Bytes;
+
+/// Text shown by the `Tj` operators, glyph ids mapped back through the ToUnicode CMap
+fn pdf_text(pdf: &[u8]) -> String {
+    let pdf = String::from_utf8_lossy(pdf);
+    let hex = |digits: &str| u32::from_str_radix(digits, 16).unwrap();
+    let bfchar = Regex::new(r"<([0-9a-fA-F]{4})> <([0-9a-fA-F]{4,6})>").unwrap();
+    let unicode: HashMap<u32, char> = bfchar
+        .captures_iter(&pdf)
+        .filter_map(|c| Some((hex(&c[1]), char::from_u32(hex(&c[2]))?)))
+        .collect();
+    let shown = Regex::new(r"<([0-9a-fA-F]+)> Tj").unwrap();
+    let mut text = String::new();
+    for c in shown.captures_iter(&pdf) {
+        for i in (0..c[1].len()).step_by(4) {
+            let glyph = hex(&c[1][i..i + 4]);
+            text.push(unicode.get(&glyph).copied().unwrap_or('?'));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[test]
+fn html_to_pdf_produces_pdf() {
+    let html = Bytes::from_static(
+        "<html><body><h1>Fatura</h1><table><tr><td>Şirket</td><td>100,00 TL</td></tr></table></body></html>"
+            .as_bytes(),
+    );
+    let pdf = html_to_pdf(&html, "Fat_1").expect("pdf render failed");
+    assert!(pdf.starts_with(b"%PDF-"));
+}
+
+#[test]
+fn html_to_pdf_paginates_long_documents() {
+    let rows: String = (0..500).map(|i| format!("<p>Satir {i}</p>")).collect();
+    let html = Bytes::from(format!("<html><body>{rows}</body></html>"));
+    let pdf = html_to_pdf(&html, "Fat_2").expect("pdf render failed");
+    assert!(pdf.starts_with(b"%PDF-"));
+}
+
+#[test]
+fn html_to_pdf_accepts_empty_html() {
+    let pdf = html_to_pdf(&Bytes::new(), "Fat_3").expect("pdf render failed");
+    assert!(pdf.starts_with(b"%PDF-"));
+}
+
+#[test]
+fn html_to_pdf_embeds_the_unicode_font() {
+    let html = Bytes::from_static("<p>Öğrenci İşleri, ışık, çağrı</p>".as_bytes());
+    let pdf = html_to_pdf(&html, "Fat_4").expect("pdf render failed");
+    let name = b"DejaVuSansMono";
+    assert!(pdf.windows(name.len()).any(|w| w == name));
+}
+
+#[test]
+fn html_to_pdf_keeps_the_invoice_text() {
+    let html = Bytes::from_static(
+        "<html><body><h1>Fatura</h1><table><tr><td>Fatura No</td><td>FTR2024000000123</td></tr><tr><td>Alıcı</td><td>Öğrenci İşleri</td></tr></table></body></html>"
+            .as_bytes(),
+    );
+    let pdf = html_to_pdf(&html, "Fat_5").expect("pdf render failed");
+    let text = pdf_text(&pdf);
+    assert!(text.contains("FTR2024000000123"), "{text}");
+    assert!(text.contains("Öğrenci İşleri"), "{text}");
+}
@@ -1,6 +1,26 @@
+pub mod archive_file;
+pub mod archive_stream;
 pub mod build_zip;
 pub mod comp_decompress;
+pub mod html_to_pdf;
 pub mod san_desanitize;
 pub mod target_types_and_formats;
+pub mod xslt_cache;
+pub mod xslt_disk_cache;
 pub mod xslt_struct;
 pub mod zip_utils;
+
+#[cfg(test)]
+mod archive_file_tests;
+#[cfg(test)]
+mod archive_stream_tests;
+#[cfg(test)]
+mod comp_decompress_tests;
+#[cfg(test)]
+mod html_to_pdf_tests;
+#[cfg(test)]
+mod san_desanitize_tests;
+#[cfg(test)]
+mod xslt_cache_tests;
+#[cfg(test)]
+mod xslt_disk_cache_tests;
@@ -1,9 +1,12 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
+use regex::bytes::Regex as BytesRegex;
 use std::borrow::Cow;
 use tokio_util::bytes;
 
 static ENTITY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"&#((x[0-9A-Fa-f]+|\d+));").unwrap());
+static SANITIZED_RE: Lazy<BytesRegex> =
+    Lazy::new(|| BytesRegex::new(r"-sanitized-((x[0-9A-Fa-f]+|\d+))--").unwrap());
 #[inline]
 fn is_xml_char(code: u32) -> bool {
     code == 0x9
@@ -48,3 +51,12 @@ pub fn sanitize_fast(content_bytes: bytes::Bytes) -> Result<bytes::Bytes, std::s
         Cow::Owned(s) => Ok(bytes::Bytes::from(s.into_bytes())), // Replacements made: owned data
     }
 }
+
+/// Reverse of `sanitize_fast`: "-sanitized-$2--" back to the "&#$2;" entity it replaced.
+/// Works on bytes, the input does not have to be UTF-8.
+pub fn desanitize(content_bytes: bytes::Bytes) -> bytes::Bytes {
+    match SANITIZED_RE.replace_all(content_bytes.as_ref(), &b"&#$1;"[..]) {
+        Cow::Borrowed(_) => content_bytes,
+        Cow::Owned(restored) => bytes::Bytes::from(restored),
+    }
+}
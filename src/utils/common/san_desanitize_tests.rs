@@ -0,0 +1,19 @@
+use super::san_desanitize::{desanitize, sanitize_fast};
+use tokio_util::bytes::Bytes;
+
+#[test]
+fn desanitize_restores_the_sanitized_entities() {
+    let original = Bytes::from_static(b"<Note>a&#x1F;b&#8;c &#x41; -sanitized-x--</Note>");
+    let sanitized = sanitize_fast(original.clone()).unwrap();
+    assert_eq!(
+        sanitized,
+        Bytes::from_static(b"<Note>a-sanitized-x1F--b-sanitized-8--c &#x41; -sanitized-x--</Note>")
+    );
+    assert_eq!(desanitize(sanitized), original);
+}
+
+#[test]
+fn desanitize_keeps_clean_and_non_utf8_input() {
+    let clean = Bytes::from_static(b"<Note>\xff\xfe</Note>");
+    assert_eq!(desanitize(clean.clone()), clean);
+}
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "PascalCase")]
 pub enum TargetType {
+    #[default]
     Html,
     Pdf,
     Ubl,
@@ -20,16 +21,23 @@ impl fmt::Display for TargetType {
         }
     }
 }
-impl Default for TargetType {
-    fn default() -> Self {
-        TargetType::Html
+impl TargetType {
+    /// Extension of each document written into the archive
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Pdf => "pdf",
+            Self::Ubl => "xml",
+            Self::UblXsltSeparate => "xml",
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TargetCompressionType {
-    Zip,  // .zip
+    #[default]
+    Zip, // .zip
     Tzip, // .tar.xz
     Gzip, // .tar.gz  (multi-file via tar.gz)
 }
@@ -42,9 +50,22 @@ impl fmt::Display for TargetCompressionType {
         }
     }
 }
-impl Default for TargetCompressionType {
-    fn default() -> Self {
-        TargetCompressionType::Zip
+impl TargetCompressionType {
+    /// Extension of the produced archive
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tzip => "tar.xz",
+            Self::Gzip => "tar.gz",
+        }
+    }
+    /// HTTP content type of the produced archive
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Tzip => "application/x-xz",
+            Self::Gzip => "application/gzip",
+        }
     }
 }
 
@@ -0,0 +1,209 @@
+use crate::utils::config::app_config::secs;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::libxslt_engine::{LibXsltCompiledStylesheet, LibXsltEngine};
+use crate::utils::xslt_engine::xslt_engine::XsltEngine;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio_util::bytes::Bytes;
+
+/// Limits of the process-wide stylesheet cache.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XsltCacheConfig {
+    /// Max number of stylesheets kept
+    pub max_entries: usize,
+    /// Max total size of the cached stylesheet bytes
+    pub max_bytes: usize,
+    /// Entries older than this are dropped on access
+    #[serde(rename = "ttl_secs", with = "secs")]
+    pub ttl: Duration,
+}
+impl Default for XsltCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 256,
+            max_bytes: 64 * 1024 * 1024,
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Counters and current size, served by the stats endpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct XsltCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub compiled_hits: u64,
+    pub compiled_misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+struct CachedXslt {
+    bytes: Bytes,
+    /// Only engines whose compiled form is Send + Sync are shared here.
+    /// xrust's `Context<RNode>` is Rc based, so xrust recompiles per request.
+    libxslt: Option<LibXsltCompiledStylesheet>,
+    inserted_at: Instant,
+}
+
+struct Inner {
+    lru: LruCache<String, CachedXslt>,
+    total_bytes: usize,
+}
+
+/// Bounded LRU of stylesheets shared by all requests, keyed by xslt key.
+pub struct XsltCache {
+    config: XsltCacheConfig,
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    compiled_hits: AtomicU64,
+    compiled_misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl XsltCache {
+    pub fn new(config: XsltCacheConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                lru: LruCache::unbounded(),
+                total_bytes: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            compiled_hits: AtomicU64::new(0),
+            compiled_misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// Stylesheet bytes, `None` when missing or expired.
+    pub fn get_bytes(&self, xslt_key: &str) -> Option<Bytes> {
+        let mut inner = self.lock();
+        let found = self
+            .live_entry(&mut inner, xslt_key)
+            .map(|entry| entry.bytes.clone());
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        found
+    }
+
+    /// Insert (or replace) stylesheet bytes, evicting least recently used entries
+    /// until both limits hold. A stylesheet larger than `max_bytes` is not cached.
+    pub fn insert_bytes(&self, xslt_key: &str, bytes: Bytes) {
+        let mut inner = self.lock();
+        self.put_locked(&mut inner, xslt_key, bytes, None);
+    }
+
+    /// Shared libxslt stylesheet for `xslt_key`, compiled once per process.
+    /// Compilation runs outside the lock; compile errors are not cached.
+    pub fn get_or_compile_libxslt(
+        &self,
+        xslt_key: &str,
+        xslt: &Bytes,
+        engine: &LibXsltEngine,
+    ) -> Result<LibXsltCompiledStylesheet, InvConvError> {
+        {
+            let mut inner = self.lock();
+            if let Some(compiled) = self
+                .live_entry(&mut inner, xslt_key)
+                .and_then(|entry| entry.libxslt.clone())
+            {
+                self.compiled_hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(compiled);
+            }
+        }
+        self.compiled_misses.fetch_add(1, Ordering::Relaxed);
+
+        let compiled = engine.compile(xslt)?;
+
+        let mut inner = self.lock();
+        match inner.lru.peek_mut(xslt_key) {
+            Some(entry) if entry.bytes == *xslt => entry.libxslt = Some(compiled.clone()),
+            Some(_) => {} // replaced meanwhile by a different version, keep that one
+            None => self.put_locked(&mut inner, xslt_key, xslt.clone(), Some(compiled.clone())),
+        }
+        Ok(compiled)
+    }
+
+    pub fn stats(&self) -> XsltCacheStats {
+        let inner = self.lock();
+        XsltCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            compiled_hits: self.compiled_hits.load(Ordering::Relaxed),
+            compiled_misses: self.compiled_misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            entries: inner.lru.len(),
+            bytes: inner.total_bytes,
+        }
+    }
+
+    /// Entry for `xslt_key` (marked as recently used), removing it if the TTL passed.
+    fn live_entry<'a>(&self, inner: &'a mut Inner, xslt_key: &str) -> Option<&'a CachedXslt> {
+        let expired = inner
+            .lru
+            .peek(xslt_key)
+            .is_some_and(|entry| entry.inserted_at.elapsed() >= self.config.ttl);
+        if expired {
+            if let Some(old) = inner.lru.pop(xslt_key) {
+                inner.total_bytes -= old.bytes.len();
+            }
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        inner.lru.get(xslt_key)
+    }
+
+    fn put_locked(
+        &self,
+        inner: &mut Inner,
+        xslt_key: &str,
+        bytes: Bytes,
+        libxslt: Option<LibXsltCompiledStylesheet>,
+    ) {
+        if self.config.max_entries == 0 || bytes.len() > self.config.max_bytes {
+            return;
+        }
+        if let Some(old) = inner.lru.pop(xslt_key) {
+            inner.total_bytes -= old.bytes.len();
+        }
+        inner.total_bytes += bytes.len();
+        inner.lru.put(
+            xslt_key.to_string(),
+            CachedXslt {
+                bytes,
+                libxslt,
+                inserted_at: Instant::now(),
+            },
+        );
+
+        while inner.lru.len() > self.config.max_entries || inner.total_bytes > self.config.max_bytes
+        {
+            match inner.lru.pop_lru() {
+                Some((_, evicted)) => {
+                    inner.total_bytes -= evicted.bytes.len();
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // A panic while holding the lock leaves the cache consistent enough to keep using.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
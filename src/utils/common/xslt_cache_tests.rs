@@ -0,0 +1,78 @@
+use super::xslt_cache::{XsltCache, XsltCacheConfig};
+use crate::utils::xslt_engine::libxslt_engine::LibXsltEngine;
+use std::time::Duration;
+use tokio_util::bytes::Bytes;
+
+const XSLT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+  <xsl:template match="/"><p><xsl:value-of select="/a"/></p></xsl:template>
+</xsl:stylesheet>"#;
+
+fn cache(max_entries: usize, max_bytes: usize, ttl: Duration) -> XsltCache {
+    XsltCache::new(XsltCacheConfig {
+        max_entries,
+        max_bytes,
+        ttl,
+    })
+}
+
+#[test]
+fn counts_hits_and_misses() {
+    let cache = XsltCache::new(XsltCacheConfig::default());
+    assert!(cache.get_bytes("k1").is_none());
+    cache.insert_bytes("k1", Bytes::from_static(b"abc"));
+    assert_eq!(cache.get_bytes("k1").unwrap(), Bytes::from_static(b"abc"));
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+    assert_eq!((stats.entries, stats.bytes), (1, 3));
+}
+
+#[test]
+fn evicts_least_recently_used_over_entry_limit() {
+    let cache = cache(2, 1024, Duration::from_secs(60));
+    cache.insert_bytes("k1", Bytes::from_static(b"1"));
+    cache.insert_bytes("k2", Bytes::from_static(b"2"));
+    cache.get_bytes("k1"); // k2 is now the least recently used
+    cache.insert_bytes("k3", Bytes::from_static(b"3"));
+
+    assert!(cache.get_bytes("k2").is_none());
+    assert!(cache.get_bytes("k1").is_some());
+    assert!(cache.get_bytes("k3").is_some());
+    assert_eq!(cache.stats().evictions, 1);
+}
+
+#[test]
+fn evicts_over_byte_limit_and_skips_oversized() {
+    let cache = cache(10, 8, Duration::from_secs(60));
+    cache.insert_bytes("k1", Bytes::from_static(b"12345"));
+    cache.insert_bytes("k2", Bytes::from_static(b"12345"));
+    assert!(cache.get_bytes("k1").is_none());
+
+    cache.insert_bytes("big", Bytes::from_static(b"123456789"));
+    assert!(cache.get_bytes("big").is_none());
+    assert_eq!(cache.stats().bytes, 5);
+}
+
+#[test]
+fn expires_entries_after_ttl() {
+    let cache = cache(10, 1024, Duration::ZERO);
+    cache.insert_bytes("k1", Bytes::from_static(b"abc"));
+    assert!(cache.get_bytes("k1").is_none());
+
+    let stats = cache.stats();
+    assert_eq!((stats.expirations, stats.entries, stats.bytes), (1, 0, 0));
+}
+
+#[test]
+fn compiles_libxslt_once() {
+    let cache = XsltCache::new(XsltCacheConfig::default());
+    let engine = LibXsltEngine::new();
+    let xslt = Bytes::from_static(XSLT);
+    cache.get_or_compile_libxslt("k1", &xslt, &engine).unwrap();
+    cache.get_or_compile_libxslt("k1", &xslt, &engine).unwrap();
+
+    let stats = cache.stats();
+    assert_eq!((stats.compiled_hits, stats.compiled_misses), (1, 1));
+    assert_eq!(cache.get_bytes("k1").unwrap(), xslt);
+}
@@ -0,0 +1,201 @@
+use crate::utils::common::xslt_cache::XsltCache;
+use crate::utils::common::xslt_struct::{TMP_PREFIX, XsltStruct};
+use crate::utils::errors::log_error::log_error;
+use crate::utils::errors::xslt_errors::{ErrCtx, XsltError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tokio_util::bytes::Bytes;
+
+/// Temp files older than this are from interrupted writes, younger ones may still be written
+const STALE_TMP_AGE: Duration = Duration::from_secs(600);
+
+/// Where and how much the on-disk stylesheet cache may keep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XsltDiskCacheConfig {
+    pub dir: String,
+    /// Total size of the cached stylesheets, least recently used are removed above it
+    pub max_bytes: u64,
+}
+impl Default for XsltDiskCacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: "/tmp/xslt_cache_dir".to_string(),
+            max_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Persistent stylesheet cache in front of the object store, one
+/// `XsltStruct` file (`{stem}.xslt`, checksum on its first line) per stylesheet.
+///
+/// File mtime is the last access time, eviction removes the oldest first.
+/// All methods do blocking file IO.
+pub struct XsltDiskCache {
+    config: XsltDiskCacheConfig,
+    /// Size of the cached stylesheets, scanned in `new` and kept up to date by
+    /// `put`; writes and removals hold it so the total stays exact.
+    total_bytes: Mutex<u64>,
+}
+
+impl XsltDiskCache {
+    pub fn new(config: XsltDiskCacheConfig) -> Result<Self, XsltError> {
+        fs::create_dir_all(&config.dir).ctx("XsltDiskCache:create_dir")?;
+        let total: u64 = stylesheets(&config.dir)?
+            .iter()
+            .map(|(_, len, _)| len)
+            .sum();
+        Ok(Self {
+            config,
+            total_bytes: Mutex::new(total),
+        })
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.config.dir
+    }
+
+    /// Cached stylesheet bytes. A corrupt file (checksum mismatch) is removed
+    /// and reported as a miss so the caller refetches it.
+    pub fn get(&self, xslt_key: &str) -> Option<Bytes> {
+        match XsltStruct::load(&self.config.dir, xslt_key) {
+            Ok(Some(xslt_struct)) => {
+                touch(&xslt_struct.xslt_path);
+                Some(Bytes::from(xslt_struct.xslt))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log_error(&e);
+                let mut total = self.total();
+                let _ = self.remove(&mut total, xslt_key);
+                None
+            }
+        }
+    }
+
+    /// Write (atomically) and evict if the stylesheets grew over `max_bytes`.
+    pub fn put(&self, xslt_key: &str, xslt: &Bytes) -> Result<(), XsltError> {
+        let mut total = self.total();
+        let replaced = stylesheet_len(&XsltStruct::xslt_path(&self.config.dir, xslt_key));
+        XsltStruct::new(xslt.to_vec(), &self.config.dir, xslt_key)?;
+        *total = (*total + xslt.len() as u64).saturating_sub(replaced);
+        if *total > self.config.max_bytes {
+            self.evict_locked(&mut total)?;
+        }
+        Ok(())
+    }
+
+    /// Remove least recently used stylesheets until the total size fits. Returns the removed count.
+    pub fn evict(&self) -> Result<usize, XsltError> {
+        self.evict_locked(&mut self.total())
+    }
+
+    /// Scans the directory, so only called once the running total is over the limit.
+    /// The total is recounted from the scan.
+    fn evict_locked(&self, total: &mut u64) -> Result<usize, XsltError> {
+        let mut files = stylesheets(&self.config.dir)?;
+        *total = files.iter().map(|(_, len, _)| len).sum();
+
+        files.sort_by_key(|(accessed, _, _)| *accessed);
+        let mut removed = 0;
+        for (_, _, key) in files {
+            if *total <= self.config.max_bytes {
+                break;
+            }
+            self.remove(total, &key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn remove(&self, total: &mut u64, xslt_key: &str) -> Result<(), XsltError> {
+        let len = stylesheet_len(&XsltStruct::xslt_path(&self.config.dir, xslt_key));
+        XsltStruct::remove(&self.config.dir, xslt_key)?;
+        *total = total.saturating_sub(len);
+        Ok(())
+    }
+
+    fn total(&self) -> MutexGuard<'_, u64> {
+        self.total_bytes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Load every valid stylesheet on disk into the in-memory cache, most
+    /// recently used last so they are the last to be evicted. Returns the loaded count.
+    /// Temp files left by interrupted writes are deleted.
+    pub fn warm_up(&self, memory_cache: &XsltCache) -> Result<usize, XsltError> {
+        self.remove_stale_temp_files()?;
+
+        let mut keys = stylesheets(&self.config.dir)?;
+        keys.sort();
+
+        let mut loaded = 0;
+        for (_, _, key) in keys {
+            // Not `get`: warm-up must not refresh the access times.
+            match XsltStruct::load(&self.config.dir, &key) {
+                Ok(Some(xslt_struct)) => {
+                    memory_cache.insert_bytes(&key, Bytes::from(xslt_struct.xslt));
+                    loaded += 1;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log_error(&e);
+                    let mut total = self.total();
+                    let _ = self.remove(&mut total, &key);
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// `TMP_PREFIX` files older than `STALE_TMP_AGE`, returns the removed count
+    fn remove_stale_temp_files(&self) -> Result<usize, XsltError> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.config.dir).ctx("XsltDiskCache:temp_files:read_dir")? {
+            let entry = entry.ctx("XsltDiskCache:temp_files:entry")?;
+            if !entry.file_name().to_string_lossy().starts_with(TMP_PREFIX) {
+                continue;
+            }
+            let stale = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified).unwrap_or_default() >= STALE_TMP_AGE
+                });
+            if stale && fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Last access, size and key of every cached stylesheet in `dir`
+fn stylesheets(dir: &str) -> Result<Vec<(SystemTime, u64, String)>, XsltError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).ctx("XsltDiskCache:scan:read_dir")? {
+        let entry = entry.ctx("XsltDiskCache:scan:entry")?;
+        let Some(key) = XsltStruct::key_from_path(&entry.path()) else {
+            continue;
+        };
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let accessed = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        files.push((accessed, XsltStruct::stylesheet_len(meta.len()), key));
+    }
+    Ok(files)
+}
+
+fn stylesheet_len(path: &std::path::Path) -> u64 {
+    fs::metadata(path).map_or(0, |m| XsltStruct::stylesheet_len(m.len()))
+}
+
+/// Best effort: mark as recently used for eviction.
+fn touch(path: &std::path::Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
@@ -0,0 +1,149 @@
+use super::xslt_cache::{XsltCache, XsltCacheConfig};
+use super::xslt_disk_cache::{XsltDiskCache, XsltDiskCacheConfig};
+use super::xslt_struct::{TMP_PREFIX, XsltStruct, sha256_hex};
+use std::fs;
+use std::time::{Duration, SystemTime};
+use tokio_util::bytes::Bytes;
+
+const KEY: &str = "MAAAAAAAAAAAAAAAAAAAAA==S/+AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+fn disk_cache(dir: &tempfile::TempDir, max_bytes: u64) -> XsltDiskCache {
+    XsltDiskCache::new(XsltDiskCacheConfig {
+        dir: dir.path().to_str().unwrap().to_string(),
+        max_bytes,
+    })
+    .unwrap()
+}
+
+#[test]
+fn roundtrip_survives_a_new_instance() {
+    let dir = tempfile::tempdir().unwrap();
+    disk_cache(&dir, 1024)
+        .put(KEY, &Bytes::from_static(b"<xsl/>"))
+        .unwrap();
+
+    // "restart": a fresh cache over the same directory
+    let cache = disk_cache(&dir, 1024);
+    assert_eq!(cache.get(KEY).unwrap(), Bytes::from_static(b"<xsl/>"));
+    assert!(cache.get("missing").is_none());
+}
+
+#[test]
+fn key_maps_to_a_flat_file_and_back() {
+    let path = XsltStruct::xslt_path("/tmp/x", KEY);
+    assert_eq!(path.parent().unwrap().to_str().unwrap(), "/tmp/x");
+    assert_eq!(XsltStruct::key_from_path(&path).unwrap(), KEY);
+}
+
+#[test]
+fn corrupt_file_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 1024);
+    cache.put(KEY, &Bytes::from_static(b"<xsl/>")).unwrap();
+
+    let dir_str = dir.path().to_str().unwrap();
+    fs::write(XsltStruct::xslt_path(dir_str, KEY), b"<tampered/>").unwrap();
+
+    assert!(cache.get(KEY).is_none());
+    assert!(!XsltStruct::xslt_path(dir_str, KEY).exists());
+}
+
+#[test]
+fn evicts_least_recently_used_over_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 10);
+    let dir_str = dir.path().to_str().unwrap();
+
+    cache.put("old", &Bytes::from_static(b"123456")).unwrap();
+    // make "old" clearly older than the next write
+    let old_path = XsltStruct::xslt_path(dir_str, "old");
+    fs::File::options()
+        .write(true)
+        .open(&old_path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+    cache.put("new", &Bytes::from_static(b"123456")).unwrap();
+
+    assert!(cache.get("old").is_none());
+    assert!(cache.get("new").is_some());
+}
+
+#[test]
+fn warm_up_fills_the_memory_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 1024);
+    cache.put("k1", &Bytes::from_static(b"one")).unwrap();
+    cache.put("k2", &Bytes::from_static(b"two")).unwrap();
+    fs::write(dir.path().join("unrelated.txt"), b"ignored").unwrap();
+
+    let memory = XsltCache::new(XsltCacheConfig::default());
+    assert_eq!(cache.warm_up(&memory).unwrap(), 2);
+    assert_eq!(memory.get_bytes("k2").unwrap(), Bytes::from_static(b"two"));
+}
+
+#[test]
+fn keys_with_underscore_and_dash_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 1024);
+    // Alike under a `/`->`_`, `+`->`-` mapping
+    let keys = ["MA_b-c==S/+x", "MA/b+c==S_-x", "MA_b-c==S_-x"];
+    for (n, key) in keys.iter().enumerate() {
+        cache
+            .put(key, &Bytes::from(format!("<xsl n=\"{n}\"/>")))
+            .unwrap();
+    }
+
+    let memory = XsltCache::new(XsltCacheConfig::default());
+    assert_eq!(cache.warm_up(&memory).unwrap(), keys.len());
+    for (n, key) in keys.iter().enumerate() {
+        let expected = Bytes::from(format!("<xsl n=\"{n}\"/>"));
+        assert_eq!(memory.get_bytes(key).unwrap(), expected);
+        let path = XsltStruct::xslt_path(dir.path().to_str().unwrap(), key);
+        assert_eq!(XsltStruct::key_from_path(&path).unwrap(), *key);
+    }
+}
+
+#[test]
+fn rewriting_a_key_does_not_count_it_twice() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 10);
+    cache.put("a", &Bytes::from_static(b"123456")).unwrap();
+    cache.put("a", &Bytes::from_static(b"1234567")).unwrap();
+    assert_eq!(cache.get("a").unwrap(), Bytes::from_static(b"1234567"));
+}
+
+#[test]
+fn warm_up_removes_stale_temp_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 1024);
+    let stale = dir.path().join(format!("{TMP_PREFIX}stale"));
+    let fresh = dir.path().join(format!("{TMP_PREFIX}fresh"));
+    fs::write(&stale, b"half written").unwrap();
+    fs::write(&fresh, b"being written").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&stale)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(3600))
+        .unwrap();
+
+    let memory = XsltCache::new(XsltCacheConfig::default());
+    assert_eq!(cache.warm_up(&memory).unwrap(), 0);
+    assert!(!stale.exists());
+    assert!(fresh.exists());
+}
+
+#[test]
+fn checksum_is_written_with_the_stylesheet() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = disk_cache(&dir, 1024);
+    cache.put(KEY, &Bytes::from_static(b"<xsl/>")).unwrap();
+
+    // One file per stylesheet, persisted by one rename
+    let files: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let file = fs::read(XsltStruct::xslt_path(dir.path().to_str().unwrap(), KEY)).unwrap();
+    let expected = format!("sha256:{}\n<xsl/>", sha256_hex(b"<xsl/>"));
+    assert_eq!(file, expected.as_bytes());
+}
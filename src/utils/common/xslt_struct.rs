@@ -1,30 +1,104 @@
 use crate::utils::errors::xslt_errors::{ErrCtx, XsltError};
-use libxml::parser::Parser;
-use libxslt::parser::parse_file;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use libxslt::stylesheet::Stylesheet;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 pub struct XsltStruct {
     pub xslt: Vec<u8>,
     pub xslt_path: PathBuf, // Persistent file path
+    /// Hex sha256 of `xslt`, the `sha256:{checksum}` first line of the file
+    pub checksum: String,
     pub compiled_xslt: Option<Stylesheet>,
 }
 impl XsltStruct {
+    /// Write the stylesheet, its checksum on the first line, into `cache_dir`.
+    /// The file is written to a temp file first and renamed, so readers never see
+    /// a half written stylesheet or a checksum of another one.
     pub fn new(xslt: Vec<u8>, cache_dir: &str, xslt_key: &str) -> Result<Self, XsltError> {
         // Ensure cache directory exists
         fs::create_dir_all(cache_dir).ctx("XsltStruct:create_dir")?;
 
         // Write XSLT to persistent cache file
-        let xslt_path = PathBuf::from(cache_dir).join(format!("{}.xslt", xslt_key));
-        fs::write(&xslt_path, &xslt).ctx("XsltStruct:write")?;
+        let xslt_path = Self::xslt_path(cache_dir, xslt_key);
+        let checksum = sha256_hex(&xslt);
+        let mut file = Vec::with_capacity(HEADER_LEN as usize + xslt.len());
+        file.extend_from_slice(format!("{CHECKSUM_PREFIX}{checksum}\n").as_bytes());
+        file.extend_from_slice(&xslt);
+        write_atomic(cache_dir, &xslt_path, &file).ctx("XsltStruct:write")?;
 
         Ok(Self {
             xslt,
             xslt_path,
+            checksum,
             compiled_xslt: None,
         })
     }
+
+    /// Read a stylesheet written by `new`.
+    /// `Ok(None)` when it is not cached, `ChecksumMismatch` when the file is corrupt.
+    pub fn load(cache_dir: &str, xslt_key: &str) -> Result<Option<Self>, XsltError> {
+        let xslt_path = Self::xslt_path(cache_dir, xslt_key);
+        let mut xslt = match fs::read(&xslt_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).ctx("XsltStruct:load:read"),
+        };
+        let header_len = HEADER_LEN as usize;
+        let stored = xslt
+            .get(..header_len)
+            .and_then(|header| header.strip_prefix(CHECKSUM_PREFIX.as_bytes()))
+            .and_then(|header| header.strip_suffix(b"\n"))
+            .map(|stored| String::from_utf8_lossy(stored).into_owned());
+        let xslt = if stored.is_some() {
+            xslt.split_off(header_len)
+        } else {
+            xslt
+        };
+
+        let checksum = sha256_hex(&xslt);
+        if stored.as_deref() != Some(checksum.as_str()) {
+            return Err(XsltError::ChecksumMismatch(xslt_key.to_string()));
+        }
+
+        Ok(Some(Self {
+            xslt,
+            xslt_path,
+            checksum,
+            compiled_xslt: None,
+        }))
+    }
+
+    /// Remove the stylesheet, a missing file is not an error.
+    pub fn remove(cache_dir: &str, xslt_key: &str) -> Result<(), XsltError> {
+        match fs::remove_file(Self::xslt_path(cache_dir, xslt_key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).ctx("XsltStruct:remove"),
+        }
+    }
+
+    /// Stylesheet bytes in a file of `file_len` bytes written by `new`
+    pub fn stylesheet_len(file_len: u64) -> u64 {
+        file_len.saturating_sub(HEADER_LEN)
+    }
+
+    /// `{cache_dir}/{stem}.xslt`, the stem is the key in unpadded base64url so every key
+    /// gets its own file and comes back unchanged from `key_from_path`.
+    pub fn xslt_path(cache_dir: &str, xslt_key: &str) -> PathBuf {
+        PathBuf::from(cache_dir).join(format!("{}.xslt", key_to_file_stem(xslt_key)))
+    }
+
+    /// Inverse of the mapping in `xslt_path`, `None` for files that are not cached stylesheets.
+    pub fn key_from_path(path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_str()?;
+        let stem = name.strip_suffix(".xslt")?;
+        let key = URL_SAFE_NO_PAD.decode(stem).ok()?;
+        String::from_utf8(key).ok()
+    }
     /*
     /// Compile the XSLT if not already compiled (lazy compilation)
     /// Only compiles once, then reuses
@@ -68,3 +142,28 @@ impl XsltStruct {
     }
     */
 }
+
+fn key_to_file_stem(xslt_key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(xslt_key)
+}
+
+/// Temp files of `write_atomic`, left behind when a write is interrupted
+pub const TMP_PREFIX: &str = ".tmp-xslt-";
+
+/// First line of a cached stylesheet file: `sha256:{64 hex digits}\n`
+const CHECKSUM_PREFIX: &str = "sha256:";
+const HEADER_LEN: u64 = (CHECKSUM_PREFIX.len() + 64 + 1) as u64;
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn write_atomic(cache_dir: &str, path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = tempfile::Builder::new()
+        .prefix(TMP_PREFIX)
+        .tempfile_in(cache_dir)?;
+    tmp.write_all(data)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
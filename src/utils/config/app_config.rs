@@ -0,0 +1,729 @@
+use crate::utils::common::xslt_cache::XsltCacheConfig;
+use crate::utils::common::xslt_disk_cache::XsltDiskCacheConfig;
+use crate::utils::database_manager::pool_registry::PoolRoute;
+use crate::utils::errors::config_errors::ConfigError;
+use crate::utils::incoming_invoice::tenant_databases::{TenantDatabases, is_valid_db_name};
+use crate::utils::jobs::conversion_jobs::JobsConfig;
+use crate::utils::scheduler::fair_scheduler::SchedulerConfig;
+use crate::utils::xslt_engine::xslt_engine::XsltConfig;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::Duration;
+use toml::{Table, Value};
+
+/// Read when UTILS_SERVER_CONFIG is not set and the file exists
+pub const DEFAULT_CONFIG_FILE: &str = "utils_server.toml";
+
+/// Environment variables, the config key each one overrides and how its text is read.
+const ENV_OVERRIDES: &[(&str, &str, EnvKind)] = &[
+    ("REST_BIND_ADDR", "server.rest_bind", Text),
+    ("GRPC_BIND_ADDR", "server.grpc_bind", Text),
+    ("MAX_BLOCKING_TASKS", "server.max_blocking_tasks", Integer),
+    (
+        "PREFETCH_PER_REQUEST",
+        "server.prefetch_per_request",
+        Integer,
+    ),
+    (
+        "TRANSFORM_WORKERS_PER_REQUEST",
+        "server.transform_workers_per_request",
+        Integer,
+    ),
+    ("DB_HOST", "database.host", Text),
+    ("DB_PORT", "database.port", Integer),
+    ("DB_NAME", "database.database", Text),
+    ("DB_USER", "database.user", Text),
+    ("DB_PASSWORD", "database.password", Text),
+    ("DB_TRUST_CERT", "database.trust_cert", Bool),
+    ("DB_POOL_MAX_SIZE", "database.max_size", Integer),
+    ("DB_POOL_MIN_IDLE", "database.min_idle", Integer),
+    (
+        "DB_MAX_LIFETIME_SECS",
+        "database.max_lifetime_secs",
+        Integer,
+    ),
+    (
+        "DB_IDLE_TIMEOUT_SECS",
+        "database.idle_timeout_secs",
+        Integer,
+    ),
+    (
+        "DB_CONNECTION_TIMEOUT_SECS",
+        "database.connection_timeout_secs",
+        Integer,
+    ),
+    ("TENANT_DB_DEFAULT", "tenants.default_db", Text),
+    ("TENANT_DBS", "tenants.databases", Text),
+    ("OBJECT_STORE_BACKEND", "object_store.backend", Text),
+    ("OBJECT_STORE_DIR", "object_store.dir", Text),
+    ("MINIO_ENDPOINT", "minio.endpoint", Text),
+    ("MINIO_BUCKET", "minio.bucket", Text),
+    ("MINIO_REGION", "minio.region", Text),
+    ("MINIO_ACCESS_KEY_ID", "minio.access_key_id", Text),
+    ("MINIO_SECRET_ACCESS_KEY", "minio.secret_access_key", Text),
+    ("XSLT_ENGINE", "xslt.engines", List),
+    ("XSLT_CACHE_MAX_ENTRIES", "xslt_cache.max_entries", Integer),
+    ("XSLT_CACHE_MAX_BYTES", "xslt_cache.max_bytes", Integer),
+    ("XSLT_CACHE_TTL_SECS", "xslt_cache.ttl_secs", Integer),
+    ("XSLT_DISK_CACHE_DIR", "xslt_disk_cache.dir", Text),
+    (
+        "XSLT_DISK_CACHE_MAX_BYTES",
+        "xslt_disk_cache.max_bytes",
+        Integer,
+    ),
+    ("JOBS_RESULT_DIR", "jobs.dir", Text),
+    ("JOBS_RESULT_TTL_SECS", "jobs.result_ttl_secs", Integer),
+    ("JOBS_MAX_QUEUED", "jobs.max_queued_jobs", Integer),
+    (
+        "SCHEDULER_MAX_RUNNING_PER_CLIENT",
+        "scheduler.max_running_per_client",
+        Integer,
+    ),
+    (
+        "SCHEDULER_QUEUE_CAPACITY",
+        "scheduler.queue_capacity",
+        Integer,
+    ),
+    (
+        "SCHEDULER_QUEUE_TIMEOUT_SECS",
+        "scheduler.queue_timeout_secs",
+        Integer,
+    ),
+];
+
+#[derive(Clone, Copy)]
+enum EnvKind {
+    Text,
+    Integer,
+    /// true / false / 1 / 0
+    Bool,
+    /// Comma separated
+    List,
+}
+use EnvKind::*;
+impl EnvKind {
+    fn value(self, text: &str) -> Result<Value, &'static str> {
+        match self {
+            Text => Ok(Value::from(text)),
+            Integer => text
+                .parse::<i64>()
+                .map(Value::from)
+                .map_err(|_| "expected an integer"),
+            Bool => match text {
+                "true" | "1" => Ok(Value::Boolean(true)),
+                "false" | "0" => Ok(Value::Boolean(false)),
+                _ => Err("expected true or false"),
+            },
+            List => Ok(Value::Array(
+                text.split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(Value::from)
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// `[pools.NAME]` keys of the route itself, the others are `[database]` keys
+const POOL_KEYS: &[&str] = &["role", "years", "tenants", "db_name"];
+
+/// Value kept out of Debug output and logs, read it with `expose`.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+/// Strings only; unlike serde's own messages the error does not quote the value
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(value) => Ok(Secret(value)),
+            _ => Err(D::Error::custom("expected a string")),
+        }
+    }
+}
+
+/// Listen addresses and request limits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub rest_bind: String,
+    pub grpc_bind: String,
+    /// Size of `blocking_limiter`, i.e. concurrent conversions
+    pub max_blocking_tasks: usize,
+    /// Invoices of one conversion fetched and decompressed ahead of the worker
+    pub prefetch_per_request: usize,
+    /// Parallel XSLT transforms of one conversion, each extra one takes a `blocking_limiter` permit
+    pub transform_workers_per_request: usize,
+}
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            rest_bind: "0.0.0.0:3090".to_string(),
+            grpc_bind: "0.0.0.0:3091".to_string(),
+            max_blocking_tasks: 64,
+            prefetch_per_request: 8,
+            transform_workers_per_request: 4,
+        }
+    }
+}
+
+/// MSSQL server of the DB pools and the MSSQL object store.
+/// `host`, `database`, `user` and `password` have no default and must be configured
+/// for the mssql object store, or once `host` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub user: String,
+    #[serde(skip_serializing)]
+    pub password: Secret,
+    /// Accept the server certificate without validation, development only
+    pub trust_cert: bool,
+    pub max_size: u32,
+    pub min_idle: u32,
+    #[serde(rename = "max_lifetime_secs", with = "secs")]
+    pub max_lifetime: Duration,
+    #[serde(rename = "idle_timeout_secs", with = "secs")]
+    pub idle_timeout: Duration,
+    #[serde(rename = "connection_timeout_secs", with = "secs")]
+    pub connection_timeout: Duration,
+}
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 1433,
+            database: String::new(),
+            user: String::new(),
+            password: Secret::default(),
+            trust_cert: false,
+            max_size: 10,
+            min_idle: 2,
+            max_lifetime: Duration::from_secs(60 * 60),
+            idle_timeout: Duration::from_secs(10 * 60),
+            connection_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Where `Store` reads UBLs and XSLTs from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectStoreBackend {
+    /// `OBJECTSTORE_{year}` tables, `object_store` pool routes
+    #[default]
+    Mssql,
+    /// S3/MinIO through OpenDAL, needs `[minio]`
+    S3,
+    /// Files under `object_store.dir`, development and tests
+    Fs,
+}
+impl ObjectStoreBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectStoreBackend::Mssql => "mssql",
+            ObjectStoreBackend::S3 => "s3",
+            ObjectStoreBackend::Fs => "fs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectStoreSettings {
+    pub backend: ObjectStoreBackend,
+    /// Root of the `fs` backend, `{dir}/{bucket}/{year}/{key}`
+    pub dir: String,
+}
+impl Default for ObjectStoreSettings {
+    fn default() -> Self {
+        Self {
+            backend: ObjectStoreBackend::default(),
+            dir: "./objstore".to_string(),
+        }
+    }
+}
+
+/// S3 compatible object store (`DalStore`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MinioConfig {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    #[serde(skip_serializing)]
+    pub secret_access_key: Secret,
+}
+impl Default for MinioConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            bucket: "my-bucket".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: String::new(),
+            secret_access_key: Secret::default(),
+        }
+    }
+}
+
+/// Everything the servers read at startup. The sections deserialize from the TOML
+/// file with their defaults for missing keys; serializing leaves the secrets out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server: ServerSettings,
+    pub database: DatabaseConfig,
+    pub tenants: TenantDatabases,
+    pub object_store: ObjectStoreSettings,
+    /// Only set when a `[minio]` key or MINIO_* variable is given
+    pub minio: Option<MinioConfig>,
+    pub xslt: XsltConfig,
+    pub xslt_cache: XsltCacheConfig,
+    pub xslt_disk_cache: XsltDiskCacheConfig,
+    pub jobs: JobsConfig,
+    pub scheduler: SchedulerConfig,
+    /// `[pools.NAME]` in file order, their connection keys over `[database]`
+    #[serde(skip)]
+    pub pools: Vec<PoolRoute>,
+}
+
+/// The route keys of `[pools.NAME]`
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct PoolKeys {
+    role: String,
+    #[serde(deserialize_with = "years")]
+    years: Option<RangeInclusive<u16>>,
+    tenants: Vec<String>,
+    db_name: Option<String>,
+}
+
+impl AppConfig {
+    /// Defaults, then the TOML file (UTILS_SERVER_CONFIG, else ./utils_server.toml when present),
+    /// then the environment variables; the result is validated.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = match std::env::var("UTILS_SERVER_CONFIG") {
+            Ok(path) => Some(path),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Some(DEFAULT_CONFIG_FILE.to_string())
+            }
+            Err(_) => None,
+        };
+        let text = match &path {
+            Some(path) => std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+                path: path.clone(),
+                source,
+            })?,
+            None => String::new(),
+        };
+        let config = Self::from_toml(path.as_deref().unwrap_or_default(), &text, |name| {
+            std::env::var(name).ok()
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `[section]` tables of `text` over the defaults, then the variables of `ENV_OVERRIDES`
+    /// (empty values are ignored) and `DB_PASSWORD_{NAME}` of the pools. Unknown keys are
+    /// errors, the result is not validated.
+    pub fn from_toml(
+        path: &str,
+        text: &str,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        // toml's Display quotes the source line, which may hold a password
+        let mut tree: Table = text.parse().map_err(|e: toml::de::Error| {
+            let offset = e.span().map_or(0, |span| span.start.min(text.len()));
+            ConfigError::Parse {
+                path: path.to_string(),
+                line: text[..offset].matches('\n').count() + 1,
+                msg: e.message().to_string(),
+            }
+        })?;
+        // In file order (preserve_order), the first matching route wins
+        let pools = match tree.remove("pools") {
+            Some(Value::Table(pools)) => pools,
+            Some(_) => return Err(invalid("pools", "expected a table")),
+            None => Table::new(),
+        };
+
+        let mut env_keys = HashMap::new();
+        for (name, key, kind) in ENV_OVERRIDES {
+            let Some(value) = env(name).filter(|v| !v.trim().is_empty()) else {
+                continue;
+            };
+            let value = kind
+                .value(value.trim())
+                .map_err(|reason| invalid(name, reason))?;
+            let (section, field) = key.split_once('.').expect("section.key");
+            match tree
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(table) => table.insert(field.to_string(), value),
+                _ => return Err(invalid(section, "expected a table")),
+            };
+            env_keys.insert(*key, *name);
+        }
+
+        let database = match tree.get("database") {
+            Some(Value::Table(database)) => database.clone(),
+            _ => Table::new(),
+        };
+        let mut config: Self = deserialize("", Value::Table(tree)).map_err(|e| match e {
+            // Name the variable when its value is the one that failed
+            ConfigError::InvalidValue { key, reason } => ConfigError::InvalidValue {
+                // `xslt.engines[1]` was set by the `xslt.engines` variable
+                key: env_keys
+                    .get(key.split('[').next().unwrap_or_default())
+                    .map_or(key, |name| name.to_string()),
+                reason,
+            },
+            e => e,
+        })?;
+        for (name, keys) in pools {
+            let prefix = format!("pools.{name}");
+            let Value::Table(keys) = keys else {
+                return Err(invalid(&prefix, "expected a table"));
+            };
+            let (route, connection): (Table, Table) = keys
+                .into_iter()
+                .partition(|(key, _)| POOL_KEYS.contains(&key.as_str()));
+            let mut merged = database.clone();
+            merged.extend(connection);
+            let var = format!("DB_PASSWORD_{}", name.to_uppercase());
+            if let Some(value) = env(&var).filter(|v| !v.is_empty()) {
+                merged.insert("password".to_string(), Value::String(value));
+            }
+            let route: PoolKeys = deserialize(&prefix, Value::Table(route))?;
+            config.pools.push(PoolRoute {
+                name,
+                role: route.role,
+                years: route.years,
+                tenants: route.tenants,
+                db_name: route.db_name,
+                database: deserialize(&prefix, Value::Table(merged))?,
+            });
+        }
+        Ok(config)
+    }
+
+    /// `[tenants]`, receivers without an entry go to `database.database` unless `default_db` is set
+    pub fn tenant_databases(&self) -> TenantDatabases {
+        let mut tenants = self.tenants.clone();
+        if tenants.default_db.is_empty() {
+            tenants.default_db = self.database.database.clone();
+        }
+        tenants
+    }
+
+    /// The mssql object store reads `[database]`, the other backends only connect
+    /// for INCOMING_INVOICE queries and need it when `database.host` is set.
+    pub fn needs_database(&self) -> bool {
+        self.object_store.backend == ObjectStoreBackend::Mssql || !self.database.host.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn check(ok: bool, key: &str, reason: &str) -> Result<(), ConfigError> {
+            if ok {
+                Ok(())
+            } else {
+                Err(invalid(key, reason))
+            }
+        }
+        let (server, db) = (&self.server, &self.database);
+        check(
+            server.rest_bind.parse::<SocketAddr>().is_ok(),
+            "server.rest_bind",
+            "expected ip:port",
+        )?;
+        check(
+            server.grpc_bind.parse::<SocketAddr>().is_ok(),
+            "server.grpc_bind",
+            "expected ip:port",
+        )?;
+        check(
+            server.max_blocking_tasks > 0,
+            "server.max_blocking_tasks",
+            "must be at least 1",
+        )?;
+        check(
+            server.prefetch_per_request > 0,
+            "server.prefetch_per_request",
+            "must be at least 1",
+        )?;
+        check(
+            server.transform_workers_per_request > 0,
+            "server.transform_workers_per_request",
+            "must be at least 1",
+        )?;
+
+        if self.needs_database() {
+            validate_database(db, "database")?;
+            check(
+                is_valid_db_name(&db.database),
+                "database.database",
+                "expected uut_YY_N",
+            )?;
+        }
+
+        check(
+            self.tenants.default_db.is_empty() || is_valid_db_name(&self.tenants.default_db),
+            "tenants.default_db",
+            "expected uut_YY_N",
+        )?;
+        for (vkntckn, db_name) in &self.tenants.by_vkntckn {
+            check(
+                is_valid_db_name(db_name),
+                &format!("tenants.databases.{vkntckn}"),
+                "expected uut_YY_N",
+            )?;
+        }
+
+        for route in &self.pools {
+            let prefix = format!("pools.{}", route.name);
+            check(
+                is_identifier(&route.name),
+                &prefix,
+                "pool names are letters, digits and _",
+            )?;
+            check(
+                !route.role.is_empty(),
+                &format!("{prefix}.role"),
+                "is required",
+            )?;
+            check(
+                route
+                    .db_name
+                    .as_deref()
+                    .is_none_or(|name| is_identifier(&name.replace("{year}", "0"))),
+                &format!("{prefix}.db_name"),
+                "letters, digits, _ and {year} only",
+            )?;
+            check(
+                is_identifier(&route.database.database),
+                &format!("{prefix}.database"),
+                "letters, digits and _ only",
+            )?;
+            validate_database(&route.database, &prefix)?;
+        }
+
+        check(
+            self.object_store.backend != ObjectStoreBackend::S3 || self.minio.is_some(),
+            "minio",
+            "is required by the s3 object store backend",
+        )?;
+        check(
+            !self.object_store.dir.is_empty(),
+            "object_store.dir",
+            "is required",
+        )?;
+        if let Some(minio) = &self.minio {
+            check(
+                minio.endpoint.starts_with("http://") || minio.endpoint.starts_with("https://"),
+                "minio.endpoint",
+                "expected an http(s) URL",
+            )?;
+            check(!minio.bucket.is_empty(), "minio.bucket", "is required")?;
+            check(
+                !minio.access_key_id.is_empty(),
+                "minio.access_key_id",
+                "is required",
+            )?;
+            check(
+                !minio.secret_access_key.expose().is_empty(),
+                "minio.secret_access_key",
+                "is required",
+            )?;
+        }
+
+        let engines = &self.xslt.engines;
+        check(!engines.is_empty(), "xslt.engines", "is required")?;
+        check(
+            engines
+                .iter()
+                .enumerate()
+                .all(|(i, engine)| !engines[..i].contains(engine)),
+            "xslt.engines",
+            "lists an engine twice",
+        )?;
+        check(
+            self.xslt_cache.max_entries > 0,
+            "xslt_cache.max_entries",
+            "must be at least 1",
+        )?;
+        check(
+            !self.xslt_disk_cache.dir.is_empty(),
+            "xslt_disk_cache.dir",
+            "is required",
+        )?;
+        check(!self.jobs.dir.is_empty(), "jobs.dir", "is required")?;
+        check(
+            self.jobs.max_queued_jobs > 0,
+            "jobs.max_queued_jobs",
+            "must be at least 1",
+        )?;
+        for (client, weight) in &self.scheduler.weights {
+            check(
+                *weight > 0,
+                &format!("scheduler.weights.{client}"),
+                "must be at least 1",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Startup log line, secrets left out.
+impl fmt::Display for AppConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rest {}, grpc {}, {} blocking tasks, db {}@{}:{}/{}",
+            self.server.rest_bind,
+            self.server.grpc_bind,
+            self.server.max_blocking_tasks,
+            self.database.user,
+            self.database.host,
+            self.database.port,
+            self.database.database
+        )?;
+        write!(f, ", object store {}", self.object_store.backend.as_str())?;
+        if self.object_store.backend == ObjectStoreBackend::Fs {
+            write!(f, " {}", self.object_store.dir)?;
+        }
+        if let Some(minio) = &self.minio {
+            write!(f, ", minio {}/{}", minio.endpoint, minio.bucket)?;
+        }
+        if !self.pools.is_empty() {
+            let names: Vec<&str> = self.pools.iter().map(|p| p.name.as_str()).collect();
+            write!(f, ", pools [{}]", names.join(", "))?;
+        }
+        let engines: Vec<String> = self.xslt.engines.iter().map(|e| e.to_string()).collect();
+        write!(
+            f,
+            ", xslt [{}], xslt disk cache {}, jobs {}",
+            engines.join(", "),
+            self.xslt_disk_cache.dir,
+            self.jobs.dir
+        )
+    }
+}
+
+fn validate_database(db: &DatabaseConfig, prefix: &str) -> Result<(), ConfigError> {
+    let check = |ok: bool, field: &str, reason: &str| {
+        if ok {
+            Ok(())
+        } else {
+            Err(invalid(&format!("{prefix}.{field}"), reason))
+        }
+    };
+    check(!db.host.is_empty(), "host", "is required")?;
+    check(db.port > 0, "port", "must not be 0")?;
+    check(!db.database.is_empty(), "database", "is required")?;
+    check(!db.user.is_empty(), "user", "is required")?;
+    check(
+        !db.password.expose().is_empty(),
+        "password",
+        "is required, set it in the config file or DB_PASSWORD",
+    )?;
+    check(db.max_size > 0, "max_size", "must be at least 1")?;
+    check(
+        db.min_idle <= db.max_size,
+        "min_idle",
+        "must not exceed max_size",
+    )?;
+    check(
+        !db.connection_timeout.is_zero(),
+        "connection_timeout_secs",
+        "must be at least 1",
+    )
+}
+
+/// SQL Server names that end up in query text
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// `T` from `value`. Errors name the key below `prefix` and what was expected,
+/// serde's own messages quote the value and are cut down to that.
+fn deserialize<T: DeserializeOwned>(prefix: &str, value: Value) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let path = e.path().to_string();
+        let key = [prefix, path.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect::<Vec<_>>()
+            .join(".");
+        let msg = e.into_inner().message().to_string();
+        // The path ends with the unknown key
+        if msg.starts_with("unknown field") {
+            return ConfigError::UnknownKey(key);
+        }
+        let reason = match msg.rsplit_once(", expected ") {
+            Some((_, expected)) => format!("expected {expected}"),
+            None => msg,
+        };
+        ConfigError::InvalidValue { key, reason }
+    })
+}
+
+/// `Duration` fields read and written as whole seconds, their keys end in `_secs`
+pub(crate) mod secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_secs())
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// "2019-2023", "2024" or 2024
+fn years<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RangeInclusive<u16>>, D::Error> {
+    let text = match Value::deserialize(deserializer)? {
+        Value::Integer(year) => year.to_string(),
+        Value::String(text) => text,
+        _ => String::new(),
+    };
+    let parse = |v: &str| v.trim().parse::<u16>().ok();
+    let range = match text.split_once('-') {
+        Some((from, to)) => parse(from).zip(parse(to)),
+        None => parse(&text).map(|year| (year, year)),
+    };
+    match range {
+        Some((from, to)) if from <= to => Ok(Some(from..=to)),
+        _ => Err(D::Error::custom("expected YYYY or YYYY-YYYY")),
+    }
+}
+
+fn invalid(key: &str, reason: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,289 @@
+use super::app_config::{AppConfig, Secret};
+use crate::utils::errors::config_errors::ConfigError;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const TOML: &str = r#"
+[server]
+rest_bind = "127.0.0.1:8080"
+max_blocking_tasks = 8
+
+[database]
+host = "db.local"
+database = "uut_25_1"
+user = "reader"
+password = "s3cret-db"
+connection_timeout_secs = 9
+
+[minio]
+endpoint = "https://minio.local"
+access_key_id = "reader"
+secret_access_key = "s3cret-minio"
+
+[jobs]
+dir = "/var/lib/utils_server/jobs"
+"#;
+
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+/// Connection keys without a default
+const DB_ENV: &[(&str, &str)] = &[
+    ("DB_HOST", "db.local"),
+    ("DB_NAME", "uut_25_1"),
+    ("DB_USER", "reader"),
+    ("DB_PASSWORD", "pw"),
+];
+
+fn from_env(vars: &[(&str, &str)]) -> Result<AppConfig, ConfigError> {
+    let vars: Vec<(&str, &str)> = DB_ENV.iter().chain(vars).copied().collect();
+    AppConfig::from_toml("", "", env(&vars))
+}
+
+#[test]
+fn toml_then_env_overrides() {
+    let config = AppConfig::from_toml(
+        "test.toml",
+        TOML,
+        env(&[
+            ("DB_HOST", "db.prod"),
+            ("MAX_BLOCKING_TASKS", "16"),
+            ("PREFETCH_PER_REQUEST", "4"),
+            ("TRANSFORM_WORKERS_PER_REQUEST", "2"),
+            ("DB_TRUST_CERT", "1"),
+        ]),
+    )
+    .unwrap();
+    config.validate().unwrap();
+
+    assert_eq!(config.server.rest_bind, "127.0.0.1:8080");
+    assert_eq!(config.server.grpc_bind, "0.0.0.0:3091");
+    assert_eq!(config.server.max_blocking_tasks, 16);
+    assert_eq!(config.server.prefetch_per_request, 4);
+    assert_eq!(config.server.transform_workers_per_request, 2);
+    assert_eq!(config.database.host, "db.prod");
+    assert_eq!(config.database.database, "uut_25_1");
+    assert_eq!(config.database.password.expose(), "s3cret-db");
+    assert!(config.database.trust_cert);
+    assert_eq!(config.database.connection_timeout, Duration::from_secs(9));
+    assert_eq!(config.minio.as_ref().unwrap().bucket, "my-bucket");
+    assert_eq!(config.jobs.dir, "/var/lib/utils_server/jobs");
+}
+
+#[test]
+fn connection_keys_have_no_defaults() {
+    let defaults = AppConfig::default();
+    assert!(!defaults.database.trust_cert);
+    for (var, key) in [
+        ("DB_HOST", "database.host"),
+        ("DB_NAME", "database.database"),
+        ("DB_USER", "database.user"),
+        ("DB_PASSWORD", "database.password"),
+    ] {
+        let vars: Vec<(&str, &str)> = DB_ENV.iter().filter(|(v, _)| *v != var).copied().collect();
+        let config = AppConfig::from_toml("", "", env(&vars)).unwrap();
+        assert!(
+            matches!(
+                config.validate(),
+                Err(ConfigError::InvalidValue { key: k, .. }) if k == key
+            ),
+            "{var}"
+        );
+    }
+}
+
+#[test]
+fn database_is_optional_without_the_mssql_object_store() {
+    let fs = AppConfig::from_toml("", "", env(&[("OBJECT_STORE_BACKEND", "fs")])).unwrap();
+    assert!(!fs.needs_database());
+    fs.validate().unwrap();
+
+    // Once a host is set the rest of the connection is checked
+    let config = AppConfig::from_toml(
+        "",
+        "",
+        env(&[("OBJECT_STORE_BACKEND", "fs"), ("DB_HOST", "db.local")]),
+    )
+    .unwrap();
+    assert!(config.needs_database());
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "database.database"
+    ));
+}
+
+#[test]
+fn secrets_never_show_in_debug_or_display() {
+    let config = AppConfig::from_toml("test.toml", TOML, env(&[])).unwrap();
+    let logged = format!("{config:?} {config}");
+    assert!(!logged.contains("s3cret"));
+    assert_eq!(format!("{:?}", Secret::new("pw")), "***");
+
+    // Parse errors report the line, not the text around it
+    let err = AppConfig::from_toml("bad.toml", "[database]\npassword = \"s3cret-db\n", env(&[]))
+        .unwrap_err();
+    assert!(matches!(err, ConfigError::Parse { line: 2, .. }));
+    assert!(!err.to_string().contains("s3cret"));
+
+    // Nor do type errors
+    let err =
+        AppConfig::from_toml("t.toml", "[database]\npassword = 12345\n", env(&[])).unwrap_err();
+    assert!(!err.to_string().contains("12345"));
+    let err = AppConfig::from_toml("t.toml", "[database]\nport = 99999\n", env(&[])).unwrap_err();
+    assert!(
+        matches!(&err, ConfigError::InvalidValue { key, .. } if key == "database.port"),
+        "{err}"
+    );
+    assert!(!err.to_string().contains("99999"));
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    assert!(matches!(
+        AppConfig::from_toml("t.toml", "[database]\nprot = 1433\n", env(&[])),
+        Err(ConfigError::UnknownKey(key)) if key == "database.prot"
+    ));
+    assert!(matches!(
+        AppConfig::from_toml("t.toml", "[databse]\nport = 1433\n", env(&[])),
+        Err(ConfigError::UnknownKey(key)) if key == "databse"
+    ));
+    assert!(matches!(
+        from_env(&[("DB_PORT", "70000")]),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "DB_PORT"
+    ));
+    assert!(matches!(
+        from_env(&[("DB_PORT", "x")]),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "DB_PORT"
+    ));
+
+    // Nothing configured
+    assert!(matches!(
+        AppConfig::default().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "database.host"
+    ));
+
+    assert!(matches!(
+        from_env(&[("DB_NAME", "master")]).unwrap().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "database.database"
+    ));
+    assert!(
+        from_env(&[("REST_BIND_ADDR", "localhost")])
+            .unwrap()
+            .validate()
+            .is_err()
+    );
+    assert!(matches!(
+        from_env(&[("OBJECT_STORE_BACKEND", "s3")]).unwrap().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "minio"
+    ));
+    assert!(matches!(
+        from_env(&[("OBJECT_STORE_BACKEND", "ftp")]),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "OBJECT_STORE_BACKEND"
+    ));
+}
+
+#[test]
+fn example_config_matches_the_defaults() {
+    let config = AppConfig::from_toml(
+        "utils_server.example.toml",
+        include_str!("../../../utils_server.example.toml"),
+        env(DB_ENV),
+    )
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(format!("{config}"), format!("{}", from_env(&[]).unwrap()));
+}
+
+#[test]
+fn scheduler_weights_and_quotas() {
+    let config = AppConfig::from_toml(
+        "t.toml",
+        r#"
+[scheduler]
+max_running_per_client = 8
+queue_timeout_secs = 10
+
+[scheduler.weights]
+"1950031086" = 3
+
+[scheduler.quotas]
+"1950031086" = 24
+"#,
+        env(&[("SCHEDULER_QUEUE_CAPACITY", "0")]),
+    )
+    .unwrap();
+
+    assert_eq!(config.scheduler.max_running_per_client, 8);
+    assert_eq!(config.scheduler.queue_capacity, 0);
+    assert_eq!(config.scheduler.queue_timeout, Duration::from_secs(10));
+    assert_eq!(config.scheduler.weights["1950031086"], 3);
+    assert_eq!(config.scheduler.quotas["1950031086"], 24);
+
+    let config =
+        AppConfig::from_toml("t.toml", "[scheduler.weights]\nacme = 0\n", env(DB_ENV)).unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "scheduler.weights.acme"
+    ));
+}
+
+#[test]
+fn xslt_engine_chain() {
+    use crate::utils::xslt_engine::xslt_engine::XsltEngineKind::{LibXslt, Xrust};
+
+    assert_eq!(from_env(&[]).unwrap().xslt.engines, [Xrust, LibXslt]);
+    let config = from_env(&[("XSLT_ENGINE", "libxslt, xrust")]).unwrap();
+    assert_eq!(config.xslt.engines, [LibXslt, Xrust]);
+    let config =
+        AppConfig::from_toml("t.toml", "[xslt]\nengines = [\"libxslt\"]\n", env(DB_ENV)).unwrap();
+    assert_eq!(config.xslt.engines, [LibXslt]);
+
+    assert!(matches!(
+        from_env(&[("XSLT_ENGINE", "xrust,saxon")]),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "XSLT_ENGINE"
+    ));
+    assert!(matches!(
+        from_env(&[("XSLT_ENGINE", "xrust,xrust")]).unwrap().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "xslt.engines"
+    ));
+}
+
+#[test]
+fn tenant_databases() {
+    let config = from_env(&[]).unwrap();
+    assert_eq!(config.tenants.default_db, "");
+    assert_eq!(config.tenant_databases().default_db, "uut_25_1");
+    assert!(config.tenants.by_vkntckn.is_empty());
+
+    let config = AppConfig::from_toml(
+        "t.toml",
+        "[tenants]\ndefault_db = \"uut_24_6\"\n[tenants.databases]\n\"1950031086\" = \"uut_25_1\"\n",
+        env(DB_ENV),
+    )
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.tenant_databases().default_db, "uut_24_6");
+    assert_eq!(config.tenants.by_vkntckn["1950031086"], "uut_25_1");
+
+    let config = from_env(&[("TENANT_DBS", "1950031086=uut_23_2, 1234567890=uut_25_1")]).unwrap();
+    assert_eq!(config.tenants.by_vkntckn["1950031086"], "uut_23_2");
+    assert_eq!(config.tenants.by_vkntckn.len(), 2);
+
+    assert!(matches!(
+        from_env(&[("TENANT_DBS", "1950031086")]),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "TENANT_DBS"
+    ));
+    assert!(matches!(
+        from_env(&[("TENANT_DBS", "1950031086=master")]).unwrap().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "tenants.databases.1950031086"
+    ));
+    assert!(matches!(
+        from_env(&[("TENANT_DB_DEFAULT", "uut_24_6 --")]).unwrap().validate(),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "tenants.default_db"
+    ));
+}
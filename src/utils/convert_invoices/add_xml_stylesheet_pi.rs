@@ -0,0 +1,48 @@
+use sha2::{Digest, Sha256};
+use tokio_util::bytes;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const XSLT_DIR_IN_ZIP: &str = "xslt";
+
+/// Path of a bundled stylesheet inside the archive, named by the first 16 hex digits of the
+/// sha256 of the key. Xslt keys are base64-like, so cleaning them up would let distinct keys
+/// (`a+b`, `a/b`) share one entry.
+pub fn xslt_filename_in_zip(xslt_key: &str) -> String {
+    let digest = Sha256::digest(xslt_key.as_bytes());
+    let stem: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    format!("{XSLT_DIR_IN_ZIP}/{stem}.xslt")
+}
+
+/// Insert `<?xml-stylesheet type="text/xsl" href="..."?>` right after the XML declaration
+/// (or at the very beginning if there is none), so browsers render the UBL with the bundled xslt.
+pub fn add_xml_stylesheet_pi(xml: &bytes::Bytes, href: &str) -> bytes::Bytes {
+    let pi = format!("<?xml-stylesheet type=\"text/xsl\" href=\"{href}\"?>");
+
+    let body_start = if xml.starts_with(UTF8_BOM) {
+        UTF8_BOM.len()
+    } else {
+        0
+    };
+    let body = &xml[body_start..];
+
+    let insert_at = if body.starts_with(b"<?xml ") || body.starts_with(b"<?xml\t") {
+        match body.windows(2).position(|w| w == b"?>") {
+            Some(pos) => body_start + pos + 2,
+            None => body_start,
+        }
+    } else {
+        body_start
+    };
+
+    let mut out = Vec::with_capacity(xml.len() + pi.len() + 1);
+    out.extend_from_slice(&xml[..insert_at]);
+    if insert_at > body_start {
+        out.push(b'\n');
+        out.extend_from_slice(pi.as_bytes());
+    } else {
+        out.extend_from_slice(pi.as_bytes());
+        out.push(b'\n');
+    }
+    out.extend_from_slice(&xml[insert_at..]);
+    bytes::Bytes::from(out)
+}
@@ -0,0 +1,47 @@
+use super::add_xml_stylesheet_pi::{add_xml_stylesheet_pi, xslt_filename_in_zip};
+use tokio_util::bytes::Bytes;
+
+#[test]
+fn pi_goes_after_xml_declaration() {
+    let xml = Bytes::from_static(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Invoice/>");
+    let out = add_xml_stylesheet_pi(&xml, "xslt/abc.xslt");
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<?xml-stylesheet type=\"text/xsl\" href=\"xslt/abc.xslt\"?>\n<Invoice/>"
+    );
+}
+
+#[test]
+fn pi_goes_first_without_xml_declaration() {
+    let xml = Bytes::from_static(b"<Invoice/>");
+    let out = add_xml_stylesheet_pi(&xml, "xslt/abc.xslt");
+    assert_eq!(
+        std::str::from_utf8(&out).unwrap(),
+        "<?xml-stylesheet type=\"text/xsl\" href=\"xslt/abc.xslt\"?>\n<Invoice/>"
+    );
+}
+
+#[test]
+fn pi_keeps_utf8_bom_first() {
+    let xml = Bytes::from_static(b"\xEF\xBB\xBF<?xml version=\"1.0\"?><Invoice/>");
+    let out = add_xml_stylesheet_pi(&xml, "x.xslt");
+    assert!(out.starts_with(b"\xEF\xBB\xBF<?xml version=\"1.0\"?>\n<?xml-stylesheet"));
+}
+
+#[test]
+fn xslt_filename_is_path_safe() {
+    let name = xslt_filename_in_zip("M1a+b/c==Sxyz=");
+    let stem = name
+        .strip_prefix("xslt/")
+        .and_then(|n| n.strip_suffix(".xslt"))
+        .unwrap();
+    assert_eq!(stem.len(), 16);
+    assert!(stem.chars().all(|c| c.is_ascii_hexdigit()), "{name}");
+}
+
+#[test]
+fn distinct_keys_get_distinct_entries() {
+    assert_ne!(xslt_filename_in_zip("a+b"), xslt_filename_in_zip("a/b"));
+    assert_ne!(xslt_filename_in_zip("a_b"), xslt_filename_in_zip("a+b"));
+    assert_eq!(xslt_filename_in_zip("a+b"), xslt_filename_in_zip("a+b"));
+}
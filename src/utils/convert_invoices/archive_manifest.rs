@@ -0,0 +1,118 @@
+use crate::utils::common::target_types_and_formats::{TargetCompressionType, TargetType};
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    ConvertedDocument, InvoiceItemForConversion, InvoiceItemStatus,
+};
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
+use serde::{Deserialize, Serialize};
+
+/// Name of the manifest entry written into every archive
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestItemStatus {
+    Converted,
+    Failed,
+    /// The request stopped before reaching this item
+    NotProcessed,
+}
+
+/// One requested invoice, in request order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub object_id: String,
+    pub sira_no: Option<u64>,
+    pub invoice_no: Option<String>,
+    pub status: ManifestItemStatus,
+    /// Entry name inside the archive (converted items only)
+    pub filename: Option<String>,
+    pub size: Option<u64>,
+    pub xslt_key: Option<String>,
+    pub xslt_engine: Option<XsltEngineKind>,
+    /// 0 when converted, otherwise `InvConvError::error_code`
+    pub error_code: Option<i32>,
+    pub error_msg: Option<String>,
+}
+
+/// `manifest.json`: what is in the archive and what is missing, for users
+/// who only keep the downloaded file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub request_id: String,
+    pub created_at: String,
+    pub target_type: TargetType,
+    pub compression_type: TargetCompressionType,
+    pub request_fully_completed: bool,
+    pub requested_count: usize,
+    pub converted_count: usize,
+    pub failed_count: usize,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Match requested items with the worker's statuses and documents.
+///
+/// Both `item_statuses` and `documents` are in request order; statuses cover a
+/// prefix of `requested_items`, documents exist only for converted statuses.
+pub fn build_manifest(
+    request_id: &str,
+    target_type: TargetType,
+    compression_type: TargetCompressionType,
+    request_fully_completed: bool,
+    requested_items: &[InvoiceItemForConversion],
+    item_statuses: &[InvoiceItemStatus],
+    documents: &[ConvertedDocument],
+) -> ArchiveManifest {
+    let mut documents = documents.iter();
+    let mut statuses = item_statuses.iter();
+    let mut converted_count = 0;
+    let mut failed_count = 0;
+
+    let entries: Vec<ManifestEntry> = requested_items
+        .iter()
+        .map(|item| {
+            let mut entry = ManifestEntry {
+                object_id: item.object_id.clone(),
+                sira_no: item.sira_no,
+                invoice_no: item.invoice_no.clone(),
+                status: ManifestItemStatus::NotProcessed,
+                filename: None,
+                size: None,
+                xslt_key: None,
+                xslt_engine: None,
+                error_code: None,
+                error_msg: None,
+            };
+            let Some(status) = statuses.next() else {
+                return entry;
+            };
+            entry.error_code = Some(status.error_code);
+            if status.error_code == 0 {
+                converted_count += 1;
+                entry.status = ManifestItemStatus::Converted;
+                if let Some(doc) = documents.next() {
+                    entry.filename = Some(doc.filename.clone());
+                    entry.size = Some(doc.size);
+                    entry.xslt_key = doc.xslt_key.clone();
+                    entry.xslt_engine = doc.xslt_engine;
+                }
+            } else {
+                failed_count += 1;
+                entry.status = ManifestItemStatus::Failed;
+                entry.error_msg = Some(status.error_msg.clone());
+            }
+            entry
+        })
+        .collect();
+
+    ArchiveManifest {
+        request_id: request_id.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        target_type,
+        compression_type,
+        request_fully_completed,
+        requested_count: requested_items.len(),
+        converted_count,
+        failed_count,
+        entries,
+    }
+}
@@ -0,0 +1,77 @@
+use super::archive_manifest::{ManifestItemStatus, build_manifest};
+use super::invoice_conversion_manager::{
+    ConvertedDocument, InvoiceItemForConversion, InvoiceItemStatus,
+};
+use crate::utils::common::target_types_and_formats::{TargetCompressionType, TargetType};
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
+
+fn item(object_id: &str, sira_no: u64) -> InvoiceItemForConversion {
+    InvoiceItemForConversion {
+        object_id: object_id.to_string(),
+        sira_no: Some(sira_no),
+        invoice_no: Some(format!("INV{sira_no}")),
+    }
+}
+
+#[test]
+fn lists_converted_failed_and_unprocessed_items() {
+    let items = vec![item("o1", 1), item("o2", 2), item("o3", 3)];
+    let statuses = vec![
+        InvoiceItemStatus::converted(&items[0]),
+        InvoiceItemStatus {
+            object_id: "o2".to_string(),
+            sira_no: Some(2),
+            error_code: 2007,
+            error_msg: "EmbeddedDocumentBinaryObject not found".to_string(),
+        },
+    ];
+    let documents = vec![ConvertedDocument {
+        object_id: "o1".to_string(),
+        sira_no: Some(1),
+        filename: "Fat_1.html".to_string(),
+        size: 42,
+        xslt_key: Some("k1".to_string()),
+        xslt_engine: Some(XsltEngineKind::Xrust),
+    }];
+
+    let manifest = build_manifest(
+        "req-1",
+        TargetType::Html,
+        TargetCompressionType::Zip,
+        false,
+        &items,
+        &statuses,
+        &documents,
+    );
+
+    assert_eq!(manifest.requested_count, 3);
+    assert_eq!((manifest.converted_count, manifest.failed_count), (1, 1));
+
+    let e = &manifest.entries;
+    assert_eq!(e[0].status, ManifestItemStatus::Converted);
+    assert_eq!(e[0].filename.as_deref(), Some("Fat_1.html"));
+    assert_eq!(e[0].size, Some(42));
+    assert_eq!(e[0].xslt_key.as_deref(), Some("k1"));
+    assert_eq!(e[1].status, ManifestItemStatus::Failed);
+    assert_eq!(e[1].error_code, Some(2007));
+    assert_eq!(e[1].invoice_no.as_deref(), Some("INV2"));
+    assert_eq!(e[2].status, ManifestItemStatus::NotProcessed);
+    assert!(e[2].error_code.is_none());
+}
+
+#[test]
+fn serializes_status_in_snake_case() {
+    let items = vec![item("o1", 1)];
+    let manifest = build_manifest(
+        "req-1",
+        TargetType::Pdf,
+        TargetCompressionType::Gzip,
+        false,
+        &items,
+        &[],
+        &[],
+    );
+    let json = serde_json::to_string(&manifest).unwrap();
+    assert!(json.contains(r#""status":"not_processed""#), "{json}");
+    assert!(json.contains(r#""target_type":"Pdf""#), "{json}");
+}
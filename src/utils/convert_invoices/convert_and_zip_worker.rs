@@ -1,31 +1,130 @@
 use crate::utils::appstate::appstate::SharedState;
+use crate::utils::common::archive_file::ArchiveFile;
 use crate::utils::common::target_types_and_formats::{
     FilenameInZipMode, TargetCompressionType, TargetType,
 };
-use crate::utils::common::zip_utils::ZipFile;
+use crate::utils::convert_invoices::add_xml_stylesheet_pi::{
+    add_xml_stylesheet_pi, xslt_filename_in_zip,
+};
+use crate::utils::convert_invoices::archive_manifest::{MANIFEST_FILENAME, build_manifest};
 use crate::utils::convert_invoices::invoice_conversion_manager::{
-    InvoiceConversionJob, InvoiceConversionResult, InvoiceItemForConversion,
+    ConvertedDocument, InvoiceConversionJob, InvoiceConversionResult, InvoiceItemForConversion,
+    InvoiceItemStatus, WorkerMessage,
+};
+use crate::utils::convert_invoices::transform_pool::{
+    TransformPool, TransformResult, TransformTask, transform_document,
 };
 use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
-use crate::utils::xslt_engine::libxslt_engine::LibXsltEngine;
-use crate::utils::xslt_engine::xrust_engine::XrustEngine;
-use crate::utils::xslt_engine::xslt_engine::XsltEngine;
-use std::collections::HashMap;
+use crate::utils::errors::log_error::log_error;
+use crate::utils::xslt_engine::engine_chain::XsltEngineChain;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
 use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use tokio::sync::mpsc;
+use tokio_util::bytes::Bytes;
 use tokio_util::sync::CancellationToken;
 
+/// Per request settings of the blocking worker
+#[derive(Debug, Clone)]
+pub struct ConvertAndZipOptions {
+    pub target_type: TargetType,
+    pub target_compression_type: TargetCompressionType,
+    pub filename_in_zip_mode: FilenameInZipMode,
+    /// Tried in order for every document, see `XsltEngineChain`
+    pub xslt_engines: Vec<XsltEngineKind>,
+    /// Record failing documents in `item_statuses` and go on with the next one
+    pub continue_on_error: bool,
+    /// Every item of the request, listed in the archive manifest
+    pub requested_items: Vec<InvoiceItemForConversion>,
+    /// Send the archive out in chunks while converting instead of returning it in `data`
+    pub stream_to: Option<mpsc::Sender<Bytes>>,
+    /// Receives every item status as soon as it is known (gRPC progress messages)
+    pub progress_to: Option<mpsc::UnboundedSender<InvoiceItemStatus>>,
+    /// Parallel Html / Pdf transforms, 1 transforms on the worker thread itself
+    pub transform_workers: usize,
+}
+
+/// What the worker has written so far, becomes the result on close.
+#[derive(Debug, Default)]
+struct WorkerProgress {
+    docs_count: u32,
+    size: u64,
+    /// Paging cursor: the last written or skipped item, None while no row was processed
+    last_processed_sira_no: Option<u64>,
+    documents: Vec<ConvertedDocument>,
+    item_statuses: Vec<InvoiceItemStatus>,
+    progress_to: Option<mpsc::UnboundedSender<InvoiceItemStatus>>,
+}
+
+impl WorkerProgress {
+    fn push_status(&mut self, status: InvoiceItemStatus) {
+        if let Some(tx) = &self.progress_to {
+            let _ = tx.send(status.clone()); // listener gone is not an error here
+        }
+        self.item_statuses.push(status);
+    }
+
+    /// An item left out under `continue_on_error` is processed, the next page starts after it
+    fn push_skipped(&mut self, status: InvoiceItemStatus) {
+        if status.sira_no.is_some() {
+            self.last_processed_sira_no = status.sira_no;
+        }
+        self.push_status(status);
+    }
+}
+
+/// A request item on its way into the archive; slots are written strictly in request order.
+enum Slot {
+    /// Skipped by the producer, only its status is recorded
+    Failed(InvoiceItemStatus),
+    /// Stopped the request, recorded without moving the cursor
+    Stopped(InvoiceItemStatus),
+    Document {
+        job: Box<InvoiceConversionJob>,
+        /// Entry name the document was transformed under, the PDF title; None without a transform
+        title: Option<String>,
+        transform: Transform,
+    },
+}
+
+enum Transform {
+    /// Ubl and UblXsltSeparate go into the archive without one
+    None,
+    Done(TransformResult),
+    /// Running on the `TransformPool` as task `seq`
+    Running(u64),
+}
+
+/// What the worker does after writing a slot
+enum Written {
+    Next,
+    /// Archive write failed, close it with what is in (partial body)
+    Stop,
+}
+
 /// ---- blocking worker ----
+///
+/// With `transform_workers` > 1 the Html / Pdf transforms run on a `TransformPool`
+/// while this thread keeps receiving jobs and writes the finished documents in order.
 pub fn convert_and_zip(
     request_id: &String,
-    mut rx: mpsc::Receiver<InvoiceConversionJob>,
-    _state: SharedState, // No use, may be required in the future
+    mut rx: mpsc::Receiver<WorkerMessage>,
+    state: SharedState,
     worker_cancellation_token: CancellationToken,
-    _target_type: TargetType,
-    _target_compression_type: TargetCompressionType,
-    filename_in_zip_mode: FilenameInZipMode,
+    options: ConvertAndZipOptions,
 ) -> Result<InvoiceConversionResult, InvConvError> {
-    let mut zip = match ZipFile::new() {
+    let target_type = options.target_type;
+    let target_compression_type = options.target_compression_type;
+    let filename_in_zip_mode = options.filename_in_zip_mode;
+
+    let archive = match &options.stream_to {
+        Some(tx) => Ok(ArchiveFile::new_streaming(
+            target_compression_type,
+            tx.clone(),
+        )),
+        None => ArchiveFile::new(target_compression_type),
+    };
+    let mut archive = match archive {
         Ok(z) => z,
         Err(e) => {
             let my_err = InvConvError::ZipFileCreationError {
@@ -38,113 +137,433 @@ pub fn convert_and_zip(
         }
     };
 
-    let mut docs_count = 0u8;
-    let mut last_processed_sira_no = 0u64;
-    let mut total_html_bytes = 0u64;
+    let mut progress = WorkerProgress {
+        progress_to: options.progress_to.clone(),
+        ..Default::default()
+    };
 
-    // Either use one of them
-    let engine = XrustEngine::new();
-    type Compiled = <XrustEngine as XsltEngine>::Compiled;
+    let transforms = matches!(target_type, TargetType::Html | TargetType::Pdf);
+    let pool = match options.transform_workers {
+        workers if workers > 1 && transforms => Some(
+            TransformPool::new(
+                workers,
+                request_id,
+                &options.xslt_engines,
+                state.xslt_cache.clone(),
+            )
+            .map_err(|e| InvConvError::TaskJoinError(format!("transform pool: {e}")))?,
+        ),
+        _ => None,
+    };
+    // Bounds the documents held in memory while an earlier one is still transforming
+    let max_in_flight = options.transform_workers.max(1) * 2;
 
-    let _engine2 = XrustEngine::new();
-    type _Compiled2 = <LibXsltEngine as XsltEngine>::Compiled;
+    let mut engine_chain =
+        XsltEngineChain::new(&options.xslt_engines, Some(state.xslt_cache.clone()));
+    // xslt_key -> stylesheet bytes, kept so a fallback engine can compile it later
+    let mut xslt_sources: HashMap<String, Bytes> = HashMap::with_capacity(4);
+    // UblXsltSeparate: xslt_key -> stylesheet entry already written into the archive
+    let mut written_xslts: HashMap<String, String> = HashMap::with_capacity(4);
 
-    let mut xslt_cache: HashMap<String, Compiled> = HashMap::with_capacity(4);
+    let mut slots: VecDeque<Slot> = VecDeque::with_capacity(max_in_flight);
+    let mut finished: HashMap<u64, TransformResult> = HashMap::new();
+    let mut running = 0usize;
+    let mut next_seq = 0u64;
+    let mut receiving = true;
+    // A Stop message came, the rest of the request is not processed
+    let mut stopped = false;
 
-    // Process incoming jobs
-    while let Some(invoice_conversion_job) = rx.blocking_recv() {
+    loop {
+        // Write every slot at the front that is complete
+        while let Some(slot) = slots.pop_front() {
+            let slot = match slot {
+                Slot::Document {
+                    job,
+                    title,
+                    transform: Transform::Running(seq),
+                } => match finished.remove(&seq) {
+                    Some(result) => Slot::Document {
+                        job,
+                        title,
+                        transform: Transform::Done(result),
+                    },
+                    None => {
+                        slots.push_front(Slot::Document {
+                            job,
+                            title,
+                            transform: Transform::Running(seq),
+                        });
+                        break;
+                    }
+                },
+                slot => slot,
+            };
+            match write_slot(
+                slot,
+                &mut archive,
+                &mut progress,
+                &mut written_xslts,
+                &mut engine_chain,
+                &xslt_sources,
+                &options,
+            )? {
+                Written::Next => {}
+                Written::Stop => {
+                    return close_archive(archive, request_id, progress, false, &options);
+                }
+            }
+        }
+
+        if !receiving && slots.is_empty() {
+            break;
+        }
+        // Wait for a transform when the window is full or nothing else can come
+        if let Some(pool) = &pool
+            && running > 0
+            && (!receiving || slots.len() >= max_in_flight)
+        {
+            let (seq, result) = pool.recv()?;
+            finished.insert(seq, result);
+            running -= 1;
+            continue;
+        }
+
+        let Some(message) = rx.blocking_recv() else {
+            receiving = false;
+            continue;
+        };
         if worker_cancellation_token.is_cancelled() {
             return Err(InvConvError::ClientDisconnectedError(
                 "Client disconnected, task canceled".to_string(),
             ))
             .ctx("convert_and_zip:process cancelled");
         }
-        let compiled_ref = match xslt_cache.entry(invoice_conversion_job.xslt_key.clone()) {
-            Entry::Occupied(o) => o.into_mut(),
-            Entry::Vacant(v) => {
-                // If not in cache, we MUST have data.
-                let bytes = match invoice_conversion_job.xslt_data.as_ref() {
-                    Some(b) => b,
-                    None => {
-                        let err =
-                            InvConvError::XsltDataMissing(invoice_conversion_job.xslt_key.clone());
-                        // Explicitly log before returning as requested
-                        log_error(&err);
-                        return Err(err);
-                    }
-                };
+        let job = match message {
+            WorkerMessage::Convert(job) => Box::new(job),
+            WorkerMessage::Failed(status) => {
+                // Skipped by the producer, only keep the request order of the statuses
+                slots.push_back(Slot::Failed(status));
+                continue;
+            }
+            WorkerMessage::Stop(status) => {
+                slots.push_back(Slot::Stopped(status));
+                stopped = true;
+                receiving = false;
+                continue;
+            }
+        };
+        if !transforms {
+            slots.push_back(Slot::Document {
+                job,
+                title: None,
+                transform: Transform::None,
+            });
+            continue;
+        }
 
-                let compiled = engine.compile(bytes)?;
-                v.insert(compiled)
+        // Stylesheets are resolved in request order, only the first job of a key has them
+        let xslt = match xslt_source(&job, &mut xslt_sources) {
+            Ok(xslt) => xslt,
+            Err(e) => {
+                slots.push_back(Slot::Document {
+                    job,
+                    title: None,
+                    transform: Transform::Done(Err(e)),
+                });
+                continue;
+            }
+        };
+        // Entries are numbered when written, this holds unless a document ahead is skipped
+        let doc_no = progress.docs_count + documents_ahead(&slots);
+        let title = format!(
+            "{}.{}",
+            filename_in_zip(&job.item, &filename_in_zip_mode, doc_no),
+            target_type.file_extension()
+        );
+        let transform = match &pool {
+            Some(pool) => {
+                pool.submit(TransformTask {
+                    seq: next_seq,
+                    target_type,
+                    xslt_key: job.xslt_key.clone(),
+                    xslt,
+                    xml: job.xml_data.clone(),
+                    title: title.clone(),
+                })?;
+                running += 1;
+                next_seq += 1;
+                Transform::Running(next_seq - 1)
             }
+            None => Transform::Done(transform_document(
+                &mut engine_chain,
+                target_type,
+                &job.xslt_key,
+                &xslt,
+                &job.xml_data,
+                &title,
+            )),
         };
+        slots.push_back(Slot::Document {
+            job,
+            title: Some(title),
+            transform,
+        });
+    }
+    close_archive(archive, request_id, progress, !stopped, &options)
+}
 
-        match engine.transform(compiled_ref, &invoice_conversion_job.xml_data) {
-            Ok(html_bytes) => {
-                let filename = filename_in_zip(
-                    &invoice_conversion_job.item,
-                    &filename_in_zip_mode,
-                    docs_count,
-                );
+/// Documents waiting in `slots` that are still expected to be written
+fn documents_ahead(slots: &VecDeque<Slot>) -> u32 {
+    let ahead = slots
+        .iter()
+        .filter(|slot| {
+            matches!(slot, Slot::Document { transform, .. }
+                if !matches!(transform, Transform::Done(Err(_))))
+        })
+        .count();
+    u32::try_from(ahead).unwrap_or(u32::MAX)
+}
 
-                let current_bytes_len = html_bytes.len() as u64;
+/// Stylesheet of a Html / Pdf job, from the job or an earlier job with the same key
+fn xslt_source(
+    invoice_conversion_job: &InvoiceConversionJob,
+    xslt_sources: &mut HashMap<String, Bytes>,
+) -> Result<Bytes, InvConvError> {
+    match xslt_sources.entry(invoice_conversion_job.xslt_key.clone()) {
+        Entry::Occupied(o) => Ok(o.get().clone()),
+        Entry::Vacant(v) => {
+            // If not seen yet, we MUST have data.
+            match invoice_conversion_job.xslt_data.as_ref() {
+                Some(b) => Ok(v.insert(b.clone()).clone()),
+                None => Err(InvConvError::XsltDataMissing(
+                    invoice_conversion_job.xslt_key.clone(),
+                )),
+            }
+        }
+    }
+}
 
-                if let Err(e) = zip.write_to_zip(&filename, html_bytes) {
-                    let wrapped_err = InvConvError::ZipIOError {
-                        sira_no: invoice_conversion_job.item.sira_no.unwrap_or(0).to_string(),
-                        source: e,
-                    };
-                    log_error(&wrapped_err);
-                    if wrapped_err.is_fatal() {
-                        return Err(wrapped_err);
-                    }
+/// Write one slot into the archive and record its status.
+fn write_slot(
+    slot: Slot,
+    archive: &mut ArchiveFile,
+    progress: &mut WorkerProgress,
+    written_xslts: &mut HashMap<String, String>,
+    engine_chain: &mut XsltEngineChain,
+    xslt_sources: &HashMap<String, Bytes>,
+    options: &ConvertAndZipOptions,
+) -> Result<Written, InvConvError> {
+    let target_type = options.target_type;
+    let (invoice_conversion_job, title, transform) = match slot {
+        Slot::Failed(status) => {
+            progress.push_skipped(status);
+            return Ok(Written::Next);
+        }
+        Slot::Stopped(status) => {
+            progress.push_status(status);
+            return Ok(Written::Next);
+        }
+        Slot::Document {
+            job,
+            title,
+            transform,
+        } => (job, title, transform),
+    };
+    // Numbered by the documents written so far, skipped ones leave no gap
+    let filename = format!(
+        "{}.{}",
+        filename_in_zip(
+            &invoice_conversion_job.item,
+            &options.filename_in_zip_mode,
+            progress.docs_count,
+        ),
+        target_type.file_extension()
+    );
 
-                    match zip.close_zip() {
-                        Ok(bytes) => {
-                            return Ok(InvoiceConversionResult {
-                                data: bytes,
-                                docs_count,
-                                size: total_html_bytes,
-                                last_processed_sira_no: Some(last_processed_sira_no),
-                                request_fully_completed: false,
-                            });
+    let mut produced_by: Option<XsltEngineKind> = None;
+    // A document ahead was skipped after this PDF was rendered, render it again under its entry name
+    let transform = match transform {
+        Transform::Done(Ok(_))
+            if target_type == TargetType::Pdf && title.as_deref() != Some(filename.as_str()) =>
+        {
+            let xslt = xslt_sources
+                .get(&invoice_conversion_job.xslt_key)
+                .cloned()
+                .unwrap_or_default();
+            Transform::Done(transform_document(
+                engine_chain,
+                target_type,
+                &invoice_conversion_job.xslt_key,
+                &xslt,
+                &invoice_conversion_job.xml_data,
+                &filename,
+            ))
+        }
+        transform => transform,
+    };
+    let output_bytes = match (target_type, transform) {
+        // Passthrough, the UBL itself is the document
+        (TargetType::Ubl, _) => invoice_conversion_job.xml_data.clone(),
+        // UBL plus its stylesheet, each stylesheet written once per archive
+        (TargetType::UblXsltSeparate, _) => {
+            let xslt_filename = match written_xslts.entry(invoice_conversion_job.xslt_key.clone()) {
+                Entry::Occupied(o) => o.into_mut(),
+                Entry::Vacant(v) => {
+                    // If not written yet, we MUST have data.
+                    let bytes = match invoice_conversion_job.xslt_data.as_ref() {
+                        Some(b) => b.clone(),
+                        None => {
+                            let err = InvConvError::XsltDataMissing(
+                                invoice_conversion_job.xslt_key.clone(),
+                            );
+                            return skip_or_fail(
+                                err,
+                                &invoice_conversion_job.item,
+                                progress,
+                                options,
+                            );
                         }
-                        Err(zip_err) => {
-                            let final_err = InvConvError::ZipError {
-                                request_id: request_id.to_string(),
-                                sira_no: last_processed_sira_no.to_string(),
-                                source: zip_err,
-                            };
-                            log_error(&final_err);
-                            return Err(final_err);
+                    };
+
+                    let xslt_filename = xslt_filename_in_zip(&invoice_conversion_job.xslt_key);
+                    let xslt_bytes_len = bytes.len() as u64;
+                    if let Err(e) = archive.write_entry(&xslt_filename, bytes) {
+                        let wrapped_err = InvConvError::ZipIOError {
+                            sira_no: invoice_conversion_job.item.sira_no.unwrap_or(0).to_string(),
+                            source: e,
+                        };
+                        log_error(&wrapped_err);
+                        if wrapped_err.is_fatal() {
+                            return Err(wrapped_err);
                         }
+                        return Ok(Written::Stop);
                     }
+                    progress.size += xslt_bytes_len;
+                    v.insert(xslt_filename)
                 }
+            };
 
-                docs_count += 1;
-                total_html_bytes += current_bytes_len;
-                if let Some(sn) = invoice_conversion_job.item.sira_no {
-                    last_processed_sira_no = sn;
-                }
-            }
-            Err(e) => {
-                log_error(&e);
-                return Err(e);
+            add_xml_stylesheet_pi(&invoice_conversion_job.xml_data, xslt_filename)
+        }
+        (_, Transform::Done(Ok((output_bytes, kind)))) => {
+            produced_by = Some(kind);
+            output_bytes
+        }
+        (_, Transform::Done(Err(e))) => {
+            return skip_or_fail(e, &invoice_conversion_job.item, progress, options);
+        }
+        (_, Transform::None | Transform::Running(_)) => {
+            return Err(InvConvError::TaskJoinError(format!(
+                "no transform result for {}",
+                invoice_conversion_job.item.object_id
+            )));
+        }
+    };
+
+    let current_bytes_len = output_bytes.len() as u64;
+
+    if let Err(e) = archive.write_entry(&filename, output_bytes) {
+        let wrapped_err = InvConvError::ZipIOError {
+            sira_no: invoice_conversion_job.item.sira_no.unwrap_or(0).to_string(),
+            source: e,
+        };
+        log_error(&wrapped_err);
+        if wrapped_err.is_fatal() {
+            return Err(wrapped_err);
+        }
+
+        return Ok(Written::Stop);
+    }
+
+    progress.push_status(InvoiceItemStatus::converted(&invoice_conversion_job.item));
+    progress.documents.push(ConvertedDocument {
+        object_id: invoice_conversion_job.item.object_id.clone(),
+        sira_no: invoice_conversion_job.item.sira_no,
+        filename,
+        size: current_bytes_len,
+        xslt_key: (!invoice_conversion_job.xslt_key.is_empty())
+            .then(|| invoice_conversion_job.xslt_key.clone()),
+        xslt_engine: produced_by,
+    });
+    progress.docs_count += 1;
+    progress.size += current_bytes_len;
+    if let Some(sn) = invoice_conversion_job.item.sira_no {
+        progress.last_processed_sira_no = Some(sn);
+    }
+    Ok(Written::Next)
+}
+
+/// Invoice level errors are recorded and skip the item with `continue_on_error`,
+/// otherwise they close the archive early; anything else fails the request
+fn skip_or_fail(
+    e: InvConvError,
+    item: &InvoiceItemForConversion,
+    progress: &mut WorkerProgress,
+    options: &ConvertAndZipOptions,
+) -> Result<Written, InvConvError> {
+    log_error(&e);
+    if !e.is_item_error() {
+        return Err(e);
+    }
+    let status = InvoiceItemStatus::failed(item, &e);
+    match options.continue_on_error {
+        true => {
+            progress.push_skipped(status);
+            Ok(Written::Next)
+        }
+        false => {
+            progress.push_status(status);
+            Ok(Written::Stop)
+        }
+    }
+}
+
+/// Write the manifest, finalize the archive and wrap it into the result.
+/// `request_fully_completed` is false when the worker stopped early (partial body).
+fn close_archive(
+    mut archive: ArchiveFile,
+    request_id: &str,
+    progress: WorkerProgress,
+    request_fully_completed: bool,
+    options: &ConvertAndZipOptions,
+) -> Result<InvoiceConversionResult, InvConvError> {
+    let manifest = build_manifest(
+        request_id,
+        options.target_type,
+        options.target_compression_type,
+        request_fully_completed,
+        &options.requested_items,
+        &progress.item_statuses,
+        &progress.documents,
+    );
+    // The documents are already in, a manifest write failure must not lose them
+    match serde_json::to_vec_pretty(&manifest) {
+        Ok(json) => {
+            if let Err(e) = archive.write_entry(MANIFEST_FILENAME, Bytes::from(json)) {
+                log_error(&InvConvError::ZipIOError {
+                    sira_no: progress.last_processed_sira_no.unwrap_or(0).to_string(),
+                    source: e,
+                });
             }
         }
+        Err(e) => log_error(&e),
     }
-    match zip.close_zip() {
+
+    match archive.close() {
         Ok(bytes) => Ok(InvoiceConversionResult {
             data: bytes,
-            docs_count,
-            size: total_html_bytes,
-            last_processed_sira_no: Some(last_processed_sira_no),
-            request_fully_completed: true,
+            docs_count: progress.docs_count,
+            size: progress.size,
+            last_processed_sira_no: progress.last_processed_sira_no,
+            request_fully_completed,
+            compression_type: options.target_compression_type,
+            documents: progress.documents,
+            item_statuses: progress.item_statuses,
         }),
         Err(e) => {
             let my_err = InvConvError::ZipError {
                 request_id: request_id.to_string(),
-                sira_no: last_processed_sira_no.to_string(),
+                sira_no: progress.last_processed_sira_no.unwrap_or(0).to_string(),
                 source: e,
             };
             log_error(&my_err);
@@ -157,7 +576,7 @@ pub fn convert_and_zip(
 fn filename_in_zip(
     item: &InvoiceItemForConversion,
     filename_in_zip_mode: &FilenameInZipMode,
-    docs_count: u8,
+    docs_count: u32,
 ) -> String {
     match filename_in_zip_mode {
         FilenameInZipMode::ExtractFromObjID => {
@@ -179,7 +598,3 @@ fn filename_in_zip(
         FilenameInZipMode::StartFromInvoiceOne => format!("Fat_{}", docs_count),
     }
 }
-
-fn log_error(e: &InvConvError) {
-    //log::error!("Error processing sira_no {}: {:?}", sira_no, e);
-}
@@ -0,0 +1,299 @@
+use super::archive_manifest::{ArchiveManifest, MANIFEST_FILENAME, ManifestItemStatus};
+use super::convert_and_zip_worker::{ConvertAndZipOptions, convert_and_zip};
+use super::invoice_conversion_manager::{
+    InvoiceConversionJob, InvoiceConversionResult, InvoiceItemForConversion, InvoiceItemStatus,
+    WorkerMessage,
+};
+use crate::utils::appstate::appstate::init_app_state;
+use crate::utils::common::target_types_and_formats::{
+    FilenameInZipMode, TargetCompressionType, TargetType,
+};
+use crate::utils::config::app_config::{AppConfig, ObjectStoreBackend, Secret};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::xslt_engine::default_engine_chain;
+use std::io::{Cursor, Read};
+use tokio::sync::mpsc;
+use tokio_util::bytes::Bytes;
+use tokio_util::sync::CancellationToken;
+
+fn item(sira_no: u64) -> InvoiceItemForConversion {
+    InvoiceItemForConversion {
+        object_id: format!("inv-{sira_no}.xml.xz"),
+        sira_no: Some(sira_no),
+        invoice_no: None,
+    }
+}
+
+fn job(sira_no: u64, xslt_key: &str, xslt_data: Option<&'static str>) -> WorkerMessage {
+    WorkerMessage::Convert(InvoiceConversionJob {
+        item: item(sira_no),
+        xml_data: Bytes::from_static(b"<Invoice/>"),
+        xslt_key: xslt_key.to_string(),
+        xslt_data: xslt_data.map(|x| Bytes::from_static(x.as_bytes())),
+    })
+}
+
+/// Runs the worker on `messages` like the manager does, on the blocking pool
+async fn run_worker(
+    target_type: TargetType,
+    continue_on_error: bool,
+    messages: Vec<WorkerMessage>,
+) -> Result<InvoiceConversionResult, InvConvError> {
+    run_worker_with(
+        target_type,
+        continue_on_error,
+        FilenameInZipMode::UseSiraNo,
+        1,
+        messages,
+    )
+    .await
+}
+
+async fn run_worker_with(
+    target_type: TargetType,
+    continue_on_error: bool,
+    filename_in_zip_mode: FilenameInZipMode,
+    transform_workers: usize,
+    messages: Vec<WorkerMessage>,
+) -> Result<InvoiceConversionResult, InvConvError> {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = AppConfig::default();
+    config.database.host = "unused".to_string();
+    config.database.database = "uut_25_1".to_string();
+    config.database.user = "unused".to_string();
+    config.database.password = Secret::new("unused");
+    config.object_store.backend = ObjectStoreBackend::Fs;
+    config.object_store.dir = tmp.path().to_string_lossy().to_string();
+    config.xslt_disk_cache.dir = tmp.path().join("xslt").to_string_lossy().to_string();
+    config.jobs.dir = tmp.path().join("jobs").to_string_lossy().to_string();
+    let state = init_app_state(&config).await.unwrap();
+
+    let requested_items = messages
+        .iter()
+        .map(|message| match message {
+            WorkerMessage::Convert(job) => job.item.clone(),
+            WorkerMessage::Failed(status) | WorkerMessage::Stop(status) => {
+                item(status.sira_no.unwrap())
+            }
+        })
+        .collect();
+    let (tx, rx) = mpsc::channel(messages.len().max(1));
+    for message in messages {
+        tx.send(message).await.unwrap();
+    }
+    drop(tx);
+    let options = ConvertAndZipOptions {
+        target_type,
+        target_compression_type: TargetCompressionType::Zip,
+        filename_in_zip_mode,
+        xslt_engines: default_engine_chain(),
+        continue_on_error,
+        requested_items,
+        stream_to: None,
+        progress_to: None,
+        transform_workers,
+    };
+    tokio::task::spawn_blocking(move || {
+        convert_and_zip(
+            &"worker-test".to_string(),
+            rx,
+            state,
+            CancellationToken::new(),
+            options,
+        )
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn ubl_xslt_separate_skips_a_missing_stylesheet_with_continue_on_error() {
+    let messages = vec![
+        job(1, "missing", None),
+        job(2, "present", Some("<xsl:stylesheet/>")),
+    ];
+    let result = run_worker(TargetType::UblXsltSeparate, true, messages)
+        .await
+        .unwrap();
+
+    assert_eq!(result.docs_count, 1);
+    let codes: Vec<i32> = result.item_statuses.iter().map(|s| s.error_code).collect();
+    assert_eq!(codes, [2013, 0]);
+}
+
+#[tokio::test]
+async fn ubl_xslt_separate_stops_on_a_missing_stylesheet_by_default() {
+    let messages = vec![
+        job(1, "missing", None),
+        job(2, "present", Some("<xsl:stylesheet/>")),
+    ];
+    let result = run_worker(TargetType::UblXsltSeparate, false, messages)
+        .await
+        .unwrap();
+
+    assert!(!result.request_fully_completed);
+    assert_eq!(result.docs_count, 0);
+    let codes: Vec<i32> = result.item_statuses.iter().map(|s| s.error_code).collect();
+    assert_eq!(codes, [2013]);
+}
+
+fn manifest(result: InvoiceConversionResult) -> ArchiveManifest {
+    let mut zip = zip::ZipArchive::new(Cursor::new(result.data)).unwrap();
+    let mut json = String::new();
+    zip.by_name(MANIFEST_FILENAME)
+        .unwrap()
+        .read_to_string(&mut json)
+        .unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[tokio::test]
+async fn manifest_lists_the_item_that_stopped_the_request() {
+    let messages = vec![
+        job(1, "good", Some(HTML_XSLT)),
+        job(2, "broken", Some("not a stylesheet")),
+        job(3, "good", None),
+    ];
+    let result = run_worker(TargetType::Html, false, messages).await.unwrap();
+    let manifest = manifest(result);
+
+    assert!(!manifest.request_fully_completed);
+    let statuses: Vec<ManifestItemStatus> = manifest.entries.iter().map(|e| e.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            ManifestItemStatus::Converted,
+            ManifestItemStatus::Failed,
+            ManifestItemStatus::NotProcessed
+        ]
+    );
+    assert_ne!(manifest.entries[1].error_code, Some(0));
+    assert!(manifest.entries[1].error_msg.is_some());
+}
+
+#[tokio::test]
+async fn manifest_lists_the_item_the_producer_stopped_on() {
+    let stopped = InvoiceItemStatus::failed(
+        &item(2),
+        &InvConvError::XsltDataMissing("missing".to_string()),
+    );
+    let messages = vec![job(1, "", None), WorkerMessage::Stop(stopped)];
+    let result = run_worker(TargetType::Ubl, false, messages).await.unwrap();
+    assert!(!result.request_fully_completed);
+    let manifest = manifest(result);
+
+    let statuses: Vec<ManifestItemStatus> = manifest.entries.iter().map(|e| e.status).collect();
+    assert_eq!(
+        statuses,
+        [ManifestItemStatus::Converted, ManifestItemStatus::Failed]
+    );
+    assert_eq!(manifest.entries[1].error_code, Some(2013));
+}
+
+#[tokio::test]
+async fn ubl_xslt_separate_keeps_keys_apart_that_clean_up_alike() {
+    let messages = vec![
+        job(1, "a+b", Some("<xsl:stylesheet id=\"plus\"/>")),
+        job(2, "a/b", Some("<xsl:stylesheet id=\"slash\"/>")),
+    ];
+    let result = run_worker(TargetType::UblXsltSeparate, false, messages)
+        .await
+        .unwrap();
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(result.data)).unwrap();
+    let mut stylesheets = vec![];
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).unwrap();
+        if entry.name().starts_with("xslt/") {
+            let mut xslt = String::new();
+            entry.read_to_string(&mut xslt).unwrap();
+            stylesheets.push(xslt);
+        }
+    }
+    stylesheets.sort();
+    assert_eq!(
+        stylesheets,
+        [
+            "<xsl:stylesheet id=\"plus\"/>",
+            "<xsl:stylesheet id=\"slash\"/>"
+        ]
+    );
+}
+
+const HTML_XSLT: &str = r#"<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+<xsl:template match="/"><html><body><p>invoice</p></body></html></xsl:template>
+</xsl:stylesheet>"#;
+
+#[tokio::test]
+async fn start_from_invoice_one_numbers_the_written_documents() {
+    let messages = vec![
+        job(1, "good", Some(HTML_XSLT)),
+        job(2, "broken", Some("not a stylesheet")),
+        job(3, "good", None),
+    ];
+    let result = run_worker_with(
+        TargetType::Pdf,
+        true,
+        FilenameInZipMode::StartFromInvoiceOne,
+        2,
+        messages,
+    )
+    .await
+    .unwrap();
+
+    let codes: Vec<i32> = result.item_statuses.iter().map(|s| s.error_code).collect();
+    assert_eq!(codes[0], 0);
+    assert_ne!(codes[1], 0);
+    assert_eq!(codes[2], 0);
+    let mut zip = zip::ZipArchive::new(Cursor::new(result.data)).unwrap();
+    let mut names: Vec<String> = zip.file_names().map(str::to_string).collect();
+    names.sort();
+    assert_eq!(names, ["Fat_0.pdf", "Fat_1.pdf", "manifest.json"]);
+
+    // Rendered ahead as the third document, its title follows the entry name
+    let mut pdf = vec![];
+    zip.by_name("Fat_1.pdf")
+        .unwrap()
+        .read_to_end(&mut pdf)
+        .unwrap();
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("Fat_1.pdf"));
+    assert!(!pdf.contains("Fat_2.pdf"));
+}
+
+#[tokio::test]
+async fn empty_request_has_no_cursor() {
+    let result = run_worker(TargetType::Html, false, vec![]).await.unwrap();
+
+    assert_eq!(result.docs_count, 0);
+    assert_eq!(result.last_processed_sira_no, None);
+    assert!(result.request_fully_completed);
+}
+
+#[tokio::test]
+async fn skipped_items_move_the_cursor() {
+    let messages = vec![
+        job(1, "", None),
+        WorkerMessage::Failed(InvoiceItemStatus::failed(
+            &item(2),
+            &InvConvError::XsltDataMissing("missing".to_string()),
+        )),
+    ];
+    let result = run_worker(TargetType::Ubl, true, messages).await.unwrap();
+
+    assert!(result.request_fully_completed);
+    assert_eq!(result.last_processed_sira_no, Some(2));
+}
+
+#[tokio::test]
+async fn stopping_on_the_first_item_has_no_cursor() {
+    let messages = vec![
+        job(1, "broken", Some("not a stylesheet")),
+        job(2, "broken", None),
+    ];
+    let result = run_worker(TargetType::Html, false, messages).await.unwrap();
+
+    // Not the end of the data, the same page is asked again
+    assert!(!result.request_fully_completed);
+    assert_eq!(result.last_processed_sira_no, None);
+}
@@ -1,13 +1,15 @@
+use crate::utils::appstate::appstate::SharedState;
 use crate::utils::common::comp_decompress::xz_decompress;
 use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
+use crate::utils::errors::log_error::log_error;
 use crate::utils::errors::object_store_errors::ObjectStoreError;
 use crate::utils::object_store::object_store::Store;
 use tokio_util::bytes;
 
 pub async fn get_xslt_from_objstore(
     object_store: &Store,
-    year: &String,
-    xslt_key: &String, // object_id of the xslt
+    year: &str,
+    xslt_key: &str, // object_id of the xslt
 ) -> Result<bytes::Bytes, InvConvError> {
     // Try compressed first
     let xslt_key_xz = format!("{xslt_key}.xz");
@@ -37,10 +39,45 @@ pub async fn get_xslt_from_objstore(
     {
         Ok(decompressed) => decompressed,
         Err(err) => {
-            let _err: InvConvError = err.into();
-            return Err(_err).ctx("get_xslt_from_objstore : xz_decompress failed");
+            return Err(err).ctx("get_xslt_from_objstore : xz_decompress failed");
         }
     };
 
     Ok(decompressed)
 }
+
+/// Stylesheet through the cache layers: memory LRU -> disk -> object store.
+/// Whatever is found is copied into the faster layers.
+pub async fn get_xslt_cached(
+    state: &SharedState,
+    year: &str,
+    xslt_key: &str,
+) -> Result<bytes::Bytes, InvConvError> {
+    if let Some(xslt_data) = state.xslt_cache.get_bytes(xslt_key) {
+        return Ok(xslt_data);
+    }
+
+    if let Some(disk_cache) = state.xslt_disk_cache.clone() {
+        let key = xslt_key.to_string();
+        if let Ok(Some(xslt_data)) = tokio::task::spawn_blocking(move || disk_cache.get(&key)).await
+        {
+            state.xslt_cache.insert_bytes(xslt_key, xslt_data.clone());
+            return Ok(xslt_data);
+        }
+    }
+
+    let xslt_data = get_xslt_from_objstore(&state.object_store, year, xslt_key).await?;
+    state.xslt_cache.insert_bytes(xslt_key, xslt_data.clone());
+
+    if let Some(disk_cache) = state.xslt_disk_cache.clone() {
+        // Best effort, the request does not wait for the write
+        let (key, data) = (xslt_key.to_string(), xslt_data.clone());
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = disk_cache.put(&key, &data) {
+                log_error(&e);
+            }
+        });
+    }
+
+    Ok(xslt_data)
+}
@@ -0,0 +1,106 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceItemForConversion, InvoicesForConversion,
+};
+use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
+use crate::utils::incoming_invoice::get_incoming_invoice_recs_afterthis::get_incoming_invoice_recs_afterthis;
+use crate::utils::incoming_invoice::incoming_invoice_rec::IncomingInvoiceRec;
+use crate::utils::incoming_invoice::tenant_databases::incoming_invoice_pool_key;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+/// A page is one SELECT and one archive, which the buffered responses keep in memory
+/// whole; bigger batches are more pages or a job.
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// Server side paging: convert the next invoices received by `source_vkntckn`
+/// instead of a client supplied item list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomingInvoicePage {
+    pub source_vkntckn: String,
+    /// Continue after this SIRA_NO, i.e. `last_processed_sira_no` of the previous page
+    #[serde(default)]
+    pub after_this: u64,
+    /// Defaults to 100, at most 1000
+    #[serde(default)]
+    pub page_size: Option<u32>,
+    /// Tenant database `uut_YY_N` of `source_vkntckn`, when set it must match the lookup
+    #[serde(default)]
+    pub db_name: Option<String>,
+}
+
+impl IncomingInvoicePage {
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// Replace the items (and year) of `conversion_request` with the next page of INCOMING_INVOICE.
+/// An empty page leaves both empty; converting it writes no document, so the result has
+/// no `last_processed_sira_no` and `request_fully_completed`: the receiver has nothing newer.
+pub async fn resolve_incoming_invoice_page(
+    state: &SharedState,
+    mut conversion_request: InvoicesForConversion,
+    page: IncomingInvoicePage,
+) -> Result<InvoicesForConversion, InvConvError> {
+    let db_name = state
+        .tenant_databases
+        .resolve(page.db_name.as_deref(), &page.source_vkntckn)
+        .ok_or_else(|| {
+            InvConvError::InvalidDatabaseName(page.db_name.clone().unwrap_or_default())
+        })?;
+    let pool = state
+        .db_pools
+        .pool(&incoming_invoice_pool_key(&db_name, &page.source_vkntckn))
+        .await
+        .ctx("resolve_incoming_invoice_page")?;
+    let recs = get_incoming_invoice_recs_afterthis(
+        &pool,
+        &db_name,
+        &page.source_vkntckn,
+        page.after_this as i64,
+        i64::from(page.page_size()),
+    )
+    .await
+    .ctx("resolve_incoming_invoice_page")?;
+
+    let (year, items) = items_from_incoming_recs(&recs)?;
+    if let Some(year) = year {
+        conversion_request.year = year;
+    }
+    conversion_request.items = items;
+    Ok(conversion_request)
+}
+
+/// Object id is the record's PATH, the year comes from the path too.
+///
+/// One request reads one yearly object store, so the page stops before the
+/// first record of another year; it comes with the next page. `None` year
+/// means an empty page.
+pub fn items_from_incoming_recs(
+    recs: &[IncomingInvoiceRec],
+) -> Result<(Option<String>, Vec<InvoiceItemForConversion>), InvConvError> {
+    let Some(first) = recs.first() else {
+        return Ok((None, Vec::new()));
+    };
+    let year =
+        first
+            .extract_year_as_string()
+            .ok_or_else(|| InvConvError::IncomingInvoiceYearMissing {
+                sira_no: first.sira_no,
+                path: first.path.clone(),
+            })?;
+
+    let items = recs
+        .iter()
+        .take_while(|rec| rec.extract_year_as_string().as_deref() == Some(year.as_str()))
+        .map(|rec| InvoiceItemForConversion {
+            object_id: rec.path.trim().to_string(),
+            sira_no: Some(rec.sira_no),
+            invoice_no: Some(rec.invoice_id.clone()),
+        })
+        .collect();
+    Ok((Some(year), items))
+}
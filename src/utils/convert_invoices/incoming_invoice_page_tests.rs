@@ -0,0 +1,61 @@
+use super::incoming_invoice_page::{
+    DEFAULT_PAGE_SIZE, IncomingInvoicePage, MAX_PAGE_SIZE, items_from_incoming_recs,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::incoming_invoice::incoming_invoice_rec::IncomingInvoiceRec;
+
+fn rec(sira_no: u64, path: &str) -> IncomingInvoiceRec {
+    IncomingInvoiceRec::new(
+        format!("uuid-{sira_no}"),
+        format!("INV{sira_no}"),
+        "receiver",
+        sira_no,
+        path,
+    )
+}
+
+#[test]
+fn page_stops_at_the_first_record_of_another_year() {
+    let recs = vec![
+        rec(10, "abc-2024-1 "),
+        rec(11, "abc-2024-2"),
+        rec(12, "abc-2025-3"),
+        rec(13, "abc-2024-4"),
+    ];
+    let (year, items) = items_from_incoming_recs(&recs).unwrap();
+    assert_eq!(year.as_deref(), Some("2024"));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].object_id, "abc-2024-1");
+    assert_eq!(items[0].sira_no, Some(10));
+    assert_eq!(items[1].invoice_no.as_deref(), Some("INV11"));
+}
+
+#[test]
+fn missing_year_on_the_first_record_is_an_error() {
+    let err = items_from_incoming_recs(&[rec(7, "no-year-here")]).unwrap_err();
+    assert!(matches!(
+        err,
+        InvConvError::IncomingInvoiceYearMissing { sira_no: 7, .. }
+    ));
+    assert_eq!(err.error_code(), 2018);
+}
+
+#[test]
+fn empty_page_has_no_year() {
+    let (year, items) = items_from_incoming_recs(&[]).unwrap();
+    assert!(year.is_none());
+    assert!(items.is_empty());
+}
+
+#[test]
+fn page_size_is_clamped() {
+    let page = |page_size| IncomingInvoicePage {
+        source_vkntckn: "1234567890".to_string(),
+        after_this: 0,
+        page_size,
+        db_name: None,
+    };
+    assert_eq!(page(None).page_size(), DEFAULT_PAGE_SIZE);
+    assert_eq!(page(Some(0)).page_size(), 1);
+    assert_eq!(page(Some(MAX_PAGE_SIZE + 1)).page_size(), MAX_PAGE_SIZE);
+}
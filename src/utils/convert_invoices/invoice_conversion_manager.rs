@@ -1,17 +1,28 @@
 use crate::utils::appstate::appstate::SharedState;
 use crate::utils::common::comp_decompress::xz_decompress;
-use crate::utils::common::san_desanitize::sanitize_fast;
+use crate::utils::common::san_desanitize::{desanitize, sanitize_fast};
 use crate::utils::common::target_types_and_formats::{
     FilenameInZipMode, TargetCompressionType, TargetType,
 };
-use crate::utils::convert_invoices::convert_and_zip_worker::convert_and_zip;
+use crate::utils::convert_invoices::convert_and_zip_worker::{
+    ConvertAndZipOptions, convert_and_zip,
+};
 use crate::utils::convert_invoices::extract_xslt_key_from_xml::extract_xslt_key_from_xml;
-use crate::utils::convert_invoices::get_xslt_from_objstore::get_xslt_from_objstore;
+use crate::utils::convert_invoices::get_xslt_from_objstore::get_xslt_cached;
+use crate::utils::convert_invoices::incoming_invoice_page::{
+    IncomingInvoicePage, resolve_incoming_invoice_page,
+};
 use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
 use crate::utils::errors::log_error::log_error;
+use crate::utils::errors::object_store_errors::ObjectStoreError;
+use crate::utils::object_store::opendal_mssql_wrapper::ObjectStoreRecord;
+use crate::utils::scheduler::fair_scheduler::ConversionPermit;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
 use tokio_util::bytes;
 use tokio_util::sync::CancellationToken;
 
@@ -32,6 +43,14 @@ pub struct InvoicesForConversion {
     pub filename_in_zip: FilenameInZipMode,
     pub request_id: String,
     pub client_id: Option<String>,
+    /// Preferred engine, tried before the rest of the server chain
+    pub xslt_engine: Option<XsltEngineKind>,
+    /// Skip failing invoices and report them in `item_statuses` instead of stopping
+    pub continue_on_error: bool,
+    /// Ubl, UblXsltSeparate: restore the `-sanitized-…--` markers of the stored XML to their character entities
+    pub desanitize: bool,
+    /// Items (and year) are resolved from INCOMING_INVOICE, `items` is ignored
+    pub incoming_invoice: Option<IncomingInvoicePage>,
 
     /// Items to fetch/process
     pub items: Vec<InvoiceItemForConversion>,
@@ -41,10 +60,59 @@ pub struct InvoicesForConversion {
 #[derive(Debug, Clone, Default)]
 pub struct InvoiceConversionResult {
     pub data: Vec<u8>,
-    pub docs_count: u8,
+    pub docs_count: u32,
     pub size: u64,
+    /// None when no document was written, e.g. an empty page: nothing to continue after
     pub last_processed_sira_no: Option<u64>,
     pub request_fully_completed: bool,
+    /// Archive format of `data` (zip, tar.xz or tar.gz)
+    pub compression_type: TargetCompressionType,
+    /// One entry per document written into the archive, in order
+    pub documents: Vec<ConvertedDocument>,
+    /// One entry per processed item (converted or failed), in request order
+    pub item_statuses: Vec<InvoiceItemStatus>,
+}
+
+/// ----- A document written into the archive -----
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConvertedDocument {
+    pub object_id: String,
+    pub sira_no: Option<u64>,
+    pub filename: String,
+    /// Bytes written into the archive
+    pub size: u64,
+    /// Stylesheet used, `None` for Ubl
+    pub xslt_key: Option<String>,
+    /// Engine that produced the document, `None` when no XSLT ran (Ubl, UblXsltSeparate)
+    pub xslt_engine: Option<XsltEngineKind>,
+}
+
+/// ----- Outcome of one requested invoice -----
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InvoiceItemStatus {
+    pub object_id: String,
+    pub sira_no: Option<u64>,
+    /// 0 when converted, otherwise `InvConvError::error_code`
+    pub error_code: i32,
+    pub error_msg: String,
+}
+impl InvoiceItemStatus {
+    pub fn converted(item: &InvoiceItemForConversion) -> Self {
+        Self {
+            object_id: item.object_id.clone(),
+            sira_no: item.sira_no,
+            error_code: 0,
+            error_msg: "OK".to_string(),
+        }
+    }
+    pub fn failed(item: &InvoiceItemForConversion, error: &InvConvError) -> Self {
+        Self {
+            object_id: item.object_id.clone(),
+            sira_no: item.sira_no,
+            error_code: error.error_code(),
+            error_msg: error.to_string(),
+        }
+    }
 }
 
 /// ----- Error Response -----
@@ -65,7 +133,9 @@ impl From<InvConvError> for InvoiceConversionError {
 /// Work item sent from the async producer to the blocking worker.
 ///
 /// - `xml_data` must be the **sanitized** XML bytes to transform.
-/// - `xslt_key` identifies the stylesheet for caching/reuse on the worker.
+///   For `TargetType::Ubl` it is the original decompressed UBL, written as is.
+/// - `xslt_key` identifies the stylesheet for caching/reuse on the worker
+///   (empty for `TargetType::Ubl`).
 /// - `xslt_data` is **Some** only the first time a given `xslt_key` appears,
 ///   carrying the stylesheet bytes; subsequent jobs with the same key set it to `None`.
 pub struct InvoiceConversionJob {
@@ -75,39 +145,139 @@ pub struct InvoiceConversionJob {
     pub xslt_data: Option<bytes::Bytes>,
 }
 
+/// What the producer sends to the blocking worker, in request order.
+pub enum WorkerMessage {
+    Convert(InvoiceConversionJob),
+    /// Item skipped before conversion (`continue_on_error`), only recorded
+    Failed(InvoiceItemStatus),
+    /// Item that stopped the request, recorded before the worker closes the archive early
+    Stop(InvoiceItemStatus),
+}
+
 pub async fn convert_invoices(
     state: SharedState,
     conversion_request: InvoicesForConversion,
-    _permit: tokio::sync::OwnedSemaphorePermit,
+    permit: ConversionPermit,
+    cancellation_token: CancellationToken,
+) -> Result<InvoiceConversionResult, InvConvError> {
+    run_conversion(
+        state,
+        conversion_request,
+        permit,
+        cancellation_token,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Like `convert_invoices`, but the archive is sent to `stream_to` in chunks
+/// while the invoices are converted; the returned result has empty `data`.
+/// The channel closes when the archive is finished or the conversion stops.
+/// `progress_to` receives each item status as soon as the worker knows it.
+pub async fn convert_invoices_streamed(
+    state: SharedState,
+    conversion_request: InvoicesForConversion,
+    permit: ConversionPermit,
+    cancellation_token: CancellationToken,
+    stream_to: mpsc::Sender<bytes::Bytes>,
+    progress_to: Option<mpsc::UnboundedSender<InvoiceItemStatus>>,
+) -> Result<InvoiceConversionResult, InvConvError> {
+    run_conversion(
+        state,
+        conversion_request,
+        permit,
+        cancellation_token,
+        Some(stream_to),
+        progress_to,
+    )
+    .await
+}
+
+async fn run_conversion(
+    state: SharedState,
+    conversion_request: InvoicesForConversion,
+    _permit: ConversionPermit,
     cancellation_token: CancellationToken,
+    stream_to: Option<mpsc::Sender<bytes::Bytes>>,
+    progress_to: Option<mpsc::UnboundedSender<InvoiceItemStatus>>,
 ) -> Result<InvoiceConversionResult, InvConvError> {
+    let mut conversion_request = conversion_request;
+    if let Some(page) = conversion_request.incoming_invoice.take() {
+        conversion_request = resolve_incoming_invoice_page(&state, conversion_request, page)
+            .await
+            .ctx("convert_invoices")?;
+    }
+
     let mut xslt_cache: HashMap<String, bytes::Bytes> = HashMap::with_capacity(4);
 
     let filename_in_zip_mode: FilenameInZipMode = conversion_request.filename_in_zip;
     let target_type = conversion_request.target_type;
     let target_compression_type = conversion_request.target_compression_type;
     let request_id = conversion_request.request_id.clone();
-
-    let (tx_jobs, rx_jobs) = mpsc::channel::<InvoiceConversionJob>(8);
+    let continue_on_error = conversion_request.continue_on_error;
+    let desanitize_ubl = conversion_request.desanitize;
+
+    // The request's own permit covers one transform thread, the others take a
+    // scheduler permit each while they are free and nobody waits
+    let extra_permits = match target_type {
+        TargetType::Html | TargetType::Pdf => state.scheduler.try_acquire_extra(
+            conversion_request.client_id.as_deref(),
+            state
+                .transform_workers_per_request
+                .min(conversion_request.items.len())
+                .saturating_sub(1),
+        ),
+        _ => vec![],
+    };
+    let worker_options = ConvertAndZipOptions {
+        target_type,
+        target_compression_type,
+        filename_in_zip_mode,
+        xslt_engines: engine_chain_for_request(
+            conversion_request.xslt_engine,
+            &state.xslt_engine_chain,
+        ),
+        continue_on_error,
+        requested_items: conversion_request.items.clone(),
+        stream_to,
+        progress_to,
+        transform_workers: 1 + extra_permits.len(),
+    };
+
+    let (tx_jobs, rx_jobs) = mpsc::channel::<WorkerMessage>(8);
 
     let state_cloned = state.clone();
 
     let worker_token = CancellationToken::new();
     let worker_cancellation_token = worker_token.clone(); // move the original downstream
     let handle = tokio::task::spawn_blocking(move || {
+        let _extra_permits = extra_permits; // released with the worker
         convert_and_zip(
             &request_id,
             rx_jobs,
             state_cloned,
             worker_token,
-            target_type,
-            target_compression_type,
-            filename_in_zip_mode,
+            worker_options,
         )
     });
 
-    let object_store = &state.object_store;
-    for (_, item) in conversion_request.items.iter().enumerate() {
+    // UBLs are fetched in batches ahead of the loop, one result per item in order
+    let mut ubls = state.object_store.get_many(
+        "ubls",
+        conversion_request
+            .items
+            .iter()
+            .map(|item| item.object_id.clone())
+            .collect(),
+        &conversion_request.year,
+    );
+
+    // Up to `prefetch_per_request` invoices are decompressed and sanitized at once,
+    // the worker still gets them in request order
+    let mut prefetch = Prefetch::new(state.prefetch_per_request);
+    let mut items = conversion_request.items.iter();
+    loop {
         if cancellation_token.is_cancelled() {
             // connection dropped, cancel the worker and return
             worker_cancellation_token.cancel();
@@ -118,152 +288,65 @@ pub async fn convert_invoices(
             .ctx("convert_invoices:process cancelled");
         }
 
-        // get compressed ubl
-        let object_store_rec_for_xml = match object_store
-            .get("ubls", &item.object_id, &conversion_request.year)
-            .await
+        while prefetch.has_room()
+            && let Some(item) = items.next()
         {
-            Ok(rec) => rec, // <— bind and continue below
-            Err(err) => {
-                let inv_err: InvConvError = err.into();
-                log_error(&inv_err);
-
-                // stop the pipeline
-                worker_cancellation_token.cancel();
-                drop(tx_jobs);
-
-                // wait worker to finalize/stop
-                let worker_res = handle
-                    .await
-                    .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?;
-
-                if inv_err.is_fatal() {
-                    return Err(inv_err).ctx("convert_invoices"); // no body
-                } else {
-                    return worker_res; // partial body from worker
-                }
-            }
+            let fetched = ubls
+                .next()
+                .await
+                .unwrap_or_else(|| Err(ObjectStoreError::BatchEnded(item.object_id.clone())));
+            prefetch.push(
+                item,
+                tokio::spawn(prepare_ubl(
+                    target_type,
+                    desanitize_ubl,
+                    item.clone(),
+                    fetched,
+                )),
+            );
+        }
+        let Some((item, prepared)) = prefetch.next().await else {
+            break;
         };
 
-        let uncompressed_size = object_store_rec_for_xml.original_size as usize;
-        let decompressed: bytes::Bytes = match xz_decompress(
-            object_store_rec_for_xml.objcontent,
-            uncompressed_size,
-            &item.object_id,
-        )
-        .await
-        {
-            Ok(bytes) => bytes,
-            Err(inv_err) => {
-                // ❌ DecompressError = NON-FATAL → stop pipeline and return partial
-                log_error(&inv_err);
-                worker_cancellation_token.cancel();
-                drop(tx_jobs);
-                let worker_res = handle
-                    .await
-                    .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?;
-                return worker_res; // partial result (request_fully_completed = false)
-            }
+        let job = match prepared {
+            Ok(ubl) => resolve_job(&state, &conversion_request.year, ubl, &mut xslt_cache).await,
+            Err(inv_err) => Err(inv_err),
         };
-
-        let sanitized_xml: bytes::Bytes = match sanitize_fast(decompressed) {
-            Ok(s) => s,
-            Err(e) => {
-                let inv_err = InvConvError::NonUtfCharError {
-                    object_id: item.object_id.clone(),
-                    source: e,
-                };
+        let message = match job {
+            Ok(job) => WorkerMessage::Convert(job),
+            Err(inv_err) => {
                 log_error(&inv_err);
 
-                // stop pipeline and return partial
-                worker_cancellation_token.cancel();
-                drop(tx_jobs);
-
-                let worker_res = handle
-                    .await
-                    .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?;
-
-                if inv_err.is_fatal() {
-                    return Err(inv_err).ctx("convert_invoices"); // no body
-                } else {
-                    return worker_res; // partial body from worker
-                }
-            }
-        };
-
-        //extract xslt key
-        let xslt_key: String =
-            match extract_xslt_key_from_xml(sanitized_xml.clone(), &item.object_id) {
-                Ok(k) => k,
-                Err(e) => {
-                    log_error(&e);
-
+                if continue_on_error && inv_err.is_item_error() {
+                    // skip this invoice, the worker records it in the item statuses
+                    WorkerMessage::Failed(InvoiceItemStatus::failed(item, &inv_err))
+                } else if inv_err.is_fatal() {
                     // stop the pipeline
                     worker_cancellation_token.cancel();
                     drop(tx_jobs);
-
-                    // wait worker to finalize/stop
-                    let worker_res = handle
+                    // wait worker to stop, its own error is superseded by this one
+                    let _ = handle
                         .await
                         .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?;
-
-                    if e.is_fatal() {
-                        return Err(e).ctx("convert_invoices"); // no body
-                    } else {
-                        return worker_res; // partial body from worker
-                    }
-                }
-            };
-
-        let job = match xslt_cache.contains_key(&xslt_key) {
-            true => {
-                // Cache HIT - worker already has this XSLT
-                InvoiceConversionJob {
-                    item: item.clone(),
-                    xml_data: sanitized_xml.clone(),
-                    xslt_key: xslt_key.clone(),
-                    xslt_data: None,
-                }
-            }
-            false => {
-                // Cache MISS
-                let xslt_data =
-                    match get_xslt_from_objstore(object_store, &conversion_request.year, &xslt_key)
+                    return Err(inv_err).ctx("convert_invoices"); // no body
+                } else {
+                    // The worker writes what came before, records this item and closes the archive
+                    let _ = tx_jobs
+                        .send(WorkerMessage::Stop(InvoiceItemStatus::failed(
+                            item, &inv_err,
+                        )))
+                        .await;
+                    drop(tx_jobs);
+                    return handle
                         .await
-                    {
-                        Ok(xslt_data) => xslt_data, // we have the xslt
-                        Err(err) => {
-                            let inv_err: InvConvError = err.into();
-                            log_error(&inv_err);
-
-                            // stop the pipeline
-                            worker_cancellation_token.cancel();
-                            drop(tx_jobs);
-
-                            // wait worker to finalize/stop
-                            let worker_res = handle
-                                .await
-                                .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?;
-
-                            if inv_err.is_fatal() {
-                                return Err(inv_err).ctx("convert_invoices"); // no body
-                            } else {
-                                return worker_res; // partial body from worker
-                            }
-                        }
-                    };
-                xslt_cache.insert(xslt_key.clone(), xslt_data.clone());
-                InvoiceConversionJob {
-                    item: item.clone(),
-                    xml_data: sanitized_xml,
-                    xslt_key: xslt_key.clone(),
-                    xslt_data: Some(xslt_data),
+                        .map_err(|e| InvConvError::TaskJoinError(e.to_string()))?; // partial body
                 }
             }
         };
 
         // SEND TO WORKER
-        if tx_jobs.send(job).await.is_err() {
+        if tx_jobs.send(message).await.is_err() {
             // The receiver (worker) dropped, likely due to a panic or error on their side.
             // We stop the pipeline.
             worker_cancellation_token.cancel();
@@ -299,3 +382,164 @@ pub async fn convert_invoices(
 
     Ok(worker_res)
 }
+
+/// A decompressed invoice waiting for its stylesheet
+struct PreparedUbl {
+    item: InvoiceItemForConversion,
+    xml_data: bytes::Bytes,
+    /// Empty for `TargetType::Ubl`
+    xslt_key: String,
+}
+
+/// Invoices being prepared, oldest first. Dropping it aborts the tasks still running.
+struct Prefetch<'a> {
+    depth: usize,
+    in_flight: VecDeque<(
+        &'a InvoiceItemForConversion,
+        JoinHandle<Result<PreparedUbl, InvConvError>>,
+    )>,
+}
+impl<'a> Prefetch<'a> {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            in_flight: VecDeque::with_capacity(depth),
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.in_flight.len() < self.depth
+    }
+
+    fn push(
+        &mut self,
+        item: &'a InvoiceItemForConversion,
+        task: JoinHandle<Result<PreparedUbl, InvConvError>>,
+    ) {
+        self.in_flight.push_back((item, task));
+    }
+
+    /// The oldest item and its outcome, `None` when nothing is in flight
+    async fn next(
+        &mut self,
+    ) -> Option<(
+        &'a InvoiceItemForConversion,
+        Result<PreparedUbl, InvConvError>,
+    )> {
+        let (item, task) = self.in_flight.front_mut()?;
+        let item = *item;
+        let prepared = match task.await {
+            Ok(prepared) => prepared,
+            Err(e) => Err(InvConvError::TaskJoinError(e.to_string())),
+        };
+        self.in_flight.pop_front();
+        Some((item, prepared))
+    }
+}
+impl Drop for Prefetch<'_> {
+    fn drop(&mut self) {
+        for (_, task) in &self.in_flight {
+            task.abort();
+        }
+    }
+}
+
+/// Decompress one fetched invoice and (unless Ubl) extract its stylesheet key from the sanitized XML.
+/// Runs as its own task, several items at a time.
+async fn prepare_ubl(
+    target_type: TargetType,
+    desanitize_ubl: bool,
+    item: InvoiceItemForConversion,
+    fetched: Result<ObjectStoreRecord, ObjectStoreError>,
+) -> Result<PreparedUbl, InvConvError> {
+    // compressed ubl from the batch fetch
+    let object_store_rec_for_xml = fetched.map_err(|err| match err {
+        ObjectStoreError::NoRecordFound(..) => {
+            InvConvError::UblNotFoundInObjectStore(item.object_id.clone())
+        }
+        err => err.into(),
+    })?;
+
+    let uncompressed_size = object_store_rec_for_xml.original_size as usize;
+    let decompressed: bytes::Bytes = xz_decompress(
+        object_store_rec_for_xml.objcontent,
+        uncompressed_size,
+        &item.object_id,
+    )
+    .await?;
+
+    // Ubl and UblXsltSeparate archive the original (unsanitized) UBL, only transforms need it sanitized
+    let original = |xml: bytes::Bytes| if desanitize_ubl { desanitize(xml) } else { xml };
+    if target_type == TargetType::Ubl {
+        // Passthrough: no stylesheet key, no xslt fetch, no transform on the worker.
+        return Ok(PreparedUbl {
+            item,
+            xml_data: original(decompressed),
+            xslt_key: String::new(),
+        });
+    }
+
+    let sanitized_xml: bytes::Bytes =
+        sanitize_fast(decompressed.clone()).map_err(|e| InvConvError::NonUtfCharError {
+            object_id: item.object_id.clone(),
+            source: e,
+        })?;
+
+    //extract xslt key
+    let xslt_key: String = extract_xslt_key_from_xml(sanitized_xml.clone(), &item.object_id)?;
+    let xml_data = match target_type {
+        TargetType::UblXsltSeparate => original(decompressed),
+        _ => sanitized_xml,
+    };
+    Ok(PreparedUbl {
+        item,
+        xml_data,
+        xslt_key,
+    })
+}
+
+/// Job of a prepared invoice, in request order.
+///
+/// `xslt_cache` holds the keys already sent to the worker in this request;
+/// the stylesheet bytes only travel with the first job of each key.
+async fn resolve_job(
+    state: &SharedState,
+    year: &str,
+    ubl: PreparedUbl,
+    xslt_cache: &mut HashMap<String, bytes::Bytes>,
+) -> Result<InvoiceConversionJob, InvConvError> {
+    let PreparedUbl {
+        item,
+        xml_data,
+        xslt_key,
+    } = ubl;
+    if xslt_key.is_empty() || xslt_cache.contains_key(&xslt_key) {
+        // Ubl, or cache HIT - worker already has this XSLT
+        return Ok(InvoiceConversionJob {
+            item,
+            xml_data,
+            xslt_key,
+            xslt_data: None,
+        });
+    }
+
+    // Cache MISS for this request: memory cache, disk cache, then object store
+    let xslt_data = get_xslt_cached(state, year, &xslt_key).await?;
+    xslt_cache.insert(xslt_key.clone(), xslt_data.clone());
+    Ok(InvoiceConversionJob {
+        item,
+        xml_data,
+        xslt_key,
+        xslt_data: Some(xslt_data),
+    })
+}
+
+/// Server chain with the request's preferred engine moved to the front.
+fn engine_chain_for_request(
+    preferred: Option<XsltEngineKind>,
+    server_chain: &[XsltEngineKind],
+) -> Vec<XsltEngineKind> {
+    let mut chain: Vec<XsltEngineKind> = preferred.into_iter().collect();
+    chain.extend(server_chain.iter().filter(|k| Some(**k) != preferred));
+    chain
+}
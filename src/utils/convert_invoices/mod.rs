@@ -1,4 +1,19 @@
+pub mod add_xml_stylesheet_pi;
+pub mod archive_manifest;
 pub mod convert_and_zip_worker;
 pub mod extract_xslt_key_from_xml;
 pub mod get_xslt_from_objstore;
+pub mod incoming_invoice_page;
 pub mod invoice_conversion_manager;
+pub mod transform_pool;
+
+#[cfg(test)]
+mod add_xml_stylesheet_pi_tests;
+#[cfg(test)]
+mod archive_manifest_tests;
+#[cfg(test)]
+mod convert_and_zip_worker_tests;
+#[cfg(test)]
+mod incoming_invoice_page_tests;
+#[cfg(test)]
+mod transform_pool_tests;
@@ -0,0 +1,142 @@
+use crate::utils::common::html_to_pdf::html_to_pdf;
+use crate::utils::common::target_types_and_formats::TargetType;
+use crate::utils::common::xslt_cache::XsltCache;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::engine_chain::XsltEngineChain;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use tokio_util::bytes::Bytes;
+
+/// Document and engine that produced it
+pub type TransformResult = Result<(Bytes, XsltEngineKind), InvConvError>;
+
+/// One Html / Pdf document for the pool, `seq` orders the results again
+pub struct TransformTask {
+    pub seq: u64,
+    pub target_type: TargetType,
+    pub xslt_key: String,
+    pub xslt: Bytes,
+    pub xml: Bytes,
+    /// Pdf title and error context
+    pub title: String,
+}
+
+/// Transform threads of one conversion, each with its own `XsltEngineChain`.
+///
+/// Results come back in completion order, `convert_and_zip` puts them back into
+/// request order. Dropping the pool skips the queued tasks and joins the threads.
+pub struct TransformPool {
+    tasks: Option<mpsc::Sender<TransformTask>>,
+    results: mpsc::Receiver<(u64, TransformResult)>,
+    closed: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl TransformPool {
+    pub fn new(
+        workers: usize,
+        request_id: &str,
+        xslt_engines: &[XsltEngineKind],
+        shared: Arc<XsltCache>,
+    ) -> std::io::Result<Self> {
+        let (tasks, task_rx) = mpsc::channel::<TransformTask>();
+        let (result_tx, results) = mpsc::channel();
+        let task_rx = Arc::new(Mutex::new(task_rx));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let mut pool = Self {
+            tasks: Some(tasks),
+            results,
+            closed: closed.clone(),
+            threads: Vec::with_capacity(workers),
+        };
+        for n in 0..workers.max(1) {
+            let (task_rx, result_tx, closed) = (task_rx.clone(), result_tx.clone(), closed.clone());
+            let (xslt_engines, shared) = (xslt_engines.to_vec(), shared.clone());
+            let thread = thread::Builder::new()
+                .name(format!("xslt-{request_id}-{n}"))
+                .spawn(move || {
+                    let mut engine_chain = XsltEngineChain::new(&xslt_engines, Some(shared));
+                    loop {
+                        // Lock only to take the next task
+                        let task = match task_rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(task) = task else {
+                            return; // pool dropped
+                        };
+                        if closed.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let result = catch_unwind(AssertUnwindSafe(|| {
+                            transform_document(
+                                &mut engine_chain,
+                                task.target_type,
+                                &task.xslt_key,
+                                &task.xslt,
+                                &task.xml,
+                                &task.title,
+                            )
+                        }))
+                        .unwrap_or_else(|_| {
+                            Err(InvConvError::TaskJoinError(format!(
+                                "transform of {} panicked",
+                                task.title
+                            )))
+                        });
+                        if result_tx.send((task.seq, result)).is_err() {
+                            return;
+                        }
+                    }
+                })?;
+            pool.threads.push(thread);
+        }
+        Ok(pool)
+    }
+
+    pub fn submit(&self, task: TransformTask) -> Result<(), InvConvError> {
+        self.tasks
+            .as_ref()
+            .and_then(|tasks| tasks.send(task).ok())
+            .ok_or_else(|| InvConvError::TaskJoinError("transform pool stopped".to_string()))
+    }
+
+    /// Next finished task, blocks until one is done
+    pub fn recv(&self) -> Result<(u64, TransformResult), InvConvError> {
+        self.results
+            .recv()
+            .map_err(|_| InvConvError::TaskJoinError("transform pool stopped".to_string()))
+    }
+}
+
+impl Drop for TransformPool {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.tasks = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Html / Pdf: run the engine chain, Pdf is rendered from the transformed html.
+pub fn transform_document(
+    engine_chain: &mut XsltEngineChain,
+    target_type: TargetType,
+    xslt_key: &str,
+    xslt: &Bytes,
+    xml: &Bytes,
+    title: &str,
+) -> TransformResult {
+    let (html_bytes, kind) = engine_chain.transform(xslt_key, xslt, xml)?;
+
+    // Html goes as is, Pdf is rendered from the transformed html
+    match target_type {
+        TargetType::Pdf => Ok((html_to_pdf(&html_bytes, title)?, kind)),
+        _ => Ok((html_bytes, kind)),
+    }
+}
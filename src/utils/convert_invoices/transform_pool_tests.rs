@@ -0,0 +1,71 @@
+use super::transform_pool::{TransformPool, TransformTask};
+use crate::utils::common::target_types_and_formats::TargetType;
+use crate::utils::common::xslt_cache::{XsltCache, XsltCacheConfig};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_util::bytes::Bytes;
+
+const XSLT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+  <xsl:template match="/"><p><xsl:value-of select="/Invoice/ID"/></p></xsl:template>
+</xsl:stylesheet>"#;
+
+fn pool(workers: usize) -> TransformPool {
+    TransformPool::new(
+        workers,
+        "test",
+        &[XsltEngineKind::LibXslt],
+        Arc::new(XsltCache::new(XsltCacheConfig::default())),
+    )
+    .unwrap()
+}
+
+fn task(seq: u64, xml: String) -> TransformTask {
+    TransformTask {
+        seq,
+        target_type: TargetType::Html,
+        xslt_key: "simple".to_string(),
+        xslt: Bytes::from_static(XSLT),
+        xml: Bytes::from(xml),
+        title: format!("Fat_{seq}.html"),
+    }
+}
+
+#[test]
+fn every_task_comes_back_with_its_seq() {
+    let pool = pool(3);
+    for seq in 0..20 {
+        let xml = format!("<Invoice><ID>INV{seq:03}</ID></Invoice>");
+        pool.submit(task(seq, xml)).unwrap();
+    }
+
+    let mut outputs = HashMap::new();
+    for _ in 0..20 {
+        let (seq, result) = pool.recv().unwrap();
+        let (html, kind) = result.unwrap();
+        assert_eq!(kind, XsltEngineKind::LibXslt);
+        outputs.insert(seq, String::from_utf8(html.to_vec()).unwrap());
+    }
+    for seq in 0..20 {
+        assert!(outputs[&seq].contains(&format!("INV{seq:03}")));
+    }
+}
+
+#[test]
+fn failed_transform_is_returned_not_dropped() {
+    let pool = pool(2);
+    pool.submit(TransformTask {
+        xslt_key: "broken".to_string(),
+        xslt: Bytes::from_static(b"not a stylesheet"),
+        ..task(0, "<Invoice/>".to_string())
+    })
+    .unwrap();
+    let (seq, result) = pool.recv().unwrap();
+    assert_eq!(seq, 0);
+    assert!(matches!(
+        result,
+        Err(InvConvError::XsltEnginesExhausted { .. })
+    ));
+}
@@ -4,34 +4,62 @@ use bb8_tiberius::ConnectionManager;
 use tiberius::{AuthMethod, Config};
 
 //use crate::utils::errors::app_errors::{AppError, ErrCtx as AppErrCtx};
+use crate::utils::config::app_config::DatabaseConfig;
+use crate::utils::database_manager::pool_registry::{PoolKey, PoolRegistry, PoolRoute};
 use crate::utils::errors::db_errors::{DbError, ErrCtx as DbErrCtx};
+use crate::utils::incoming_invoice::tenant_databases::is_valid_db_name;
+use std::sync::Arc;
 
 pub type ConnectionPool = Pool<ConnectionManager>;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct DbPools {
-    pub incoming_invoice_pool: ConnectionPool,
+    /// `[database]`, created at startup, serves every key no route matches
+    pub default_pool: ConnectionPool,
+    /// `[pools.*]` by role, year and tenant, created on first use
+    pub registry: Arc<PoolRegistry>,
     //pub xslt_pool: ConnectionPool,
     //pub cert_pool: ConnectionPool,
 }
+impl DbPools {
+    pub async fn pool(&self, key: &PoolKey<'_>) -> Result<ConnectionPool, DbError> {
+        match self.registry.get(key).await.ctx("DbPools::pool")? {
+            Some(pool) => Ok(pool),
+            None => Ok(self.default_pool.clone()),
+        }
+    }
+}
 
-pub async fn init_db_connection_pool(name: &str) -> Result<ConnectionPool, DbError> {
+fn pool_builder(
+    db_config: &DatabaseConfig,
+) -> (bb8::Builder<ConnectionManager>, ConnectionManager) {
     let mut config = Config::new();
-    config.host("192.168.3.28");
-    config.port(1433);
-    config.database("uut_24_6");
-    config.authentication(AuthMethod::sql_server("uut", "uut"));
-    config.trust_cert(); // Only for development
+    config.host(&db_config.host);
+    config.port(db_config.port);
+    config.database(&db_config.database);
+    config.authentication(AuthMethod::sql_server(
+        &db_config.user,
+        db_config.password.expose(),
+    ));
+    if db_config.trust_cert {
+        config.trust_cert(); // Only for development
+    }
 
-    check_database_name("uut_24_6".to_string())?;
-    let manager = ConnectionManager::new(config);
+    let builder = Pool::builder()
+        .max_size(db_config.max_size) // Maximum number of connections in the pool
+        .min_idle(Some(db_config.min_idle)) // Minimum idle connections to maintain
+        .max_lifetime(Some(db_config.max_lifetime))
+        .idle_timeout(Some(db_config.idle_timeout))
+        .connection_timeout(db_config.connection_timeout);
+    (builder, ConnectionManager::new(config))
+}
 
-    let pool = Pool::builder()
-        .max_size(10) // Maximum number of connections in the pool
-        .min_idle(Some(2)) // Minimum idle connections to maintain
-        .max_lifetime(Some(std::time::Duration::from_secs(3600))) // 1 hour max lifetime
-        .idle_timeout(Some(std::time::Duration::from_secs(600))) // 10 minutes idle timeout
-        .connection_timeout(std::time::Duration::from_secs(5)) // 30 seconds connection timeout
+pub async fn init_db_connection_pool(
+    name: &str,
+    db_config: &DatabaseConfig,
+) -> Result<ConnectionPool, DbError> {
+    let (builder, manager) = pool_builder(db_config);
+    let pool = builder
         .build(manager)
         .await
         .ctx("init_db_connection_pool:build")?;
@@ -40,8 +68,12 @@ pub async fn init_db_connection_pool(name: &str) -> Result<ConnectionPool, DbErr
     Ok(pool)
 }
 
-pub async fn init_db_connection_pools() -> Result<DbPools, DbError> {
-    let incoming_invoice_pool = init_db_connection_pool("incoming_invoice_pool").await?;
+pub async fn init_db_connection_pools(
+    db_config: &DatabaseConfig,
+    routes: Vec<PoolRoute>,
+) -> Result<DbPools, DbError> {
+    check_database_name(db_config.database.clone())?;
+    let default_pool = init_db_connection_pool("default_pool", db_config).await?;
 
     /*
     let xslt_pool = init_db_connection_pool("xslt_pool")
@@ -55,16 +87,28 @@ pub async fn init_db_connection_pools() -> Result<DbPools, DbError> {
         .ctx("init_db_connection_pools/cert")?;
         */
     Ok(DbPools {
-        incoming_invoice_pool,
+        default_pool,
+        registry: Arc::new(PoolRegistry::new(routes)),
         //xslt_pool,
         //cert_pool,
     })
 }
 
+/// Same pools, but the default pool connects on first use instead of at startup.
+/// For the `fs` object store, where the server and tests may run without MSSQL.
+pub fn lazy_db_connection_pools(db_config: &DatabaseConfig, routes: Vec<PoolRoute>) -> DbPools {
+    let (builder, manager) = pool_builder(db_config);
+    DbPools {
+        default_pool: builder.min_idle(None).build_unchecked(manager),
+        registry: Arc::new(PoolRegistry::new(routes)),
+    }
+}
+
+/// Tenant databases are named uut_YY_N
 pub fn check_database_name(db_name: String) -> Result<(), DbError> {
-    let expected = "uut_24_6";
+    let expected = "uut_YY_N";
 
-    if db_name != expected {
+    if !is_valid_db_name(&db_name) {
         return Err(DbError::WrongDatabaseName {
             expected,
             found: db_name.to_string(),
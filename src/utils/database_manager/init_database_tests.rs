@@ -1,4 +1,5 @@
 use super::init_database::{DbPools, check_database_name, init_db_connection_pools};
+use crate::utils::config::app_config::AppConfig;
 
 #[test]
 fn check_database_name_test() {
@@ -10,8 +11,10 @@ fn check_database_name_test() {
 }
 
 #[tokio::test]
+#[ignore = "needs the MSSQL test server"]
 async fn init_db_connection_pools_test() {
-    match init_db_connection_pools().await {
+    let config = AppConfig::load().expect("config");
+    match init_db_connection_pools(&config.database, config.pools.clone()).await {
         Ok(pools) => {
             println!("✅ Database connection pools initialized successfully.");
             // Optionally, you can add more checks here to verify the pools
@@ -1,4 +1,7 @@
 pub mod init_database;
+pub mod pool_registry;
 
 #[cfg(test)]
 mod init_database_tests;
+#[cfg(test)]
+mod pool_registry_tests;
@@ -0,0 +1,109 @@
+use crate::utils::config::app_config::DatabaseConfig;
+use crate::utils::database_manager::init_database::{ConnectionPool, init_db_connection_pool};
+use crate::utils::errors::db_errors::DbError;
+use std::ops::RangeInclusive;
+use tokio::sync::OnceCell;
+
+/// Logical pool names the queries ask for
+pub const INCOMING_INVOICE_POOL: &str = "incoming_invoice";
+pub const OBJECT_STORE_POOL: &str = "object_store";
+
+/// Object store database of a year when the route does not set `db_name`
+pub const DEFAULT_OBJECT_STORE_DB: &str = "EFaturaDB01_{year}";
+
+/// What a query needs a connection for.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolKey<'a> {
+    pub role: &'a str,
+    pub year: Option<u16>,
+    /// Receiver VKN/TCKN
+    pub tenant: Option<&'a str>,
+}
+impl<'a> PoolKey<'a> {
+    pub fn new(role: &'a str) -> Self {
+        Self {
+            role,
+            year: None,
+            tenant: None,
+        }
+    }
+    pub fn with_year(mut self, year: u16) -> Self {
+        self.year = Some(year);
+        self
+    }
+    pub fn with_tenant(mut self, tenant: &'a str) -> Self {
+        self.tenant = Some(tenant);
+        self
+    }
+}
+
+/// One `[pools.NAME]` entry: which keys it serves and where it connects.
+#[derive(Debug, Clone)]
+pub struct PoolRoute {
+    pub name: String,
+    pub role: String,
+    /// `None` serves every year
+    pub years: Option<RangeInclusive<u16>>,
+    /// Receiver VKN/TCKNs of the tenant group, empty serves every tenant
+    pub tenants: Vec<String>,
+    /// Database queried on this server, `{year}` is replaced
+    pub db_name: Option<String>,
+    pub database: DatabaseConfig,
+}
+impl PoolRoute {
+    pub fn matches(&self, key: &PoolKey<'_>) -> bool {
+        self.role == key.role
+            && self
+                .years
+                .as_ref()
+                .is_none_or(|years| key.year.is_some_and(|year| years.contains(&year)))
+            && (self.tenants.is_empty()
+                || key
+                    .tenant
+                    .is_some_and(|tenant| self.tenants.iter().any(|t| t == tenant)))
+    }
+}
+
+/// Routed pools, each created on first use. Routes are tried in config order.
+#[derive(Debug)]
+pub struct PoolRegistry {
+    routes: Vec<(PoolRoute, OnceCell<ConnectionPool>)>,
+}
+
+impl PoolRegistry {
+    pub fn new(routes: Vec<PoolRoute>) -> Self {
+        Self {
+            routes: routes
+                .into_iter()
+                .map(|route| (route, OnceCell::new()))
+                .collect(),
+        }
+    }
+
+    /// First route serving `key`
+    pub fn route(&self, key: &PoolKey<'_>) -> Option<&PoolRoute> {
+        self.routes
+            .iter()
+            .map(|(route, _)| route)
+            .find(|route| route.matches(key))
+    }
+
+    /// Pool of the first matching route, `None` when no route serves `key`.
+    pub async fn get(&self, key: &PoolKey<'_>) -> Result<Option<ConnectionPool>, DbError> {
+        let Some((route, pool)) = self.routes.iter().find(|(route, _)| route.matches(key)) else {
+            return Ok(None);
+        };
+        let pool = pool
+            .get_or_try_init(|| init_db_connection_pool(&route.name, &route.database))
+            .await?;
+        Ok(Some(pool.clone()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
@@ -0,0 +1,120 @@
+use super::pool_registry::{
+    INCOMING_INVOICE_POOL, OBJECT_STORE_POOL, PoolKey, PoolRegistry, PoolRoute,
+};
+use crate::utils::config::app_config::AppConfig;
+use crate::utils::errors::config_errors::ConfigError;
+use crate::utils::incoming_invoice::tenant_databases::incoming_invoice_pool_key;
+
+const TOML: &str = r#"
+[database]
+host = "10.0.0.1"
+database = "uut_25_1"
+user = "uut"
+password = "default-pw"
+
+[pools.objstore_old]
+role = "object_store"
+years = "2019-2023"
+host = "10.0.0.5"
+db_name = "EFaturaArchive_{year}"
+database = "EFaturaArchive_2023"
+
+[pools.objstore]
+role = "object_store"
+host = "10.0.0.6"
+database = "EFaturaDB01_2025"
+
+[pools.big_tenants]
+role = "incoming_invoice"
+tenants = ["1950031086", "1234567890"]
+years = 2025
+host = "10.0.0.7"
+"#;
+
+fn routes() -> Vec<PoolRoute> {
+    let config = AppConfig::from_toml("test.toml", TOML, |name| {
+        (name == "DB_PASSWORD_BIG_TENANTS").then(|| "tenant-pw".to_string())
+    })
+    .unwrap();
+    config.validate().unwrap();
+    config.pools
+}
+
+#[test]
+fn routes_inherit_the_default_database() {
+    let routes = routes();
+    assert_eq!(routes.len(), 3);
+    assert_eq!(routes[0].name, "objstore_old");
+    assert_eq!(routes[0].years, Some(2019..=2023));
+    assert_eq!(routes[0].database.host, "10.0.0.5");
+    assert_eq!(routes[0].database.user, "uut");
+    assert_eq!(routes[0].database.password.expose(), "default-pw");
+    assert_eq!(routes[2].tenants, vec!["1950031086", "1234567890"]);
+    assert_eq!(routes[2].database.password.expose(), "tenant-pw");
+}
+
+#[test]
+fn first_matching_route_wins() {
+    let registry = PoolRegistry::new(routes());
+    let name = |key: PoolKey<'_>| registry.route(&key).map(|route| route.name.clone());
+
+    let objects = PoolKey::new(OBJECT_STORE_POOL);
+    assert_eq!(
+        name(objects.with_year(2021)).as_deref(),
+        Some("objstore_old")
+    );
+    assert_eq!(name(objects.with_year(2025)).as_deref(), Some("objstore"));
+    assert_eq!(name(objects).as_deref(), Some("objstore"));
+
+    assert_eq!(
+        name(incoming_invoice_pool_key("uut_25_1", "1950031086")).as_deref(),
+        Some("big_tenants")
+    );
+    // Other year or other tenant: default pool
+    assert_eq!(
+        name(incoming_invoice_pool_key("uut_24_6", "1950031086")),
+        None
+    );
+    assert_eq!(
+        name(incoming_invoice_pool_key("uut_25_1", "9999999999")),
+        None
+    );
+    assert_eq!(name(PoolKey::new(INCOMING_INVOICE_POOL)), None);
+}
+
+#[tokio::test]
+async fn unrouted_keys_create_no_pool() {
+    let registry = PoolRegistry::new(routes());
+    let key = PoolKey::new("cert");
+    assert!(registry.get(&key).await.unwrap().is_none());
+}
+
+#[test]
+fn invalid_pool_entries_are_rejected() {
+    let load = |toml: &str| {
+        let env = |name: &str| match name {
+            "DB_HOST" => Some("h".to_string()),
+            "DB_NAME" => Some("uut_25_1".to_string()),
+            "DB_USER" => Some("u".to_string()),
+            "DB_PASSWORD" => Some("pw".to_string()),
+            _ => None,
+        };
+        AppConfig::from_toml("t.toml", toml, env)?.validate()
+    };
+    assert!(matches!(
+        load("[pools.a]\nrole = \"x\"\nyears = \"2025-2020\"\n"),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "pools.a.years"
+    ));
+    assert!(matches!(
+        load("[pools.a]\nrole = \"x\"\nhots = \"h\"\n"),
+        Err(ConfigError::UnknownKey(key)) if key == "pools.a.hots"
+    ));
+    assert!(matches!(
+        load("[pools.a]\nhost = \"h\"\n"),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "pools.a.role"
+    ));
+    assert!(matches!(
+        load("[pools.a]\nrole = \"x\"\ndb_name = \"db; DROP\"\n"),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "pools.a.db_name"
+    ));
+}
@@ -1,117 +1,87 @@
-use crate::utils::download_request::download_request_types::{
-    DownloadDocRequest, DownloadFormat, DownloadType,
-};
+use crate::utils::common::target_types_and_formats::FilenameInZipMode;
+use crate::utils::convert_invoices::incoming_invoice_page::IncomingInvoicePage;
+use crate::utils::convert_invoices::invoice_conversion_manager::InvoicesForConversion;
+use crate::utils::download_request::download_request_types::DownloadDocRequest;
 use crate::utils::errors::download_request_errors::{
     DownloadRequestError, ErrCtx as DownloadRequestErrCtx,
 };
+use crate::utils::incoming_invoice::get_incoming_invoice_recs_afterthis::get_incoming_invoice_recs_afterthis;
+use crate::utils::incoming_invoice::tenant_databases::{
+    TenantDatabases, incoming_invoice_pool_key,
+};
 use crate::utils::{
-    database_manager::init_database, incoming_invoice::incoming_invoice_rec::IncomingInvoiceRec,
+    database_manager::init_database::DbPools,
+    incoming_invoice::incoming_invoice_rec::IncomingInvoiceRec,
 };
-use tiberius::Query;
 
 impl DownloadDocRequest {
-    fn extract_dbname(&self) -> Result<String, DownloadRequestError> {
-        //Ok(format!("uut_{}_{}", 24, 6))
-        return Err(DownloadRequestError::CanNotExtractDatabaseName(
-            "path in incomin invoices".to_string(),
-        ));
-    }
-
-    pub fn validate_download_type_and_format(&self) -> Result<(), DownloadRequestError> {
-        use DownloadFormat::*;
-        use DownloadType::*;
-
-        match (self.download_type, self.format) {
-            // ✅ valid combinations
-            (Html, Zip)
-            | (Html, Gzip)
-            | (Pdf, Zip)
-            | (Pdf, Gzip)
-            | (Ubl, Zip)
-            | (Ubl, Gzip)
-            | (UblXsltSeparate, Zip)
-            | (UblXsltSeparate, Gzip) => Ok(()),
-
-            // 🚫 anything else
-            _ => Err(DownloadRequestError::InvalidTypeFormat {
-                downloadtype: self.download_type,
-                format: self.format,
-            }),
-        }
+    /// Tenant database (`uut_YY_N`) of `source_vkntckn` in the lookup table, the request's
+    /// `db_name` must be the same one.
+    pub fn extract_dbname(
+        &self,
+        tenants: &TenantDatabases,
+    ) -> Result<String, DownloadRequestError> {
+        tenants
+            .resolve(self.db_name.as_deref(), &self.source_vkntckn)
+            .ok_or_else(|| {
+                DownloadRequestError::CanNotExtractDatabaseName(format!(
+                    "'{}' is not the database of {}",
+                    self.db_name.as_deref().unwrap_or_default(),
+                    self.source_vkntckn
+                ))
+            })
     }
 
+    /// INCOMING_INVOICE page of the request, without converting it.
     pub async fn get_incoming_invoice_recs(
         &self,
-        pool: &init_database::ConnectionPool,
+        db_pools: &DbPools,
+        tenants: &TenantDatabases,
     ) -> Result<Vec<IncomingInvoiceRec>, DownloadRequestError> {
-        self.validate_download_type_and_format()?;
-        let db_name = self.extract_dbname().ctx("get_incoming_invoice_recs")?;
-        let sql_sentence = format!(
-            "SELECT TOP 100 UUID, INVOICE_ID, RECEIVER_CONTACT, SIRA_NO, PATH
-             FROM {}.dbo.INCOMING_INVOICE
-             WHERE RECEIVER_CONTACT = @P1 AND SIRA_NO > @P2
-             ORDER BY SIRA_NO ASC",
-            db_name // e.g. "uut_24_6" or "uut_25_1"
-        );
-        let mut query = Query::new(sql_sentence);
-        query.bind(self.source_vkntckn.clone());
-        query.bind(self.after_this);
-
-        let mut conn = pool
-            .get()
-            .await
-            .map_err(DownloadRequestError::from)
+        let db_name = self
+            .extract_dbname(tenants)
             .ctx("get_incoming_invoice_recs")?;
-
-        let stream = query
-            .query(&mut *conn)
-            .await
-            .map_err(DownloadRequestError::from)
-            .ctx("get_incoming_invoice_recs:Query")?;
-        let rows = stream
-            .into_first_result()
+        let pool = db_pools
+            .pool(&incoming_invoice_pool_key(&db_name, &self.source_vkntckn))
             .await
-            .map_err(DownloadRequestError::from)
-            .ctx("get_incoming_invoice_recs:stream")?;
+            .ctx("get_incoming_invoice_recs")?;
+        let page = self.incoming_invoice_page(db_name);
 
-        let mut incoming_invoice_recs: Vec<IncomingInvoiceRec> = Vec::with_capacity(rows.len());
-        for row in rows {
-            // Try to extract all fields
-            let Some(uuid) = row.get::<&str, _>(0) else {
-                eprintln!("⚠️ Skipping row: missing UUID");
-                continue;
-            };
-            let Some(invoice_id) = row.get::<&str, _>(1) else {
-                eprintln!("⚠️ Skipping row: missing INVOICE_ID");
-                continue;
-            };
-            let Some(receiver_contact) = row.get::<&str, _>(2) else {
-                eprintln!("⚠️ Skipping row: missing RECEIVER_CONTACT");
-                continue;
-            };
-            let Some(sira_no_i64) = row.get::<i64, _>(3) else {
-                eprintln!("⚠️ Skipping row: missing SIRA_NO");
-                continue;
-            };
-            let Some(path) = row.get::<&str, _>(4) else {
-                eprintln!("⚠️ Skipping row: missing PATH");
-                continue;
-            };
+        get_incoming_invoice_recs_afterthis(
+            &pool,
+            page.db_name.as_deref().unwrap_or_default(),
+            &page.source_vkntckn,
+            page.after_this as i64,
+            i64::from(page.page_size()),
+        )
+        .await
+        .ctx("get_incoming_invoice_recs")
+    }
 
-            incoming_invoice_recs.push(IncomingInvoiceRec {
-                uuid: uuid.to_string(),
-                invoice_id: invoice_id.to_string(),
-                receiver_contact: receiver_contact.to_string(),
-                sira_no: sira_no_i64 as u64,
-                path: path.to_string(),
-                //s3: None,
-            });
+    /// Conversion request over the next INCOMING_INVOICE page in `db_name`.
+    pub fn into_conversion_request(self, db_name: String) -> InvoicesForConversion {
+        let page = self.incoming_invoice_page(db_name);
+        InvoicesForConversion {
+            target_type: self.download_type.into(),
+            target_compression_type: self.format.into(),
+            year: String::new(), // from the records
+            filename_in_zip: FilenameInZipMode::default(),
+            request_id: format!("{}_{}", self.source_vkntckn, page.after_this),
+            client_id: Some(self.source_vkntckn),
+            xslt_engine: None,
+            continue_on_error: false,
+            desanitize: false,
+            incoming_invoice: Some(page),
+            items: Vec::new(),
         }
+    }
 
-        println!(
-            "✅ Successfully parsed {} records",
-            incoming_invoice_recs.len()
-        );
-        Ok(incoming_invoice_recs)
+    fn incoming_invoice_page(&self, db_name: String) -> IncomingInvoicePage {
+        IncomingInvoicePage {
+            source_vkntckn: self.source_vkntckn.clone(),
+            after_this: self.after_this.max(0) as u64,
+            page_size: self.page_size,
+            db_name: Some(db_name),
+        }
     }
 }
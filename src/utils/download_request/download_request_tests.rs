@@ -1,111 +1,113 @@
+use crate::utils::common::target_types_and_formats::{TargetCompressionType, TargetType};
+use crate::utils::config::app_config::{AppConfig, DatabaseConfig};
 use crate::utils::database_manager::init_database::{DbPools, init_db_connection_pools};
 use crate::utils::download_request::download_request_types::{
     DownloadDocRequest, DownloadFormat, DownloadType,
 };
-use crate::utils::get_and_process_invoices::process_invoices_types::GetAndProcessInvoicesRequest;
-use crate::utils::object_store::object_store::Store;
-use crate::utils::object_store::opendal_mssql_wrapper::MssqlStore;
+use crate::utils::errors::download_request_errors::DownloadRequestError;
+use crate::utils::incoming_invoice::tenant_databases::{TenantDatabases, parse_tenant_table};
 
-use std::sync::Arc;
-
-async fn get_new_pools() -> anyhow::Result<DbPools> {
-    let db_pools: DbPools = init_db_connection_pools().await?;
-    Ok(db_pools)
-}
-
-#[tokio::test]
-async fn test_no_invoice_after_sirano() {
-    let pools: DbPools = get_new_pools().await.unwrap();
-
-    let request = DownloadDocRequest {
+fn request(after_this: i64) -> DownloadDocRequest {
+    DownloadDocRequest {
         source_vkntckn: "1950031086".to_string(),
-        after_this: 26000,
+        after_this,
         download_type: DownloadType::Html,
         format: DownloadFormat::Zip,
-    };
+        db_name: None,
+        page_size: None,
+    }
+}
 
-    let invoices = request
-        .get_incoming_invoice_recs(&pools.incoming_invoice_pool)
-        .await
-        .unwrap();
-    assert_eq!(invoices.len(), 0);
+fn db_config() -> DatabaseConfig {
+    AppConfig::load().expect("config").database
 }
 
-#[tokio::test]
-async fn test_get_100_invoices() {
-    let pools: DbPools = get_new_pools().await.unwrap();
+fn tenants() -> TenantDatabases {
+    AppConfig::load().expect("config").tenant_databases()
+}
 
-    let request = DownloadDocRequest {
-        source_vkntckn: "1950031086".to_string(),
-        after_this: 24000,
-        download_type: DownloadType::Html,
-        format: DownloadFormat::Zip,
+#[test]
+fn curl_body_deserializes() {
+    // Body of curl1.sh
+    let request: DownloadDocRequest = serde_json::from_str(
+        r#"{ "source_vkntckn": "1950031086", "after_this": 25000,
+             "download_type": "html", "format": "zip" }"#,
+    )
+    .unwrap();
+    assert_eq!(request.download_type, DownloadType::Html);
+    assert_eq!(request.format, DownloadFormat::Zip);
+    assert_eq!(request.db_name, None);
+}
+
+#[test]
+fn dbname_from_lookup_table_confirmed_by_request() {
+    let tenants = TenantDatabases {
+        default_db: "uut_24_6".to_string(),
+        by_vkntckn: parse_tenant_table("1950031086=uut_25_1").unwrap(),
     };
+    assert_eq!(request(0).extract_dbname(&tenants).unwrap(), "uut_25_1");
+
+    let mut explicit = request(0);
+    explicit.db_name = Some("uut_25_1".to_string());
+    assert_eq!(explicit.extract_dbname(&tenants).unwrap(), "uut_25_1");
+
+    // The default database belongs to other receivers
+    explicit.db_name = Some("uut_24_6".to_string());
+    assert!(explicit.extract_dbname(&tenants).is_err());
+
+    explicit.db_name = Some("uut_24_6.dbo.X;--".to_string());
+    let err = explicit.extract_dbname(&tenants).unwrap_err();
+    assert!(matches!(
+        err,
+        DownloadRequestError::CanNotExtractDatabaseName(_)
+    ));
+    assert_eq!(err.error_code(), 4001);
+}
 
-    let invoices = request
-        .get_incoming_invoice_recs(&pools.incoming_invoice_pool)
-        .await
-        .unwrap();
-    assert!(invoices.len() == 100, "Should retrieve 100 invoices");
+#[test]
+fn conversion_request_pages_incoming_invoice() {
+    let mut download = request(25000);
+    download.download_type = DownloadType::Pdf;
+    download.format = DownloadFormat::Gzip;
+    download.page_size = Some(20);
+
+    let conversion = download.into_conversion_request("uut_25_1".to_string());
+    assert_eq!(conversion.target_type, TargetType::Pdf);
+    assert_eq!(
+        conversion.target_compression_type,
+        TargetCompressionType::Gzip
+    );
+    assert!(conversion.items.is_empty());
+    let page = conversion.incoming_invoice.unwrap();
+    assert_eq!(page.after_this, 25000);
+    assert_eq!(page.page_size(), 20);
+    assert_eq!(page.db_name.as_deref(), Some("uut_25_1"));
 }
 
 #[tokio::test]
-async fn test_get_44_invoices() {
-    let pools: DbPools = get_new_pools().await.unwrap();
-
-    let request = DownloadDocRequest {
-        source_vkntckn: "1950031086".to_string(),
-        after_this: 25000,
-        download_type: DownloadType::Html,
-        format: DownloadFormat::Zip,
-    };
-    let request: Arc<DownloadDocRequest> = Arc::new(request);
-
-    let invoices = request
-        .get_incoming_invoice_recs(&pools.incoming_invoice_pool)
+#[ignore = "needs the MSSQL test server"]
+async fn test_no_invoice_after_sirano() {
+    let pools: DbPools = init_db_connection_pools(&db_config(), Vec::new())
         .await
         .unwrap();
-    assert!(invoices.len() == 9, "Should retrieve 44 invoices");
-
-    println!("✅ Found {} invoice records", invoices.len());
-    let process_invoices_req = GetAndProcessInvoicesRequest {
-        request: request.clone(),
-        invoices: invoices,
-    };
-    let object_store = Store::Mssql(
-        MssqlStore::new_mssql()
-            .await
-            .expect("Failed to init MSSQL store"),
-    );
-    match process_invoices_req.process(&object_store).await {
-        Ok(result) => {
-            println!(
-                "✅ Processed {} invoices, size: {} bytes",
-                result.record_count, result.size_bytes
-            );
-        }
-        Err(e) => {
-            assert!(4 == 5, "❌ Processing error: {}", e);
-        }
-    }
+    let invoices = request(26000)
+        .get_incoming_invoice_recs(&pools, &tenants())
+        .await
+        .unwrap();
+    assert_eq!(invoices.len(), 0);
 }
 
 #[tokio::test]
+#[ignore = "needs the MSSQL test server"]
 async fn test_invoices_sorted() {
-    let pools: DbPools = get_new_pools().await.unwrap();
-
-    let request = DownloadDocRequest {
-        source_vkntckn: "1950031086".to_string(),
-        after_this: 24000,
-        download_type: DownloadType::Html,
-        format: DownloadFormat::Zip,
-    };
-
-    let invoices = request
-        .get_incoming_invoice_recs(&pools.incoming_invoice_pool)
+    let pools: DbPools = init_db_connection_pools(&db_config(), Vec::new())
         .await
         .unwrap();
-
+    let invoices = request(24000)
+        .get_incoming_invoice_recs(&pools, &tenants())
+        .await
+        .unwrap();
+    assert_eq!(invoices.len(), 100);
     for i in 1..invoices.len() {
         assert!(
             invoices[i].sira_no >= invoices[i - 1].sira_no,
@@ -1,13 +1,17 @@
+use crate::utils::common::target_types_and_formats::{TargetCompressionType, TargetType};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "PascalCase")]
 pub enum DownloadType {
+    #[serde(alias = "html")]
     Html,
+    #[serde(alias = "pdf")]
     Pdf,
+    #[serde(alias = "ubl")]
     Ubl,
-    #[serde(rename = "Ubl_Xslt_Separate")]
+    #[serde(rename = "Ubl_Xslt_Separate", alias = "ubl_xslt_separate")]
     UblXsltSeparate,
 }
 
@@ -22,6 +26,17 @@ impl fmt::Display for DownloadType {
     }
 }
 
+impl From<DownloadType> for TargetType {
+    fn from(download_type: DownloadType) -> Self {
+        match download_type {
+            DownloadType::Html => TargetType::Html,
+            DownloadType::Pdf => TargetType::Pdf,
+            DownloadType::Ubl => TargetType::Ubl,
+            DownloadType::UblXsltSeparate => TargetType::UblXsltSeparate,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum DownloadFormat {
@@ -36,6 +51,14 @@ impl fmt::Display for DownloadFormat {
         }
     }
 }
+impl From<DownloadFormat> for TargetCompressionType {
+    fn from(format: DownloadFormat) -> Self {
+        match format {
+            DownloadFormat::Zip => TargetCompressionType::Zip,
+            DownloadFormat::Gzip => TargetCompressionType::Gzip,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
 pub struct DownloadDocRequest {
@@ -43,6 +66,12 @@ pub struct DownloadDocRequest {
     pub after_this: i64,
     pub download_type: DownloadType,
     pub format: DownloadFormat,
+    /// Tenant database `uut_YY_N` of `source_vkntckn`, when set it must match the lookup
+    #[serde(default)]
+    pub db_name: Option<String>,
+    /// Invoices per archive, defaults to 100, at most 1000
+    #[serde(default)]
+    pub page_size: Option<u32>,
 }
 
 impl std::fmt::Display for DownloadDocRequest {
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod download_request;
 pub mod download_request_types;
 
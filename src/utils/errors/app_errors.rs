@@ -6,8 +6,8 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum AppError {
-    // #[error(transparent)]
-    // Db(#[from] DbError),
+    #[error(transparent)]
+    Db(#[from] DbError),
     #[error(transparent)]
     Xml(#[from] XmlError),
 
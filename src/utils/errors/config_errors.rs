@@ -0,0 +1,26 @@
+use std::io;
+use thiserror::Error;
+
+/// Messages name the key, never the value, so secrets stay out of logs.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("can not read config file '{path}': {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid TOML in '{path}' line {line}: {msg}")]
+    Parse {
+        path: String,
+        line: usize,
+        msg: String,
+    },
+
+    #[error("unknown config key '{0}'")]
+    UnknownKey(String),
+
+    #[error("invalid config value for '{key}': {reason}")]
+    InvalidValue { key: String, reason: String },
+}
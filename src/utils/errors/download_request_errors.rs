@@ -1,3 +1,6 @@
+use crate::utils::errors::db_errors::DbError;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use axum::http::StatusCode;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -11,6 +14,12 @@ pub enum DownloadRequestError {
     #[error("problem in query build and execution: {0} ")]
     CanNotExcuteQuery(#[from] tiberius::error::Error),
 
+    #[error("database error: {0}")]
+    Db(#[from] DbError),
+
+    #[error(transparent)]
+    Conversion(#[from] InvConvError),
+
     // Function context (preserves typed inner error)
     #[error("{func}: {source}")]
     Context {
@@ -20,6 +29,29 @@ pub enum DownloadRequestError {
     },
 }
 
+impl DownloadRequestError {
+    pub fn error_code(&self) -> i32 {
+        match self {
+            DownloadRequestError::CanNotExtractDatabaseName(_) => 4001,
+            DownloadRequestError::CanNotGetConnectionFromPool(_) => 4003,
+            DownloadRequestError::CanNotExcuteQuery(_) => 4004,
+            DownloadRequestError::Db(_) => 4005,
+            DownloadRequestError::Conversion(e) => e.error_code(),
+            DownloadRequestError::Context { source, .. } => source.error_code(),
+        }
+    }
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            DownloadRequestError::CanNotExtractDatabaseName(_) => StatusCode::BAD_REQUEST,
+            DownloadRequestError::CanNotGetConnectionFromPool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            DownloadRequestError::CanNotExcuteQuery(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            DownloadRequestError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            DownloadRequestError::Conversion(e) => e.http_status(),
+            DownloadRequestError::Context { source, .. } => source.http_status(),
+        }
+    }
+}
+
 pub trait ErrCtx<T> {
     fn ctx(self, func: &'static str) -> Result<T, DownloadRequestError>;
 }
@@ -93,6 +93,21 @@ pub enum InvConvError {
     #[error("Xslt key is not in cache and xslt data is missing xsltkey: {0}")]
     XsltDataMissing(String),
 
+    #[error("PDF render error: {0}")]
+    PdfRenderError(String),
+
+    #[error("LibXslt XSLT error: {0}")]
+    LibXsltError(String),
+
+    #[error("All XSLT engines failed for xslt_key '{xslt_key}': {errors}")]
+    XsltEnginesExhausted { xslt_key: String, errors: String },
+
+    #[error("No year in INCOMING_INVOICE path, sira_no '{sira_no}': {path}")]
+    IncomingInvoiceYearMissing { sira_no: u64, path: String },
+
+    #[error("Tenant database '{0}' is not the receiver's database")]
+    InvalidDatabaseName(String),
+
     // Function context (preserves typed inner error)
     #[error("{func}: {source}")]
     Context {
@@ -115,6 +130,17 @@ impl InvConvError {
                 | InvConvError::ZipFileCreationError { .. }
         )
     }
+    /// Errors tied to a single invoice (bad data, missing object, failed transform).
+    /// With `continue_on_error` such items are skipped instead of stopping the batch.
+    pub fn is_item_error(&self) -> bool {
+        match self {
+            InvConvError::Context { source, .. } => source.is_item_error(),
+            InvConvError::ObjStoreError(e) => e.is_no_record_found(), // e.g. missing xslt
+            // Archive failures affect every following item
+            InvConvError::ZipError { .. } | InvConvError::ZipIOError { .. } => false,
+            e => !e.is_fatal(),
+        }
+    }
     pub fn error_code(&self) -> i32 {
         match self {
             InvConvError::DatabaseError(_) => 1001,
@@ -137,6 +163,11 @@ impl InvConvError {
             InvConvError::XRustXsltError(_) => 2012,
             InvConvError::XsltDataMissing(_) => 2013,
             InvConvError::ZipFileCreationError { .. } => 2014,
+            InvConvError::PdfRenderError(_) => 2015,
+            InvConvError::LibXsltError(_) => 2016,
+            InvConvError::XsltEnginesExhausted { .. } => 2017,
+            InvConvError::IncomingInvoiceYearMissing { .. } => 2018,
+            InvConvError::InvalidDatabaseName(_) => 2019,
 
             InvConvError::Context { source, .. } => source.error_code(),
         }
@@ -148,6 +179,8 @@ impl InvConvError {
             InvConvError::TaskJoinError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             InvConvError::ClientDisconnectedError(_) => StatusCode::GATEWAY_TIMEOUT,
             InvConvError::ObjStoreError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            InvConvError::IncomingInvoiceYearMissing { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            InvConvError::InvalidDatabaseName(_) => StatusCode::BAD_REQUEST,
             InvConvError::Context { source, .. } => source.http_status(),
             _ => StatusCode::OK,
         }
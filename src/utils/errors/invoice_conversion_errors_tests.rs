@@ -0,0 +1,44 @@
+use super::invoice_conversion_errors::{ErrCtx, InvConvError};
+use super::object_store_errors::ObjectStoreError;
+
+fn wrapped(e: InvConvError) -> InvConvError {
+    Err::<(), _>(e).ctx("test:wrapped").unwrap_err()
+}
+
+#[test]
+fn invoice_level_errors_are_item_errors() {
+    assert!(InvConvError::MissingNodeError("o1".to_string()).is_item_error());
+    assert!(InvConvError::UblNotFoundInObjectStore("o1".to_string()).is_item_error());
+    // Context is fatal by itself, the wrapped error decides
+    assert!(wrapped(InvConvError::PdfRenderError("x".to_string())).is_item_error());
+    assert!(
+        wrapped(InvConvError::ObjStoreError(
+            ObjectStoreError::NoRecordFound("xslts".to_string(), "k".to_string())
+        ))
+        .is_item_error()
+    );
+}
+
+#[test]
+fn system_and_archive_errors_are_not_item_errors() {
+    assert!(!InvConvError::ServerBusyError("busy".to_string()).is_item_error());
+    assert!(
+        !InvConvError::ObjStoreError(ObjectStoreError::MissingField("f".to_string()))
+            .is_item_error()
+    );
+    assert!(
+        !InvConvError::ZipIOError {
+            sira_no: "1".to_string(),
+            source: std::io::Error::other("disk full"),
+        }
+        .is_item_error()
+    );
+}
+
+#[test]
+fn context_keeps_inner_error_code() {
+    assert_eq!(
+        wrapped(InvConvError::MissingNodeError("o1".to_string())).error_code(),
+        2007
+    );
+}
@@ -0,0 +1,42 @@
+use axum::http::StatusCode;
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("Job not found (or expired): {0}")]
+    NotFound(String),
+
+    #[error("Job '{job_id}' has no result yet, state: {state}")]
+    NotFinished { job_id: String, state: String },
+
+    #[error("Job '{0}' ended without a result archive")]
+    NoResult(String),
+
+    #[error("Job '{job_id}' can not be cancelled, state: {state}")]
+    AlreadyFinished { job_id: String, state: String },
+
+    #[error("Job I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl JobError {
+    pub fn error_code(&self) -> i32 {
+        match self {
+            JobError::NotFound(_) => 3001,
+            JobError::NotFinished { .. } => 3002,
+            JobError::NoResult(_) => 3003,
+            JobError::Io(_) => 3004,
+            JobError::AlreadyFinished { .. } => 3005,
+        }
+    }
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            JobError::NotFound(_) => StatusCode::NOT_FOUND,
+            JobError::NotFinished { .. } => StatusCode::CONFLICT,
+            JobError::NoResult(_) => StatusCode::GONE,
+            JobError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            JobError::AlreadyFinished { .. } => StatusCode::CONFLICT,
+        }
+    }
+}
@@ -1,5 +1,27 @@
-use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::error::Error;
 
-pub fn log_error(e: &InvConvError) {
-    //log::error!("Error processing sira_no {}: {:?}", sira_no, e);
+pub fn log_error(e: &dyn Error) {
+    log::error!("{e}");
+}
+
+/// Writes `log` records at info and above to stderr
+struct StderrLogger;
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+    fn flush(&self) {}
+}
+
+/// Installed by the servers at startup, a no-op when a logger is already set
+pub fn init_logger() {
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
 }
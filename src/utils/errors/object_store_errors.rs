@@ -1,3 +1,4 @@
+use crate::utils::errors::db_errors::DbError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -5,11 +6,18 @@ pub enum ObjectStoreError {
     #[error("can not extract database name path: {0}")]
     CanNotExtractDatabaseName(String),
 
+    #[error("Database error: {0}")]
+    Db(#[from] DbError),
+
     #[error("bb8 pool Error: {0}")]
     PoolBuild(#[from] bb8::RunError<bb8_tiberius::Error>),
 
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Boxed, `opendal::Error` alone would double the size of every `Result` carrying this
     #[error("OpenDAL error: {0}")]
-    OpenDALError(#[from] opendal::Error),
+    OpenDALError(Box<opendal::Error>),
 
     #[error("MsSqlStore error: {0}")]
     MsSqlStoreError(#[from] tiberius::error::Error),
@@ -20,6 +28,15 @@ pub enum ObjectStoreError {
     #[error("No record found in bucket '{0}' for key '{1}'")]
     NoRecordFound(String, String),
 
+    #[error("Invalid object path '{0}'")]
+    InvalidObjectPath(String),
+
+    #[error("Object '{0}' is larger than the store accepts ({1} bytes)")]
+    ObjectTooLarge(String, u64),
+
+    #[error("Batch fetch ended before object '{0}'")]
+    BatchEnded(String),
+
     #[error("Missing field '{0}' ")]
     MissingField(String),
 
@@ -35,6 +52,22 @@ pub enum ObjectStoreError {
     },
 }
 
+impl From<opendal::Error> for ObjectStoreError {
+    fn from(e: opendal::Error) -> Self {
+        ObjectStoreError::OpenDALError(Box::new(e))
+    }
+}
+
+impl ObjectStoreError {
+    pub fn is_no_record_found(&self) -> bool {
+        match self {
+            ObjectStoreError::NoRecordFound(..) => true,
+            ObjectStoreError::Context { source, .. } => source.is_no_record_found(),
+            _ => false,
+        }
+    }
+}
+
 pub trait ErrCtx<T> {
     fn ctx(self, func: &'static str) -> Result<T, ObjectStoreError>;
 }
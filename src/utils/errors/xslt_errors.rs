@@ -15,6 +15,9 @@ pub enum XsltError {
     #[error("Can not transform xml file: {0}")]
     XsltTransformError(String),
 
+    #[error("Checksum mismatch in cached xslt: {0}")]
+    ChecksumMismatch(String),
+
     #[error("LibXml parse error:  {0}")]
     LibXmlParseError(#[from] XmlParseError),
 
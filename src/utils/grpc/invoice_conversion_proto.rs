@@ -0,0 +1,3 @@
+//! Messages and service of proto/invoice_conversion.proto, generated by build.rs.
+
+tonic::include_proto!("invoice_conversion");
@@ -0,0 +1,276 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::common::target_types_and_formats::FilenameInZipMode;
+use crate::utils::convert_invoices::incoming_invoice_page::IncomingInvoicePage;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    ConvertedDocument, InvoiceConversionResult, InvoiceItemForConversion, InvoiceItemStatus,
+    InvoicesForConversion, convert_invoices, convert_invoices_streamed,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::grpc::invoice_conversion_proto as pb;
+use crate::utils::grpc::invoice_conversion_proto::convert_invoices_chunk::Kind;
+use crate::utils::grpc::invoice_conversion_proto::invoice_conversion_server::InvoiceConversion;
+use crate::utils::scheduler::fair_scheduler::ConversionPermit;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::bytes::Bytes;
+use tokio_util::sync::CancellationToken;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Response, Status};
+
+/// Stream messages buffered between the worker and the client
+const STREAM_CHANNEL_CAPACITY: usize = 4;
+
+/// gRPC front of `convert_invoices`, same rules as the REST handlers:
+/// wait for a `FairScheduler` permit of the client, cancel the work when the
+/// client goes away, `request_fully_completed = false` for partial archives.
+pub struct InvoiceConversionService {
+    state: SharedState,
+}
+
+impl InvoiceConversionService {
+    pub fn new(state: SharedState) -> Self {
+        Self { state }
+    }
+
+    /// This client's turn at the scheduler, ServerBusyError when the queue is full or times out
+    async fn acquire_permit(
+        &self,
+        conversion_request: &InvoicesForConversion,
+    ) -> Result<ConversionPermit, InvConvError> {
+        self.state
+            .scheduler
+            .acquire(conversion_request.client_id.as_deref())
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl InvoiceConversion for InvoiceConversionService {
+    async fn convert_invoices(
+        &self,
+        request: Request<pb::ConvertInvoicesRequest>,
+    ) -> Result<Response<pb::ConvertInvoicesResponse>, Status> {
+        let conversion_request: InvoicesForConversion = request.into_inner().try_into()?;
+        let permit = self.acquire_permit(&conversion_request).await?;
+
+        // tonic drops this future when the client disconnects
+        let token = CancellationToken::new();
+        let _cancel_on_drop = token.clone().drop_guard();
+
+        let result =
+            convert_invoices(self.state.clone(), conversion_request, permit, token).await?;
+
+        let fully_completed = result.request_fully_completed;
+        let mut response = Response::new(pb::ConvertInvoicesResponse::from(result));
+        response.metadata_mut().insert(
+            "x-request-fully-completed",
+            MetadataValue::from_static(if fully_completed { "true" } else { "false" }),
+        );
+        Ok(response)
+    }
+
+    type ConvertInvoicesStreamStream = ReceiverStream<Result<pb::ConvertInvoicesChunk, Status>>;
+
+    /// Archive bytes and item statuses while converting, then a summary.
+    /// A failure after the first message ends the stream with its status.
+    async fn convert_invoices_stream(
+        &self,
+        request: Request<pb::ConvertInvoicesRequest>,
+    ) -> Result<Response<Self::ConvertInvoicesStreamStream>, Status> {
+        let conversion_request: InvoicesForConversion = request.into_inner().try_into()?;
+        let permit = self.acquire_permit(&conversion_request).await?;
+
+        let token = CancellationToken::new();
+        let cancel_on_drop = token.clone().drop_guard();
+
+        let (chunk_tx, mut chunk_rx) = mpsc::channel::<Bytes>(STREAM_CHANNEL_CAPACITY);
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel::<InvoiceItemStatus>();
+        let (out_tx, out_rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+
+        let conversion = tokio::spawn(convert_invoices_streamed(
+            self.state.clone(),
+            conversion_request,
+            permit,
+            token,
+            chunk_tx,
+            Some(progress_tx),
+        ));
+
+        tokio::spawn(async move {
+            // Returning early (client gone) cancels the conversion
+            let _cancel_on_drop = cancel_on_drop;
+            let mut chunks_open = true;
+            let mut progress_open = true;
+            while chunks_open || progress_open {
+                let kind = tokio::select! {
+                    biased;
+                    status = progress_rx.recv(), if progress_open => match status {
+                        Some(status) => Kind::Progress(status.into()),
+                        None => {
+                            progress_open = false;
+                            continue;
+                        }
+                    },
+                    chunk = chunk_rx.recv(), if chunks_open => match chunk {
+                        Some(chunk) => Kind::Data(chunk),
+                        None => {
+                            chunks_open = false;
+                            continue;
+                        }
+                    },
+                };
+                let message = pb::ConvertInvoicesChunk { kind: Some(kind) };
+                if out_tx.send(Ok(message)).await.is_err() {
+                    return;
+                }
+            }
+
+            let last = match conversion.await {
+                Ok(Ok(result)) => Ok(pb::ConvertInvoicesChunk {
+                    kind: Some(Kind::Summary(result.into())),
+                }),
+                Ok(Err(e)) => Err(Status::from(e)),
+                Err(e) => Err(Status::from(InvConvError::TaskJoinError(e.to_string()))),
+            };
+            let _ = out_tx.send(last).await;
+        });
+
+        Ok(Response::new(ReceiverStream::new(out_rx)))
+    }
+}
+
+/// Error code of `InvConvError` travels in the `x-error-code` metadata.
+impl From<InvConvError> for Status {
+    fn from(error: InvConvError) -> Self {
+        let code = match root_error(&error) {
+            InvConvError::ServerBusyError(_) => Code::ResourceExhausted,
+            InvConvError::ClientDisconnectedError(_) => Code::Cancelled,
+            InvConvError::UblNotFoundInObjectStore(_) => Code::NotFound,
+            _ => Code::Internal,
+        };
+        let mut status = Status::new(code, error.to_string());
+        status
+            .metadata_mut()
+            .insert("x-error-code", MetadataValue::from(error.error_code()));
+        status
+    }
+}
+
+fn root_error(error: &InvConvError) -> &InvConvError {
+    match error {
+        InvConvError::Context { source, .. } => root_error(source),
+        e => e,
+    }
+}
+
+impl TryFrom<pb::ConvertInvoicesRequest> for InvoicesForConversion {
+    type Error = Status;
+
+    fn try_from(req: pb::ConvertInvoicesRequest) -> Result<Self, Self::Error> {
+        let filename_in_zip = match req.filename_in_zip.trim() {
+            "" => FilenameInZipMode::default(),
+            mode => parse_name("filename_in_zip", mode).map_err(Status::invalid_argument)?,
+        };
+        let xslt_engine = match req.xslt_engine.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(engine) => Some(
+                engine
+                    .parse()
+                    .map_err(|e: String| Status::invalid_argument(format!("xslt_engine: {e}")))?,
+            ),
+        };
+        Ok(InvoicesForConversion {
+            target_type: parse_name("target_type", &req.target_type)
+                .map_err(Status::invalid_argument)?,
+            target_compression_type: parse_name(
+                "target_compression_type",
+                &req.target_compression_type,
+            )
+            .map_err(Status::invalid_argument)?,
+            year: req.year,
+            filename_in_zip,
+            request_id: req.request_id,
+            client_id: req.client_id,
+            xslt_engine,
+            continue_on_error: req.continue_on_error,
+            desanitize: req.desanitize,
+            incoming_invoice: req.incoming_invoice.map(|page| IncomingInvoicePage {
+                source_vkntckn: page.source_vkntckn,
+                after_this: page.after_this,
+                page_size: page.page_size,
+                db_name: page.db_name,
+            }),
+            items: req
+                .items
+                .into_iter()
+                .map(|item| InvoiceItemForConversion {
+                    object_id: item.object_id,
+                    sira_no: item.sira_no,
+                    invoice_no: item.invoice_no,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Enum fields use the same names as the REST JSON request.
+fn parse_name<T: DeserializeOwned>(field: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.trim().to_string()))
+        .map_err(|e| format!("{field}: {e}"))
+}
+
+impl From<InvoiceConversionResult> for pb::ConvertInvoicesResponse {
+    fn from(result: InvoiceConversionResult) -> Self {
+        pb::ConvertInvoicesResponse {
+            data: result.data,
+            docs_count: result.docs_count,
+            size: result.size,
+            last_processed_sira_no: result.last_processed_sira_no,
+            request_fully_completed: result.request_fully_completed,
+            file_extension: result.compression_type.file_extension().to_string(),
+            content_type: result.compression_type.content_type().to_string(),
+            documents: result.documents.into_iter().map(Into::into).collect(),
+            item_statuses: result.item_statuses.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<InvoiceConversionResult> for pb::ConversionSummary {
+    fn from(result: InvoiceConversionResult) -> Self {
+        pb::ConversionSummary {
+            docs_count: result.docs_count,
+            size: result.size,
+            last_processed_sira_no: result.last_processed_sira_no,
+            request_fully_completed: result.request_fully_completed,
+            file_extension: result.compression_type.file_extension().to_string(),
+            content_type: result.compression_type.content_type().to_string(),
+            documents: result.documents.into_iter().map(Into::into).collect(),
+            item_statuses: result.item_statuses.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ConvertedDocument> for pb::ConvertedDocument {
+    fn from(doc: ConvertedDocument) -> Self {
+        pb::ConvertedDocument {
+            object_id: doc.object_id,
+            sira_no: doc.sira_no,
+            filename: doc.filename,
+            size: doc.size,
+            xslt_key: doc.xslt_key,
+            xslt_engine: doc.xslt_engine.map(|k| k.to_string()),
+        }
+    }
+}
+
+impl From<InvoiceItemStatus> for pb::InvoiceItemStatus {
+    fn from(status: InvoiceItemStatus) -> Self {
+        pb::InvoiceItemStatus {
+            object_id: status.object_id,
+            sira_no: status.sira_no,
+            error_code: status.error_code,
+            error_msg: status.error_msg,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::invoice_conversion_proto as pb;
+use crate::utils::common::target_types_and_formats::{TargetCompressionType, TargetType};
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionResult, InvoiceItemStatus, InvoicesForConversion,
+};
+use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
+use tonic::{Code, Status};
+
+fn request() -> pb::ConvertInvoicesRequest {
+    pb::ConvertInvoicesRequest {
+        target_type: "Pdf".to_string(),
+        target_compression_type: "gzip".to_string(),
+        year: "2025".to_string(),
+        filename_in_zip: String::new(),
+        request_id: "req-1".to_string(),
+        client_id: None,
+        xslt_engine: Some("libxslt".to_string()),
+        continue_on_error: true,
+        desanitize: false,
+        incoming_invoice: None,
+        items: vec![pb::InvoiceItem {
+            object_id: "obj-1".to_string(),
+            sira_no: Some(7),
+            invoice_no: None,
+        }],
+    }
+}
+
+#[test]
+fn request_uses_rest_names() {
+    let converted: InvoicesForConversion = request().try_into().expect("valid request");
+    assert_eq!(converted.target_type, TargetType::Pdf);
+    assert_eq!(
+        converted.target_compression_type,
+        TargetCompressionType::Gzip
+    );
+    assert_eq!(converted.xslt_engine, Some(XsltEngineKind::LibXslt));
+    assert!(converted.continue_on_error);
+    assert_eq!(converted.items[0].sira_no, Some(7));
+}
+
+#[test]
+fn invalid_request_is_invalid_argument() {
+    let mut req = request();
+    req.target_type = "Docx".to_string();
+    let err = InvoicesForConversion::try_from(req).unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert!(err.message().starts_with("target_type"));
+}
+
+#[test]
+fn errors_map_to_grpc_codes() {
+    let busy = Status::from(InvConvError::ServerBusyError("busy".to_string()));
+    assert_eq!(busy.code(), Code::ResourceExhausted);
+    assert_eq!(busy.metadata().get("x-error-code").unwrap(), "1002");
+
+    let wrapped: Result<(), InvConvError> =
+        Err(InvConvError::ClientDisconnectedError("gone".to_string())).ctx("test");
+    assert_eq!(Status::from(wrapped.unwrap_err()).code(), Code::Cancelled);
+}
+
+#[test]
+fn partial_result_is_flagged() {
+    let result = InvoiceConversionResult {
+        docs_count: 1,
+        request_fully_completed: false,
+        compression_type: TargetCompressionType::Zip,
+        item_statuses: vec![InvoiceItemStatus {
+            object_id: "obj-1".to_string(),
+            sira_no: Some(7),
+            error_code: 0,
+            error_msg: "OK".to_string(),
+        }],
+        ..Default::default()
+    };
+    let response = pb::ConvertInvoicesResponse::from(result);
+    assert!(!response.request_fully_completed);
+    assert_eq!(response.content_type, "application/zip");
+    assert_eq!(response.item_statuses.len(), 1);
+}
@@ -0,0 +1,5 @@
+pub mod invoice_conversion_proto;
+pub mod invoice_conversion_service;
+
+#[cfg(test)]
+mod invoice_conversion_service_tests;
@@ -3,22 +3,25 @@ use crate::utils::database_manager::init_database;
 use crate::utils::errors::db_errors::{DbError, ErrCtx as DbErrCtx};
 use tiberius::Query;
 
+/// Next `page_size` records of `source_vkntckn` with SIRA_NO > `after_this`, in SIRA_NO order.
 pub async fn get_incoming_invoice_recs_afterthis(
     pool: &init_database::ConnectionPool,
     dbname: &str,
     source_vkntckn: &str,
     after_this: i64,
+    page_size: i64,
 ) -> Result<Vec<IncomingInvoiceRec>, DbError> {
     let sql_sentence = format!(
-        "SELECT TOP 100 UUID, INVOICE_ID, RECEIVER_CONTACT, SIRA_NO, PATH
+        "SELECT TOP (@P3) UUID, INVOICE_ID, RECEIVER_CONTACT, SIRA_NO, PATH
          FROM {}.dbo.INCOMING_INVOICE
          WHERE RECEIVER_CONTACT = @P1 AND SIRA_NO > @P2
          ORDER BY SIRA_NO ASC",
         dbname // e.g. "uut_24_6" or "uut_25_1"
     );
     let mut query = Query::new(sql_sentence);
-    query.bind(source_vkntckn.clone());
+    query.bind(source_vkntckn);
     query.bind(after_this);
+    query.bind(page_size);
 
     let mut conn = pool
         .get()
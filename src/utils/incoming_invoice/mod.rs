@@ -1,2 +1,6 @@
 pub mod get_incoming_invoice_recs_afterthis;
 pub mod incoming_invoice_rec;
+pub mod tenant_databases;
+
+#[cfg(test)]
+mod tenant_databases_tests;
@@ -0,0 +1,90 @@
+use crate::utils::database_manager::pool_registry::{INCOMING_INVOICE_POOL, PoolKey};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+/// Tenant databases (`uut_YY_N`) holding INCOMING_INVOICE, looked up by the receiver VKN/TCKN.
+/// Queried as `{db}.dbo.INCOMING_INVOICE` on the pool `incoming_invoice_pool_key` selects.
+/// `[tenants]` of the config, the names are checked by `AppConfig::validate`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantDatabases {
+    /// Receivers without an entry, see `AppConfig::tenant_databases`
+    pub default_db: String,
+    /// `[tenants.databases]`, or `"1950031086=uut_25_1,..."` from TENANT_DBS
+    #[serde(rename = "databases", deserialize_with = "tenant_table")]
+    pub by_vkntckn: HashMap<String, String>,
+}
+
+impl TenantDatabases {
+    /// The table entry of `source_vkntckn`, else the default. A requested name only
+    /// confirms it: a client reads its own receiver's database, never another tenant's.
+    /// `None` when the requested name is a different database.
+    pub fn resolve(&self, requested: Option<&str>, source_vkntckn: &str) -> Option<String> {
+        let db_name = self
+            .by_vkntckn
+            .get(source_vkntckn)
+            .unwrap_or(&self.default_db);
+        match requested {
+            Some(name) if name != db_name => None,
+            _ => Some(db_name.clone()),
+        }
+    }
+}
+
+/// `vkntckn=uut_YY_N` pairs separated by commas. Entries without `=` are errors,
+/// the database names are not checked here.
+pub fn parse_tenant_table(value: &str) -> Result<HashMap<String, String>, String> {
+    let mut table = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some((vkntckn, db_name)) if !vkntckn.trim().is_empty() => {
+                table.insert(vkntckn.trim().to_string(), db_name.trim().to_string());
+            }
+            _ => return Err("expected vkntckn=uut_YY_N pairs".to_string()),
+        }
+    }
+    Ok(table)
+}
+
+/// A table of the config file or the `parse_tenant_table` text of TENANT_DBS
+fn tenant_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::String(pairs) => parse_tenant_table(&pairs).map_err(D::Error::custom),
+        table => HashMap::deserialize(table).map_err(D::Error::custom),
+    }
+}
+
+/// `uut_` + two digit year + `_` + number, e.g. uut_24_6
+pub fn is_valid_db_name(name: &str) -> bool {
+    let Some((year, number)) = name
+        .strip_prefix("uut_")
+        .and_then(|rest| rest.split_once('_'))
+    else {
+        return false;
+    };
+    year.len() == 2
+        && year.bytes().all(|b| b.is_ascii_digit())
+        && !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// 2000 + YY of a uut_YY_N name
+pub fn db_name_year(name: &str) -> Option<u16> {
+    if !is_valid_db_name(name) {
+        return None;
+    }
+    let year: u16 = name.get(4..6)?.parse().ok()?;
+    Some(2000 + year)
+}
+
+/// Pool of the INCOMING_INVOICE queries: by receiver and the year of its tenant database.
+pub fn incoming_invoice_pool_key<'a>(db_name: &str, source_vkntckn: &'a str) -> PoolKey<'a> {
+    let key = PoolKey::new(INCOMING_INVOICE_POOL).with_tenant(source_vkntckn);
+    match db_name_year(db_name) {
+        Some(year) => key.with_year(year),
+        None => key,
+    }
+}
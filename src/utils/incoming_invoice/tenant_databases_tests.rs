@@ -0,0 +1,38 @@
+use super::tenant_databases::{TenantDatabases, is_valid_db_name, parse_tenant_table};
+
+#[test]
+fn db_names_must_be_uut_yy_n() {
+    assert!(is_valid_db_name("uut_24_6"));
+    assert!(is_valid_db_name("uut_25_12"));
+    assert!(!is_valid_db_name("uut_2024_6"));
+    assert!(!is_valid_db_name("uut_24_"));
+    assert!(!is_valid_db_name("uut_24_6; DROP TABLE x"));
+    assert!(!is_valid_db_name("master"));
+}
+
+#[test]
+fn requested_name_then_table_then_default() {
+    let tenants = TenantDatabases {
+        default_db: "uut_24_6".to_string(),
+        by_vkntckn: parse_tenant_table("1950031086=uut_25_1, ,2=uut_23_1").unwrap(),
+    };
+    assert_eq!(tenants.by_vkntckn.len(), 2);
+    assert!(parse_tenant_table("1950031086=uut_25_1,uut_24_6").is_err());
+
+    assert_eq!(
+        tenants.resolve(Some("uut_25_1"), "1950031086").as_deref(),
+        Some("uut_25_1")
+    );
+    // Another tenant's database, even a well formed one
+    assert_eq!(tenants.resolve(Some("uut_23_4"), "1950031086"), None);
+    assert_eq!(tenants.resolve(Some("uut_25_1"), "9999999999"), None);
+    assert_eq!(
+        tenants.resolve(None, "1950031086").as_deref(),
+        Some("uut_25_1")
+    );
+    assert_eq!(
+        tenants.resolve(None, "9999999999").as_deref(),
+        Some("uut_24_6")
+    );
+    assert_eq!(tenants.resolve(Some("uut_24_6 --"), "1950031086"), None);
+}
@@ -0,0 +1,485 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::common::target_types_and_formats::TargetCompressionType;
+use crate::utils::config::app_config::secs;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionResult, InvoiceItemStatus, InvoicesForConversion, convert_invoices_streamed,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::errors::job_errors::JobError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio_util::bytes::Bytes;
+use tokio_util::sync::CancellationToken;
+
+/// Archive chunks buffered between the worker and the result file
+const JOB_CHANNEL_CAPACITY: usize = 4;
+
+/// Where finished job archives are kept and for how long.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    pub dir: String,
+    /// Finished jobs (status and archive) are dropped this long after they end
+    #[serde(rename = "result_ttl_secs", with = "secs")]
+    pub result_ttl: Duration,
+    /// Jobs waiting for a scheduler permit, more are refused with `ServerBusyError`
+    pub max_queued_jobs: usize,
+}
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            dir: "/tmp/utils_server_jobs".to_string(),
+            result_ttl: Duration::from_secs(60 * 60),
+            max_queued_jobs: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a `FairScheduler` permit
+    Queued,
+    Running,
+    /// Archive ready, see `request_fully_completed` for partial results
+    Completed,
+    Failed,
+    Cancelled,
+}
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Completed | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Queued => write!(f, "queued"),
+            Self::Running => write!(f, "running"),
+            Self::Completed => write!(f, "completed"),
+            Self::Failed => write!(f, "failed"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Progress of a job, served by `GET /api/v1/jobs/{id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub request_id: String,
+    pub state: JobState,
+    /// None for `incoming_invoice` jobs, their items are only known once the page is read
+    pub requested_count: Option<usize>,
+    /// Items converted so far
+    pub docs_done: usize,
+    pub failed_count: usize,
+    pub last_processed_sira_no: Option<u64>,
+    /// Failed items so far (`continue_on_error`)
+    pub errors: Vec<InvoiceItemStatus>,
+    pub request_fully_completed: bool,
+    /// Why the whole job failed or stopped
+    pub error_code: Option<i32>,
+    pub error_msg: Option<String>,
+    /// Size of the result archive
+    pub result_size: Option<u64>,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+    /// Status and archive are dropped after this
+    pub expires_at: Option<String>,
+}
+
+/// Finished archive of a job, for the download handler.
+#[derive(Debug, Clone)]
+pub struct JobResult {
+    pub path: PathBuf,
+    pub request_id: String,
+    pub compression_type: TargetCompressionType,
+    pub request_fully_completed: bool,
+}
+
+struct JobEntry {
+    status: JobStatus,
+    token: CancellationToken,
+    compression_type: TargetCompressionType,
+    result_path: Option<PathBuf>,
+    expires_at: Option<Instant>,
+}
+
+/// Conversion batches running detached from the client connection.
+///
+/// Jobs live in memory; their archives are written to `config.dir` while
+/// converting and removed together with the job when it expires.
+pub struct JobManager {
+    config: JobsConfig,
+    jobs: Mutex<HashMap<String, JobEntry>>,
+}
+
+impl JobManager {
+    /// Archives left over from a previous run have no job anymore and are removed,
+    /// other files in `config.dir` are left alone.
+    pub fn new(config: JobsConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        for entry in fs::read_dir(&config.dir)?.flatten() {
+            if is_job_archive(&entry.file_name().to_string_lossy()) && entry.path().is_file() {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(Self {
+            config,
+            jobs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn config(&self) -> &JobsConfig {
+        &self.config
+    }
+
+    /// Queue the conversion and return at once, the job runs on its own task.
+    /// `ServerBusyError` when `max_queued_jobs` jobs are already waiting.
+    pub fn submit(
+        self: &Arc<Self>,
+        state: SharedState,
+        request: InvoicesForConversion,
+    ) -> Result<JobStatus, InvConvError> {
+        let (status, token) = self.register(&request)?;
+        tokio::spawn(Arc::clone(self).run(state, status.job_id.clone(), request, token));
+        Ok(status)
+    }
+
+    pub fn status(&self, job_id: &str) -> Result<JobStatus, JobError> {
+        self.lock()
+            .get(job_id)
+            .map(|job| job.status.clone())
+            .ok_or_else(|| JobError::NotFound(job_id.to_string()))
+    }
+
+    pub fn result(&self, job_id: &str) -> Result<JobResult, JobError> {
+        let jobs = self.lock();
+        let job = jobs
+            .get(job_id)
+            .ok_or_else(|| JobError::NotFound(job_id.to_string()))?;
+        if !job.status.state.is_finished() {
+            return Err(JobError::NotFinished {
+                job_id: job_id.to_string(),
+                state: job.status.state.to_string(),
+            });
+        }
+        match (&job.result_path, job.status.state) {
+            (Some(path), JobState::Completed) => Ok(JobResult {
+                path: path.clone(),
+                request_id: job.status.request_id.clone(),
+                compression_type: job.compression_type,
+                request_fully_completed: job.status.request_fully_completed,
+            }),
+            _ => Err(JobError::NoResult(job_id.to_string())),
+        }
+    }
+
+    /// Cancel a queued or running job; a finished job is dropped with its archive.
+    pub fn cancel(&self, job_id: &str) -> Result<JobStatus, JobError> {
+        let mut jobs = self.lock();
+        let job = jobs
+            .get_mut(job_id)
+            .ok_or_else(|| JobError::NotFound(job_id.to_string()))?;
+        // A finished job keeps its archive until it expires
+        if job.status.state.is_finished() {
+            return Err(JobError::AlreadyFinished {
+                job_id: job_id.to_string(),
+                state: job.status.state.to_string(),
+            });
+        }
+        job.token.cancel();
+        job.status.state = JobState::Cancelled;
+        Ok(job.status.clone())
+    }
+
+    /// Drop finished jobs whose TTL passed. Returns the removed count.
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut jobs = self.lock();
+        let expired: Vec<String> = jobs
+            .iter()
+            .filter(|(_, job)| job.expires_at.is_some_and(|at| at <= now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            if let Some(job) = jobs.remove(id) {
+                remove_result(&job);
+            }
+        }
+        expired.len()
+    }
+
+    /// Run `purge_expired` every `every` on a background task.
+    pub fn start_expiry_sweeper(self: &Arc<Self>, every: Duration) {
+        let jobs = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let removed = jobs.purge_expired();
+                if removed > 0 {
+                    println!("🧹 {removed} expired conversion jobs removed");
+                }
+            }
+        });
+    }
+
+    pub(crate) fn register(
+        &self,
+        request: &InvoicesForConversion,
+    ) -> Result<(JobStatus, CancellationToken), InvConvError> {
+        let mut jobs = self.lock();
+        let queued = jobs
+            .values()
+            .filter(|job| job.status.state == JobState::Queued)
+            .count();
+        if queued >= self.config.max_queued_jobs {
+            return Err(InvConvError::ServerBusyError(format!(
+                "{queued} jobs are waiting already. Please retry later."
+            )));
+        }
+
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let status = JobStatus {
+            job_id: job_id.clone(),
+            request_id: request.request_id.clone(),
+            state: JobState::Queued,
+            requested_count: request
+                .incoming_invoice
+                .is_none()
+                .then_some(request.items.len()),
+            docs_done: 0,
+            failed_count: 0,
+            last_processed_sira_no: None,
+            errors: Vec::new(),
+            request_fully_completed: false,
+            error_code: None,
+            error_msg: None,
+            result_size: None,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: None,
+            expires_at: None,
+        };
+        let token = CancellationToken::new();
+        jobs.insert(
+            job_id,
+            JobEntry {
+                status: status.clone(),
+                token: token.clone(),
+                compression_type: request.target_compression_type,
+                result_path: None,
+                expires_at: None,
+            },
+        );
+        Ok((status, token))
+    }
+
+    pub(crate) fn record_progress(&self, job_id: &str, item: InvoiceItemStatus) {
+        self.update(job_id, |status| {
+            if item.error_code == 0 {
+                status.docs_done += 1;
+                if item.sira_no.is_some() {
+                    status.last_processed_sira_no = item.sira_no;
+                }
+            } else {
+                status.failed_count += 1;
+                status.errors.push(item);
+            }
+        });
+    }
+
+    /// Final state of the job. A failed or cancelled job keeps no archive.
+    pub(crate) fn finish(
+        &self,
+        job_id: &str,
+        outcome: Result<InvoiceConversionResult, InvConvError>,
+        result_path: Option<PathBuf>,
+    ) {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(job_id) else {
+            // Dropped meanwhile, only the file is left
+            if let Some(path) = result_path {
+                let _ = fs::remove_file(path);
+            }
+            return;
+        };
+        let now = chrono::Utc::now();
+        let ttl = chrono::Duration::from_std(self.config.result_ttl).unwrap_or_default();
+        job.status.finished_at = Some(now.to_rfc3339());
+        job.status.expires_at = Some((now + ttl).to_rfc3339());
+        job.expires_at = Some(Instant::now() + self.config.result_ttl);
+        job.result_path = result_path;
+
+        // DELETE wins over a conversion that finished meanwhile
+        let cancelled = job.status.state == JobState::Cancelled;
+        let outcome = match outcome {
+            Ok(_) if cancelled => Err(InvConvError::ClientDisconnectedError(
+                "Job cancelled".to_string(),
+            )),
+            outcome => outcome,
+        };
+        match outcome {
+            Ok(result) => {
+                job.status.state = JobState::Completed;
+                job.status.request_fully_completed = result.request_fully_completed;
+                job.status.docs_done = result.documents.len();
+                job.status.last_processed_sira_no = result.last_processed_sira_no;
+                job.status.result_size = job
+                    .result_path
+                    .as_ref()
+                    .and_then(|p| fs::metadata(p).ok())
+                    .map(|m| m.len());
+            }
+            Err(e) => {
+                job.status.state = if cancelled {
+                    JobState::Cancelled
+                } else {
+                    JobState::Failed
+                };
+                job.status.error_code = Some(e.error_code());
+                job.status.error_msg = Some(e.to_string());
+                remove_result(job);
+                job.result_path = None;
+            }
+        }
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        state: SharedState,
+        job_id: String,
+        request: InvoicesForConversion,
+        token: CancellationToken,
+    ) {
+        // Jobs wait for their client's turn without the REST call's queue bound and timeout
+        let permit = tokio::select! {
+            permit = state.scheduler.acquire_queued(request.client_id.as_deref()) => match permit {
+                Ok(permit) => permit,
+                Err(e) => {
+                    self.finish(&job_id, Err(e), None);
+                    return;
+                }
+            },
+            _ = token.cancelled() => {
+                let cancelled = InvConvError::ClientDisconnectedError("Job cancelled".to_string());
+                self.finish(&job_id, Err(cancelled), None);
+                return;
+            }
+        };
+        self.update(&job_id, |status| {
+            if status.state == JobState::Queued {
+                status.state = JobState::Running;
+            }
+        });
+
+        let path = PathBuf::from(&self.config.dir).join(format!(
+            "{job_id}.{}",
+            request.target_compression_type.file_extension()
+        ));
+        let mut file = match tokio::fs::File::create(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                let err = InvConvError::ZipFileCreationError {
+                    request_id: request.request_id.clone(),
+                    source: e,
+                };
+                self.finish(&job_id, Err(err), None);
+                return;
+            }
+        };
+
+        let (chunk_tx, mut chunk_rx) = mpsc::channel::<Bytes>(JOB_CHANNEL_CAPACITY);
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel::<InvoiceItemStatus>();
+        let conversion = tokio::spawn(convert_invoices_streamed(
+            state,
+            request,
+            permit,
+            token.clone(),
+            chunk_tx,
+            Some(progress_tx),
+        ));
+
+        let mut write_error: Option<io::Error> = None;
+        let mut chunks_open = true;
+        let mut progress_open = true;
+        while chunks_open || progress_open {
+            tokio::select! {
+                biased;
+                item = progress_rx.recv(), if progress_open => match item {
+                    Some(item) => self.record_progress(&job_id, item),
+                    None => progress_open = false,
+                },
+                chunk = chunk_rx.recv(), if chunks_open => match chunk {
+                    Some(chunk) if write_error.is_none() => {
+                        if let Err(e) = file.write_all(&chunk).await {
+                            // Stop the conversion, keep draining so the worker is not blocked
+                            write_error = Some(e);
+                            token.cancel();
+                        }
+                    }
+                    Some(_) => {}
+                    None => chunks_open = false,
+                },
+            }
+        }
+        if write_error.is_none()
+            && let Err(e) = file.flush().await
+        {
+            write_error = Some(e);
+        }
+
+        let outcome = match (conversion.await, write_error) {
+            (_, Some(e)) => Err(InvConvError::ZipIOError {
+                sira_no: String::new(),
+                source: e,
+            }),
+            (Ok(outcome), None) => outcome,
+            (Err(e), None) => Err(InvConvError::TaskJoinError(e.to_string())),
+        };
+        self.finish(&job_id, outcome, Some(path));
+    }
+
+    fn update(&self, job_id: &str, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(job) = self.lock().get_mut(job_id) {
+            f(&mut job.status);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, JobEntry>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// `{job_id}.{extension}`, the names `run` writes
+fn is_job_archive(file_name: &str) -> bool {
+    let Some((job_id, extension)) = file_name.split_once('.') else {
+        return false;
+    };
+    uuid::Uuid::parse_str(job_id).is_ok()
+        && [
+            TargetCompressionType::Zip,
+            TargetCompressionType::Tzip,
+            TargetCompressionType::Gzip,
+        ]
+        .iter()
+        .any(|t| t.file_extension() == extension)
+}
+
+fn remove_result(job: &JobEntry) {
+    if let Some(path) = &job.result_path {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,212 @@
+use super::conversion_jobs::{JobManager, JobState, JobsConfig};
+use crate::utils::common::target_types_and_formats::{
+    FilenameInZipMode, TargetCompressionType, TargetType,
+};
+use crate::utils::convert_invoices::incoming_invoice_page::IncomingInvoicePage;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionResult, InvoiceItemForConversion, InvoiceItemStatus, InvoicesForConversion,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::errors::job_errors::JobError;
+use std::time::Duration;
+
+fn manager(dir: &tempfile::TempDir, ttl: Duration) -> JobManager {
+    JobManager::new(JobsConfig {
+        dir: dir.path().to_string_lossy().to_string(),
+        result_ttl: ttl,
+        ..Default::default()
+    })
+    .expect("job manager")
+}
+
+fn request() -> InvoicesForConversion {
+    InvoicesForConversion {
+        target_type: TargetType::Html,
+        target_compression_type: TargetCompressionType::Zip,
+        year: "2025".to_string(),
+        filename_in_zip: FilenameInZipMode::default(),
+        request_id: "req-1".to_string(),
+        client_id: None,
+        xslt_engine: None,
+        continue_on_error: true,
+        desanitize: false,
+        incoming_invoice: None,
+        items: (1..=3)
+            .map(|n| InvoiceItemForConversion {
+                object_id: format!("obj-{n}"),
+                sira_no: Some(n),
+                invoice_no: None,
+            })
+            .collect(),
+    }
+}
+
+fn status(sira_no: u64, error_code: i32) -> InvoiceItemStatus {
+    InvoiceItemStatus {
+        object_id: format!("obj-{sira_no}"),
+        sira_no: Some(sira_no),
+        error_code,
+        error_msg: String::new(),
+    }
+}
+
+#[test]
+fn progress_and_result_of_a_completed_job() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = manager(&dir, Duration::from_secs(60));
+    let (queued, _token) = jobs.register(&request()).unwrap();
+    let id = queued.job_id.clone();
+    assert_eq!(queued.state, JobState::Queued);
+    assert_eq!(queued.requested_count, Some(3));
+    assert!(matches!(
+        jobs.result(&id),
+        Err(JobError::NotFinished { .. })
+    ));
+
+    jobs.record_progress(&id, status(1, 0));
+    jobs.record_progress(&id, status(2, 2003));
+    let running = jobs.status(&id).unwrap();
+    assert_eq!(running.docs_done, 1);
+    assert_eq!(running.failed_count, 1);
+    assert_eq!(running.last_processed_sira_no, Some(1));
+    assert_eq!(running.errors[0].error_code, 2003);
+
+    let path = dir.path().join(format!("{id}.zip"));
+    std::fs::write(&path, b"archive").unwrap();
+    jobs.finish(
+        &id,
+        Ok(InvoiceConversionResult {
+            // The skipped item 2 counts as processed for the next page
+            last_processed_sira_no: Some(2),
+            request_fully_completed: true,
+            ..Default::default()
+        }),
+        Some(path.clone()),
+    );
+    let done = jobs.status(&id).unwrap();
+    assert_eq!(done.state, JobState::Completed);
+    assert_eq!(done.last_processed_sira_no, Some(2));
+    assert_eq!(done.result_size, Some(7));
+    assert!(done.expires_at.is_some());
+    assert_eq!(jobs.result(&id).unwrap().path, path);
+
+    // Cancelling does not take the finished archive away
+    assert!(matches!(
+        jobs.cancel(&id),
+        Err(JobError::AlreadyFinished { .. })
+    ));
+    assert!(path.exists());
+    assert_eq!(jobs.status(&id).unwrap().state, JobState::Completed);
+}
+
+#[test]
+fn incoming_invoice_jobs_have_no_requested_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = manager(&dir, Duration::from_secs(60));
+    let mut request = request();
+    request.items.clear();
+    request.incoming_invoice = Some(IncomingInvoicePage {
+        source_vkntckn: "1950031086".to_string(),
+        after_this: 0,
+        page_size: None,
+        db_name: None,
+    });
+    let (queued, _token) = jobs.register(&request).unwrap();
+    assert_eq!(queued.requested_count, None);
+}
+
+#[test]
+fn cancelled_job_keeps_no_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = manager(&dir, Duration::from_secs(60));
+    let (queued, token) = jobs.register(&request()).unwrap();
+    let id = queued.job_id;
+
+    assert_eq!(jobs.cancel(&id).unwrap().state, JobState::Cancelled);
+    assert!(token.is_cancelled());
+
+    // The conversion may still finish fine, the cancel wins
+    let path = dir.path().join(format!("{id}.zip"));
+    std::fs::write(&path, b"archive").unwrap();
+    jobs.finish(
+        &id,
+        Ok(InvoiceConversionResult::default()),
+        Some(path.clone()),
+    );
+    assert_eq!(jobs.status(&id).unwrap().state, JobState::Cancelled);
+    assert!(!path.exists());
+    assert!(matches!(jobs.result(&id), Err(JobError::NoResult(_))));
+}
+
+#[test]
+fn failed_job_reports_the_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = manager(&dir, Duration::from_secs(60));
+    let (queued, _token) = jobs.register(&request()).unwrap();
+    jobs.finish(
+        &queued.job_id,
+        Err(InvConvError::TaskJoinError("boom".to_string())),
+        None,
+    );
+    let failed = jobs.status(&queued.job_id).unwrap();
+    assert_eq!(failed.state, JobState::Failed);
+    assert_eq!(failed.error_code, Some(1003));
+}
+
+#[test]
+fn expired_jobs_are_purged_with_their_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = manager(&dir, Duration::ZERO);
+    let (finished, _) = jobs.register(&request()).unwrap();
+    let (running, _) = jobs.register(&request()).unwrap();
+    let path = dir.path().join(format!("{}.zip", finished.job_id));
+    std::fs::write(&path, b"archive").unwrap();
+    jobs.finish(
+        &finished.job_id,
+        Ok(InvoiceConversionResult::default()),
+        Some(path.clone()),
+    );
+
+    assert_eq!(jobs.purge_expired(), 1);
+    assert!(!path.exists());
+    assert!(matches!(
+        jobs.status(&finished.job_id),
+        Err(JobError::NotFound(_))
+    ));
+    assert!(jobs.status(&running.job_id).is_ok());
+}
+
+#[test]
+fn queued_jobs_are_capped() {
+    let dir = tempfile::tempdir().unwrap();
+    let jobs = JobManager::new(JobsConfig {
+        dir: dir.path().to_string_lossy().to_string(),
+        max_queued_jobs: 2,
+        ..Default::default()
+    })
+    .unwrap();
+    let (first, _) = jobs.register(&request()).unwrap();
+    jobs.register(&request()).unwrap();
+    assert!(matches!(
+        jobs.register(&request()),
+        Err(InvConvError::ServerBusyError(_))
+    ));
+
+    // Finished jobs do not count
+    jobs.finish(&first.job_id, Ok(InvoiceConversionResult::default()), None);
+    jobs.register(&request()).unwrap();
+}
+
+#[test]
+fn startup_removes_only_job_archives() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join(format!("{}.tar.gz", uuid::Uuid::new_v4()));
+    let others = ["notes.txt", "report.zip", "keep.tar.gz"].map(|name| dir.path().join(name));
+    for path in others.iter().chain([&archive]) {
+        std::fs::write(path, b"x").unwrap();
+    }
+
+    manager(&dir, Duration::from_secs(60));
+    assert!(!archive.exists());
+    assert!(others.iter().all(|path| path.exists()));
+}
@@ -0,0 +1,4 @@
+pub mod conversion_jobs;
+
+#[cfg(test)]
+mod conversion_jobs_tests;
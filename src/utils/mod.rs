@@ -1,11 +1,15 @@
 pub mod appstate;
-pub mod database_manager;
-//pub mod download_request;
 pub mod common;
+pub mod config;
 pub mod convert_invoices;
+pub mod database_manager;
 pub mod docs_from_objstore;
+pub mod download_request;
 pub mod errors;
+pub mod grpc;
 pub mod incoming_invoice;
+pub mod jobs;
 pub mod object_store;
 pub mod rest_handlers;
+pub mod scheduler;
 pub mod xslt_engine;
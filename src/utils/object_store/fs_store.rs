@@ -0,0 +1,223 @@
+use crate::utils::common::comp_decompress::{ensure_xz, xz_uncompressed_size};
+use crate::utils::errors::object_store_errors::{ErrCtx as ObjErrCtx, ObjectStoreError};
+use crate::utils::object_store::object_store::{ObjectStore, object_path, prepare_object};
+use crate::utils::object_store::opendal_mssql_wrapper::{ObjectStoreMeta, ObjectStoreRecord};
+use async_trait::async_trait;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Objects as files under `root`, for development and tests: `{root}/{bucket}/{year}/{key}`.
+///
+/// Files may be stored xz compressed as in `OBJECTSTORE_{year}` or as plain text; plain
+/// files are compressed on read, and `inv.xml` also answers for the key `inv.xml.xz`.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The file of the key, then the plain file of a `.xz` key
+    fn candidates(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<Vec<PathBuf>, ObjectStoreError> {
+        let mut paths = vec![self.root.join(object_path(bucket, key, year)?)];
+        if let Some(plain) = key
+            .strip_suffix(".xz")
+            .and_then(|plain| object_path(bucket, plain, year).ok())
+        {
+            paths.push(self.root.join(plain));
+        }
+        Ok(paths)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FsStore {
+    async fn get(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreRecord, ObjectStoreError> {
+        let paths = self.candidates(bucket, key, year)?;
+        let found = tokio::task::spawn_blocking(move || read_object(&paths))
+            .await
+            .map_err(io::Error::other)
+            .ctx("FsStore : get : join")?
+            .ctx("FsStore : get : read")?;
+        let Some((objcontent, original_size, modified)) = found else {
+            return Err(ObjectStoreError::NoRecordFound(
+                bucket.to_string(),
+                key.to_string(),
+            ));
+        };
+
+        let size = |len: u64| {
+            i32::try_from(len).map_err(|_| ObjectStoreError::ObjectTooLarge(key.to_string(), len))
+        };
+        Ok(ObjectStoreRecord {
+            bucket: bucket.to_string(),
+            object_id: key.to_string(),
+            metadata: vec![],
+            compressed_size: size(objcontent.len() as u64)?,
+            original_size: size(original_size)?,
+            objcontent: objcontent.into(),
+            lmts: chrono::DateTime::<chrono::Local>::from(modified).naive_local(),
+        })
+    }
+
+    async fn object_exists(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<bool, ObjectStoreError> {
+        for path in self.candidates(bucket, key, year)? {
+            if tokio::fs::try_exists(&path)
+                .await
+                .ctx("FsStore : object_exists")?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Plain files report their length as original size, xz files the one in their index
+    async fn head(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreMeta, ObjectStoreError> {
+        let paths = self.candidates(bucket, key, year)?;
+        let found = tokio::task::spawn_blocking(move || stat_object(&paths))
+            .await
+            .map_err(io::Error::other)
+            .ctx("FsStore : head : join")?
+            .ctx("FsStore : head : read")?;
+        let Some((compressed_size, original_size, modified)) = found else {
+            return Err(ObjectStoreError::NoRecordFound(
+                bucket.to_string(),
+                key.to_string(),
+            ));
+        };
+
+        let size = |len: u64| {
+            i32::try_from(len).map_err(|_| ObjectStoreError::ObjectTooLarge(key.to_string(), len))
+        };
+        Ok(ObjectStoreMeta {
+            bucket: bucket.to_string(),
+            object_id: key.to_string(),
+            metadata: vec![],
+            compressed_size: size(compressed_size)?,
+            original_size: size(original_size)?,
+            lmts: chrono::DateTime::<chrono::Local>::from(modified).naive_local(),
+        })
+    }
+
+    /// Creates the bucket and year directories
+    async fn put(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+        data: Vec<u8>,
+    ) -> Result<(), ObjectStoreError> {
+        let path = self.root.join(object_path(bucket, key, year)?);
+        let (content, _, _) = prepare_object(key, data).await?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .ctx("FsStore : put : create_dir_all")?;
+        }
+        tokio::fs::write(&path, content)
+            .await
+            .ctx("FsStore : put : write")
+    }
+
+    /// Removes the plain file of a `.xz` key too
+    async fn delete(&self, bucket: &str, key: &str, year: &str) -> Result<bool, ObjectStoreError> {
+        let mut deleted = false;
+        for path in self.candidates(bucket, key, year)? {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => deleted = true,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).ctx("FsStore : delete"),
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// File names as stored, plain fixtures keep their name without `.xz`
+    async fn list(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        year: &str,
+    ) -> Result<Vec<String>, ObjectStoreError> {
+        // Any valid key, only the directory is used
+        let dir = self.root.join(object_path(bucket, "_", year)?);
+        let Some(dir) = dir.parent() else {
+            return Ok(vec![]);
+        };
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).ctx("FsStore : list : read_dir"),
+        };
+        let mut keys = vec![];
+        while let Some(entry) = entries.next_entry().await.ctx("FsStore : list : entry")? {
+            if let Some(name) = entry.file_name().to_str()
+                && name.starts_with(prefix)
+                && entry.file_type().await.ctx("FsStore : list")?.is_file()
+            {
+                keys.push(name.to_string());
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Xz content, original size and mtime of the first existing file
+fn read_object(paths: &[PathBuf]) -> io::Result<Option<(Vec<u8>, u64, SystemTime)>> {
+    for path in paths {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let modified = std::fs::metadata(path)?.modified()?;
+        let (content, original_size) = ensure_xz(data)?;
+        return Ok(Some((content, original_size, modified)));
+    }
+    Ok(None)
+}
+
+/// File size, original size and mtime of the first existing file. Plain files are
+/// not compressed here, their compressed size is the file size.
+fn stat_object(paths: &[PathBuf]) -> io::Result<Option<(u64, u64, SystemTime)>> {
+    for path in paths {
+        let meta = match std::fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let original_size = xz_uncompressed_size(&std::fs::read(path)?).unwrap_or(meta.len());
+        return Ok(Some((meta.len(), original_size, meta.modified()?)));
+    }
+    Ok(None)
+}
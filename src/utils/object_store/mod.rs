@@ -1,3 +1,5 @@
+pub mod fs_store;
+#[allow(clippy::module_inception)]
 pub mod object_store;
 pub mod opendal_minio_wrapper;
 pub mod opendal_mssql_wrapper;
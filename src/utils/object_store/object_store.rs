@@ -1,66 +1,99 @@
-use crate::utils::errors::object_store_errors::ObjectStoreError;
-use crate::utils::object_store::opendal_mssql_wrapper::{MssqlStore, ObjectStoreRecord};
+use crate::utils::common::comp_decompress::ensure_xz;
+use crate::utils::errors::object_store_errors::{ErrCtx, ObjectStoreError};
+use crate::utils::object_store::fs_store::FsStore;
+use crate::utils::object_store::opendal_minio_wrapper::DalStore;
+use crate::utils::object_store::opendal_mssql_wrapper::{
+    MssqlStore, ObjectStoreMeta, ObjectStoreRecord,
+};
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 
-/*
-pub enum Store {
-    Dal(DalStore),
-    Mssql(MssqlStore),
-}
+/// Keys fetched per round trip by `Store::get_many`
+pub const GET_MANY_CHUNK: usize = 50;
 
-impl Store {
-    async fn put(&self, bucket: &str, key: &str, data: &[u8]) -> Result<()> {
-        match self {
-            Store::Dal(s) => s.put(bucket, key, data).await,
-            Store::Mssql(s) => s.put(bucket, key, data).await,
-        }
-    }
+/// Results of `Store::get_many`, one per key in request order
+pub type ObjectStream = ReceiverStream<Result<ObjectStoreRecord, ObjectStoreError>>;
 
-    async fn get(&self, bucket: &str, key: &str) -> Result<Vec<u8>> {
-        match self {
-            Store::Dal(s) => s.get(bucket, key).await,
-            Store::Mssql(s) => s.get(bucket, key).await,
+/// An object store backend. Objects are addressed by bucket, key and year,
+/// `objcontent` is returned as stored (xz compressed UBLs and XSLTs).
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn get(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreRecord, ObjectStoreError>;
+
+    async fn object_exists(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<bool, ObjectStoreError>;
+
+    /// One result per key, in the order of `keys`; `Err` only when the whole batch failed.
+    /// Backends without a batch query fetch the keys one by one.
+    async fn get_batch(
+        &self,
+        bucket: &str,
+        keys: &[String],
+        year: &str,
+    ) -> Result<Vec<Result<ObjectStoreRecord, ObjectStoreError>>, ObjectStoreError> {
+        let mut records = Vec::with_capacity(keys.len());
+        for key in keys {
+            records.push(self.get(bucket, key, year).await);
         }
+        Ok(records)
     }
-}
-
-/// Demo main
-#[tokio::main]
-async fn main() -> Result<()> {
-    // switch backend
-    let use_mssql = false;
 
-    let bucket = "demo";
-    let key = "hello.txt";
-    let data = b"Hello world from Store enum!";
+    /// Sizes, metadata and LMTS without transferring the content
+    async fn head(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreMeta, ObjectStoreError>;
 
-    let store = if use_mssql {
-        Store::Mssql(MssqlStore::new_mssql().await?)
-    } else {
-        Store::Dal(DalStore::new_minio().await?)
-    };
+    /// Inserts or replaces the object. `data` is xz compressed unless it already is,
+    /// the original and compressed sizes are recorded with it; its metadata is empty.
+    async fn put(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+        data: Vec<u8>,
+    ) -> Result<(), ObjectStoreError>;
 
-    store.put(bucket, key, data).await?;
-    let out = store.get(bucket, key).await?;
-    println!("Got {} bytes: {}", out.len(), String::from_utf8_lossy(&out));
+    /// `false` when there was nothing to delete
+    async fn delete(&self, bucket: &str, key: &str, year: &str) -> Result<bool, ObjectStoreError>;
 
-    Ok(())
+    /// Keys of `bucket` in `year` starting with `prefix`, sorted
+    async fn list(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        year: &str,
+    ) -> Result<Vec<String>, ObjectStoreError>;
 }
-*/
+
+/// Backend picked by `object_store.backend` at startup
 #[derive(Debug, Clone)]
 pub enum Store {
-    //Dal(DalStore),
+    Dal(DalStore),
+    Fs(FsStore),
     Mssql(MssqlStore),
 }
 
 impl Store {
-    /*
-    async fn put(&self, bucket: &str, key: &str, data: &[u8]) -> Result<()> {
+    fn backend(&self) -> &dyn ObjectStore {
         match self {
-            Store::Dal(s) => s.put(bucket, key, data).await,
-            Store::Mssql(s) => s.put(bucket, key, data).await,
+            Store::Dal(s) => s,
+            Store::Fs(s) => s,
+            Store::Mssql(s) => s,
         }
     }
-    */
 
     pub async fn get(
         &self,
@@ -68,10 +101,7 @@ impl Store {
         key: &str,
         year: &str,
     ) -> Result<ObjectStoreRecord, ObjectStoreError> {
-        match self {
-            //Store::Dal(s) => s.get(bucket, key).await,
-            Store::Mssql(s) => s.get(bucket, key, year).await,
-        }
+        self.backend().get(bucket, key, year).await
     }
 
     pub async fn object_exists(
@@ -80,9 +110,102 @@ impl Store {
         key: &str,
         year: &str,
     ) -> Result<bool, ObjectStoreError> {
-        match self {
-            //Store::Dal(s) => s.get(bucket, key).await,
-            Store::Mssql(s) => s.object_exists(bucket, key, year).await,
-        }
+        self.backend().object_exists(bucket, key, year).await
+    }
+
+    /// `keys` in chunks of `GET_MANY_CHUNK`, one query per chunk on MSSQL. The next chunk
+    /// is fetched while the caller consumes the current one. Missing keys yield
+    /// `NoRecordFound` in their place; when a chunk fails its error is the last item.
+    pub fn get_many(&self, bucket: &str, keys: Vec<String>, year: &str) -> ObjectStream {
+        let (tx, rx) = mpsc::channel(GET_MANY_CHUNK);
+        let (store, bucket, year) = (self.clone(), bucket.to_string(), year.to_string());
+        tokio::spawn(async move {
+            for chunk in keys.chunks(GET_MANY_CHUNK) {
+                let records = match store.backend().get_batch(&bucket, chunk, &year).await {
+                    Ok(records) => records,
+                    Err(err) => {
+                        let _ = tx.send(Err(err)).await;
+                        return;
+                    }
+                };
+                for record in records {
+                    if tx.send(record).await.is_err() {
+                        return; // consumer gone
+                    }
+                }
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+
+    pub async fn head(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreMeta, ObjectStoreError> {
+        self.backend().head(bucket, key, year).await
+    }
+
+    pub async fn put(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+        data: Vec<u8>,
+    ) -> Result<(), ObjectStoreError> {
+        self.backend().put(bucket, key, year, data).await
+    }
+
+    pub async fn delete(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<bool, ObjectStoreError> {
+        self.backend().delete(bucket, key, year).await
+    }
+
+    pub async fn list(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        year: &str,
+    ) -> Result<Vec<String>, ObjectStoreError> {
+        self.backend().list(bucket, prefix, year).await
+    }
+}
+
+/// Xz content of `data` with its original and compressed size, see `ObjectStore::put`.
+/// Compression runs on the blocking pool.
+pub async fn prepare_object(
+    key: &str,
+    data: Vec<u8>,
+) -> Result<(Vec<u8>, i32, i32), ObjectStoreError> {
+    let (content, original_size) = tokio::task::spawn_blocking(move || ensure_xz(data))
+        .await
+        .map_err(std::io::Error::other)
+        .ctx("prepare_object : join")?
+        .ctx("prepare_object : compress")?;
+    let size = |len: u64| {
+        i32::try_from(len).map_err(|_| ObjectStoreError::ObjectTooLarge(key.to_string(), len))
+    };
+    let compressed_size = size(content.len() as u64)?;
+    Ok((content, size(original_size)?, compressed_size))
+}
+
+/// `{bucket}/{year}/{key}`, segments that would leave the bucket are rejected.
+pub fn object_path(bucket: &str, key: &str, year: &str) -> Result<String, ObjectStoreError> {
+    let safe = |segment: &str| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && !segment.contains(['/', '\\', '\0'])
+    };
+    if !safe(bucket) || !safe(key) || year.parse::<u16>().is_err() {
+        return Err(ObjectStoreError::InvalidObjectPath(format!(
+            "{bucket}/{year}/{key}"
+        )));
     }
+    Ok(format!("{bucket}/{year}/{key}"))
 }
@@ -1,7 +1,25 @@
-use crate::utils::object_store::object_store::Store;
-use crate::utils::object_store::opendal_mssql_wrapper::MssqlStore;
+use crate::utils::common::comp_decompress::xz_decompress;
+use crate::utils::config::app_config::AppConfig;
+use crate::utils::database_manager::init_database::{DbPools, init_db_connection_pools};
+use crate::utils::errors::object_store_errors::ObjectStoreError;
+use crate::utils::object_store::fs_store::FsStore;
+use crate::utils::object_store::object_store::{GET_MANY_CHUNK, Store};
+use crate::utils::object_store::opendal_minio_wrapper::DalStore;
+use crate::utils::object_store::opendal_mssql_wrapper::{MssqlStore, like_prefix, rows_per_key};
+use opendal::{Operator, services::Memory};
+use std::io::Write;
+use tokio_stream::StreamExt;
+use xz2::write::XzEncoder;
+
+async fn db_pools() -> DbPools {
+    let config = AppConfig::load().expect("config");
+    init_db_connection_pools(&config.database, config.pools.clone())
+        .await
+        .expect("Failed to init MSSQL store")
+}
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[ignore = "needs the MSSQL test server"]
 async fn test_mssql_store_get() {
     let bucket = "ubls";
     let key = "-2025-gelen-1950031086-2025-10-09-9C05F392-C508-4887-8497-68FBBEBC6D61-INVOICE-SATIS-AAA2025000000038.xml.xz";
@@ -10,11 +28,7 @@ async fn test_mssql_store_get() {
     //    Store::Mssql(s) => s,
     //    _ => panic!("Expected MssqlStore"),
     //};
-    let store = Store::Mssql(
-        MssqlStore::new_mssql()
-            .await
-            .expect("Failed to init MSSQL store"),
-    );
+    let store = Store::Mssql(MssqlStore::new_mssql(db_pools().await));
     let data = store
         .get(bucket, key, "2025")
         .await
@@ -23,6 +37,7 @@ async fn test_mssql_store_get() {
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[ignore = "needs the MSSQL test server"]
 async fn test_mssql_store_object_exists() {
     let bucket = "ubls";
     let key = "-2025-gelen-1950031086-2025-10-09-9C05F392-C508-4887-8497-68FBBEBC6D61-INVOICE-SATIS-AAA2025000000038.xml.xz";
@@ -31,14 +46,298 @@ async fn test_mssql_store_object_exists() {
     //    Store::Mssql(s) => s,
     //    _ => panic!("Expected MssqlStore"),
     //};
-    let store = Store::Mssql(
-        MssqlStore::new_mssql()
-            .await
-            .expect("Failed to init MSSQL store"),
-    );
+    let store = Store::Mssql(MssqlStore::new_mssql(db_pools().await));
     let exists = store
         .object_exists(bucket, key, "2025")
         .await
         .expect("Failed to get object from MSSQL store");
     assert!(exists, "Expected record to exist");
 }
+
+fn memory_store() -> DalStore {
+    DalStore::new(Operator::new(Memory::default()).unwrap().finish())
+}
+
+#[tokio::test]
+async fn test_dal_store_get_uses_bucket_year_key_layout() {
+    let store = Store::Dal(memory_store());
+    let xml = b"<Invoice><ID>AAA2025000000038</ID></Invoice>".repeat(100);
+    let mut encoder = XzEncoder::new(Vec::new(), 6);
+    encoder.write_all(&xml).unwrap();
+    let compressed = encoder.finish().unwrap();
+    store
+        .put("ubls", "inv-1.xml.xz", "2025", compressed.clone())
+        .await
+        .unwrap();
+
+    let rec = store.get("ubls", "inv-1.xml.xz", "2025").await.unwrap();
+    assert_eq!(
+        (rec.bucket.as_str(), rec.object_id.as_str()),
+        ("ubls", "inv-1.xml.xz")
+    );
+    assert_eq!(rec.objcontent, compressed);
+    assert_eq!(rec.original_size as usize, xml.len());
+    assert_eq!(rec.compressed_size as usize, compressed.len());
+
+    assert!(
+        store
+            .object_exists("ubls", "inv-1.xml.xz", "2025")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !store
+            .object_exists("ubls", "inv-1.xml.xz", "2024")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !store
+            .object_exists("xslts", "inv-1.xml.xz", "2025")
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_dal_store_missing_and_invalid_objects() {
+    let store = Store::Dal(memory_store());
+    let err = store.get("ubls", "nope.xml.xz", "2025").await.unwrap_err();
+    assert!(err.is_no_record_found());
+
+    for (bucket, key, year) in [
+        ("ubls", "../x", "2025"),
+        ("ubls", "a/b", "2025"),
+        ("ubls", "k", "20x5"),
+    ] {
+        assert!(matches!(
+            store.get(bucket, key, year).await,
+            Err(ObjectStoreError::InvalidObjectPath(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_fs_store_compresses_plain_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let xml = b"<Invoice><ID>FXT2025000000001</ID></Invoice>".to_vec();
+    std::fs::create_dir_all(dir.path().join("ubls/2025")).unwrap();
+    std::fs::write(dir.path().join("ubls/2025/inv-1.xml"), &xml).unwrap();
+
+    // The .xz key is answered from the plain file
+    let store = Store::Fs(FsStore::new(dir.path()));
+    let rec = store.get("ubls", "inv-1.xml.xz", "2025").await.unwrap();
+    assert_eq!(rec.original_size as usize, xml.len());
+    assert_eq!(rec.compressed_size as usize, rec.objcontent.len());
+    let out = xz_decompress(rec.objcontent, rec.original_size as usize, "inv-1")
+        .await
+        .unwrap();
+    assert_eq!(out, xml);
+
+    assert!(
+        store
+            .object_exists("ubls", "inv-1.xml.xz", "2025")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !store
+            .object_exists("ubls", "inv-1.xml.xz", "2024")
+            .await
+            .unwrap()
+    );
+    assert!(
+        store
+            .get("ubls", "inv-2.xml.xz", "2025")
+            .await
+            .unwrap_err()
+            .is_no_record_found()
+    );
+}
+
+#[tokio::test]
+async fn test_fs_store_keeps_xz_files_as_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::Fs(FsStore::new(dir.path()));
+    let mut encoder = XzEncoder::new(Vec::new(), 6);
+    encoder.write_all(&[b'x'; 4096]).unwrap();
+    let compressed = encoder.finish().unwrap();
+    store
+        .put("xslts", "style.xslt.xz", "2025", compressed.clone())
+        .await
+        .unwrap();
+
+    let rec = store.get("xslts", "style.xslt.xz", "2025").await.unwrap();
+    assert_eq!(rec.objcontent, compressed);
+    assert_eq!(rec.original_size, 4096);
+    assert_eq!(rec.compressed_size as usize, compressed.len());
+
+    // head reads sizes and mtime the same way, LMTS is the file's mtime
+    let head = store.head("xslts", "style.xslt.xz", "2025").await.unwrap();
+    assert_eq!(head, rec.meta());
+}
+
+/// put compresses, list sees the key, delete removes it once
+async fn write_path(store: Store) {
+    let xslt = b"<xsl:stylesheet version=\"1.0\"/>".repeat(50);
+    store
+        .put("xslts", "style-a.xslt", "2025", xslt.clone())
+        .await
+        .unwrap();
+    store
+        .put("xslts", "style-b.xslt", "2025", b"old".to_vec())
+        .await
+        .unwrap();
+    store
+        .put("xslts", "style-b.xslt", "2025", b"new".to_vec())
+        .await
+        .unwrap();
+    store
+        .put("xslts", "other.xslt", "2025", b"x".to_vec())
+        .await
+        .unwrap();
+
+    let rec = store.get("xslts", "style-a.xslt", "2025").await.unwrap();
+    assert_eq!(rec.original_size as usize, xslt.len());
+    assert!(rec.compressed_size < rec.original_size);
+    let head = store.head("xslts", "style-a.xslt", "2025").await.unwrap();
+    assert_eq!(
+        (head.original_size, head.compressed_size),
+        (rec.original_size, rec.compressed_size)
+    );
+    // put writes no metadata, a replaced object does not keep the old one
+    assert!(head.metadata.is_empty());
+    let head = store.head("xslts", "style-b.xslt", "2025").await.unwrap();
+    assert!(head.metadata.is_empty());
+    assert!(
+        store
+            .head("xslts", "style-c.xslt", "2025")
+            .await
+            .unwrap_err()
+            .is_no_record_found()
+    );
+    let out = xz_decompress(rec.objcontent, rec.original_size as usize, "style-a")
+        .await
+        .unwrap();
+    assert_eq!(out, xslt);
+
+    let rec = store.get("xslts", "style-b.xslt", "2025").await.unwrap();
+    assert_eq!(rec.original_size, 3);
+
+    assert_eq!(
+        store.list("xslts", "style-", "2025").await.unwrap(),
+        vec!["style-a.xslt", "style-b.xslt"]
+    );
+    assert_eq!(store.list("xslts", "", "2025").await.unwrap().len(), 3);
+    assert!(store.list("xslts", "", "2024").await.unwrap().is_empty());
+
+    assert!(store.delete("xslts", "style-a.xslt", "2025").await.unwrap());
+    assert!(!store.delete("xslts", "style-a.xslt", "2025").await.unwrap());
+    assert!(
+        !store
+            .object_exists("xslts", "style-a.xslt", "2025")
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_dal_store_write_path() {
+    write_path(Store::Dal(memory_store())).await;
+}
+
+#[tokio::test]
+async fn test_fs_store_write_path() {
+    let dir = tempfile::tempdir().unwrap();
+    write_path(Store::Fs(FsStore::new(dir.path()))).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[ignore = "needs the MSSQL test server"]
+async fn test_mssql_store_write_path() {
+    let store = Store::Mssql(MssqlStore::new_mssql(db_pools().await));
+    for key in ["style-a.xslt", "style-b.xslt", "other.xslt"] {
+        store.delete("xslts", key, "2025").await.unwrap();
+    }
+    write_path(store).await;
+}
+
+/// Request order across chunk boundaries, duplicates and missing keys in place
+async fn get_many_in_order(store: Store) {
+    let count = GET_MANY_CHUNK + 3;
+    for n in 0..count {
+        store
+            .put(
+                "ubls",
+                &format!("inv-{n}.xml"),
+                "2025",
+                n.to_string().into_bytes(),
+            )
+            .await
+            .unwrap();
+    }
+    let mut keys: Vec<String> = (0..count).rev().map(|n| format!("inv-{n}.xml")).collect();
+    keys.insert(1, "inv-missing.xml".to_string());
+    keys.push("inv-0.xml".to_string());
+
+    let results: Vec<_> = store.get_many("ubls", keys.clone(), "2025").collect().await;
+    assert_eq!(results.len(), keys.len());
+    for (key, result) in keys.iter().zip(results) {
+        match result {
+            Ok(rec) => assert_eq!(&rec.object_id, key),
+            Err(err) => {
+                assert_eq!(key, "inv-missing.xml");
+                assert!(err.is_no_record_found());
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dal_store_get_many() {
+    get_many_in_order(Store::Dal(memory_store())).await;
+}
+
+#[tokio::test]
+async fn test_fs_store_get_many() {
+    let dir = tempfile::tempdir().unwrap();
+    get_many_in_order(Store::Fs(FsStore::new(dir.path()))).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+#[ignore = "needs the MSSQL test server"]
+async fn test_mssql_store_get_many() {
+    get_many_in_order(Store::Mssql(MssqlStore::new_mssql(db_pools().await))).await;
+}
+
+#[tokio::test]
+async fn test_get_many_reports_invalid_paths_per_key() {
+    let store = Store::Dal(memory_store());
+    let results: Vec<_> = store
+        .get_many("ubls", vec!["inv.xml".to_string()], "20x5")
+        .collect()
+        .await;
+    assert_eq!(results.len(), 1);
+    assert!(matches!(
+        results[0],
+        Err(ObjectStoreError::InvalidObjectPath(_))
+    ));
+}
+
+#[test]
+fn like_prefix_escapes_wildcards() {
+    assert_eq!(like_prefix("2025-gelen-"), "2025-gelen-%");
+    assert_eq!(like_prefix("a_b%c[d]\\"), "a\\_b\\%c\\[d]\\\\%");
+}
+
+#[test]
+fn batch_rows_match_keys_like_the_collation() {
+    let keys: Vec<String> = ["INV-1.xml", "inv-2.xml", "inv-1.XML", "inv-3.xml"]
+        .map(String::from)
+        .to_vec();
+    let rows = [("inv-1.xml ", 1), ("INV-2.xml", 2), ("Inv-2.XML", 3)];
+    assert_eq!(
+        rows_per_key(&keys, &rows),
+        [vec![1], vec![2, 3], vec![1], vec![]]
+    );
+}
@@ -1,46 +1,199 @@
-use opendal::Operator;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use opendal::layers::LoggingLayer;
 use opendal::services::S3;
+use opendal::{ErrorKind, Metadata, Operator};
 
-use crate::utils::errors::object_store_errors::ObjectStoreError;
+use crate::utils::common::comp_decompress::xz_uncompressed_size;
+use crate::utils::config::app_config::MinioConfig;
+use crate::utils::errors::object_store_errors::{ErrCtx as ObjErrCtx, ObjectStoreError};
+use crate::utils::object_store::object_store::{ObjectStore, object_path, prepare_object};
+use crate::utils::object_store::opendal_mssql_wrapper::{ObjectStoreMeta, ObjectStoreRecord};
 
-// OpenDaL wrapper for MinIO
+/// User metadata holding the uncompressed size, `x-amz-meta-original-size` on S3
+const ORIGINAL_SIZE_META: &str = "original-size";
+
+/// OpenDAL wrapper, S3/MinIO in production. Same layout as `OBJECTSTORE_{year}`:
+/// the object `key` of `bucket` in `year` is stored at `{bucket}/{year}/{key}`.
+#[derive(Debug, Clone)]
 pub struct DalStore {
     op: Operator,
 }
 impl DalStore {
-    pub async fn new_minio() -> Result<Self, ObjectStoreError> {
+    pub fn new_minio(config: &MinioConfig) -> Result<Self, ObjectStoreError> {
         let b = S3::default()
-            .bucket("my-bucket")
-            .endpoint("http://127.0.0.1:9000")
-            .region("us-east-1")
-            .access_key_id("minioadmin")
-            .secret_access_key("minioadmin");
+            .bucket(&config.bucket)
+            .endpoint(&config.endpoint)
+            .region(&config.region)
+            .access_key_id(&config.access_key_id)
+            .secret_access_key(config.secret_access_key.expose());
 
         let op = Operator::new(b)?.finish().layer(LoggingLayer::default());
         Ok(Self { op })
     }
 
-    pub async fn put(
+    /// Any OpenDAL service, e.g. `services::Memory` in tests
+    pub fn new(op: Operator) -> Self {
+        Self { op }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for DalStore {
+    async fn get(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreRecord, ObjectStoreError> {
+        let path = object_path(bucket, key, year)?;
+        let (buf, stat) = match tokio::try_join!(self.op.read(&path), self.op.stat(&path)) {
+            Ok(found) => found,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(ObjectStoreError::NoRecordFound(
+                    bucket.to_string(),
+                    key.to_string(),
+                ));
+            }
+            Err(e) => return Err(e).ctx("DalStore : get : read"),
+        };
+        let objcontent = buf.to_bytes();
+
+        // The xz index has the original size
+        let size = |len: u64| {
+            i32::try_from(len).map_err(|_| ObjectStoreError::ObjectTooLarge(key.to_string(), len))
+        };
+        let compressed_size = size(objcontent.len() as u64)?;
+        let original_size = match xz_uncompressed_size(&objcontent) {
+            Some(len) => size(len)?,
+            None => compressed_size,
+        };
+
+        Ok(ObjectStoreRecord {
+            bucket: bucket.to_string(),
+            object_id: key.to_string(),
+            metadata: vec![],
+            objcontent,
+            original_size,
+            compressed_size,
+            lmts: lmts(&stat),
+        })
+    }
+
+    /// Original size from the `original-size` user metadata `put` writes,
+    /// objects written without it are read once to get it from the xz index.
+    async fn head(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreMeta, ObjectStoreError> {
+        let path = object_path(bucket, key, year)?;
+        let stat = match self.op.stat(&path).await {
+            Ok(stat) => stat,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(ObjectStoreError::NoRecordFound(
+                    bucket.to_string(),
+                    key.to_string(),
+                ));
+            }
+            Err(e) => return Err(e).ctx("DalStore : head : stat"),
+        };
+        let recorded = stat
+            .user_metadata()
+            .and_then(|meta| meta.get(ORIGINAL_SIZE_META))
+            .and_then(|size| size.parse::<i32>().ok());
+        let size = |len: u64| {
+            i32::try_from(len).map_err(|_| ObjectStoreError::ObjectTooLarge(key.to_string(), len))
+        };
+        let compressed_size = size(stat.content_length())?;
+        let original_size = match recorded {
+            Some(original_size) => original_size,
+            None => self.get(bucket, key, year).await?.original_size,
+        };
+
+        Ok(ObjectStoreMeta {
+            bucket: bucket.to_string(),
+            object_id: key.to_string(),
+            metadata: vec![],
+            original_size,
+            compressed_size,
+            lmts: lmts(&stat),
+        })
+    }
+
+    async fn object_exists(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<bool, ObjectStoreError> {
+        let path = object_path(bucket, key, year)?;
+        self.op.exists(&path).await.ctx("DalStore : object_exists")
+    }
+
+    async fn put(
         &self,
         bucket: &str,
         key: &str,
         year: &str,
         data: Vec<u8>,
     ) -> Result<(), ObjectStoreError> {
-        let path = format!("{}/{}", bucket, key);
-        self.op.write(&path, data).await?;
+        let path = object_path(bucket, key, year)?;
+        let (content, original_size, _) = prepare_object(key, data).await?;
+        let mut write = self.op.write_with(&path, content);
+        if self.op.info().full_capability().write_with_user_metadata {
+            write =
+                write.user_metadata([(ORIGINAL_SIZE_META.to_string(), original_size.to_string())]);
+        }
+        write.await.ctx("DalStore : put")?;
         Ok(())
     }
 
-    pub async fn get(
+    /// S3 deletes are idempotent, existence is checked first
+    async fn delete(&self, bucket: &str, key: &str, year: &str) -> Result<bool, ObjectStoreError> {
+        let path = object_path(bucket, key, year)?;
+        if !self
+            .op
+            .exists(&path)
+            .await
+            .ctx("DalStore : delete : exists")?
+        {
+            return Ok(false);
+        }
+        self.op.delete(&path).await.ctx("DalStore : delete")?;
+        Ok(true)
+    }
+
+    async fn list(
         &self,
         bucket: &str,
-        key: &str,
+        prefix: &str,
         year: &str,
-    ) -> Result<Vec<u8>, ObjectStoreError> {
-        let path = format!("{}/{}", bucket, key);
-        let buf = self.op.read(&path).await?;
-        Ok(buf.to_vec())
+    ) -> Result<Vec<String>, ObjectStoreError> {
+        let dir = format!(
+            "{}/",
+            object_path(bucket, "_", year)?.trim_end_matches("/_")
+        );
+        let entries = match self.op.list(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).ctx("DalStore : list"),
+        };
+        let mut keys: Vec<String> = entries
+            .into_iter()
+            .filter(|entry| entry.metadata().is_file() && entry.name().starts_with(prefix))
+            .map(|entry| entry.name().to_string())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Last modification as local time, now for services that do not keep it
+fn lmts(stat: &Metadata) -> NaiveDateTime {
+    match stat.last_modified() {
+        Some(modified) => modified.with_timezone(&chrono::Local).naive_local(),
+        None => chrono::Local::now().naive_local(),
     }
 }
@@ -1,12 +1,13 @@
-use crate::utils::errors::db_errors::DbError;
-use crate::utils::{
-    database_manager::init_database::init_db_connection_pool,
-    errors::object_store_errors::{ErrCtx as ObjErrCtx, ObjectStoreError},
+use crate::utils::database_manager::init_database::{ConnectionPool, DbPools};
+use crate::utils::database_manager::pool_registry::{
+    DEFAULT_OBJECT_STORE_DB, OBJECT_STORE_POOL, PoolKey,
 };
+use crate::utils::errors::object_store_errors::{ErrCtx as ObjErrCtx, ObjectStoreError};
+use crate::utils::object_store::object_store::{ObjectStore, prepare_object};
 
-use bb8::Pool;
-use bb8_tiberius::ConnectionManager;
+use async_trait::async_trait;
 use chrono::NaiveDateTime;
+use std::collections::HashMap;
 use tiberius::Query;
 use tokio_util::bytes;
 
@@ -21,40 +22,79 @@ pub struct ObjectStoreRecord {
     pub lmts: NaiveDateTime,
 }
 
-pub type ObjectStoreConnectionPool = Pool<ConnectionManager>;
+/// Everything of an object but its content, see `ObjectStore::head`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectStoreMeta {
+    pub bucket: String,
+    pub object_id: String,
+    pub metadata: Vec<u8>,
+    pub original_size: i32,
+    pub compressed_size: i32,
+    /// Last modification, server local time as stored in LMTS
+    pub lmts: NaiveDateTime,
+}
+impl ObjectStoreRecord {
+    pub fn meta(&self) -> ObjectStoreMeta {
+        ObjectStoreMeta {
+            bucket: self.bucket.clone(),
+            object_id: self.object_id.clone(),
+            metadata: self.metadata.clone(),
+            original_size: self.original_size,
+            compressed_size: self.compressed_size,
+            lmts: self.lmts,
+        }
+    }
+}
+
+/// Objects of a year live in `{db}.dbo.OBJECTSTORE_{year}`, the `object_store`
+/// pool route of the year says which server and database.
 #[derive(Clone, Debug)]
 pub struct MssqlStore {
-    object_store_conn_pool: ObjectStoreConnectionPool,
+    db_pools: DbPools,
 }
 impl MssqlStore {
-    pub async fn new_mssql() -> Result<Self, DbError> {
-        let object_store_conn_pool = init_db_connection_pool("MsSqlStore").await?;
-        Ok(Self {
-            object_store_conn_pool,
-        })
+    pub fn new_mssql(db_pools: DbPools) -> Self {
+        Self { db_pools }
     }
 
-    pub async fn get(
+    /// Pool, database name and parsed `year`; table names use the number, never the raw text
+    async fn route(&self, year: &str) -> Result<(ConnectionPool, String, u16), ObjectStoreError> {
+        let year_num: u16 = year
+            .parse()
+            .map_err(|_| ObjectStoreError::CanNotExtractDatabaseName(format!("year '{year}'")))?;
+        let key = PoolKey::new(OBJECT_STORE_POOL).with_year(year_num);
+        let db_name = self
+            .db_pools
+            .registry
+            .route(&key)
+            .and_then(|route| route.db_name.as_deref())
+            .unwrap_or(DEFAULT_OBJECT_STORE_DB)
+            .replace("{year}", &year_num.to_string());
+        let pool = self.db_pools.pool(&key).await?;
+        Ok((pool, db_name, year_num))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MssqlStore {
+    async fn get(
         &self,
         bucket: &str,
         key: &str,
         year: &str,
     ) -> Result<ObjectStoreRecord, ObjectStoreError> {
-        let mut conn = self
-            .object_store_conn_pool
+        let (pool, db_name, year) = self.route(year).await.ctx("MsSqlStore : get : route")?;
+        let mut conn = pool
             .get()
             .await
             //.map_err(ObjectStoreError::from)
             .ctx("MsSqlStore : get : get conn from pool")?;
 
         let sql_sentence = format!(
-            "SELECT OBJCONTENT, ORIGINALSIZE, COMPRESSEDSIZE
-             FROM {}.dbo.{}
-             WHERE BUCKET = @P1 AND OBJECTID = @P2",
-            self.get_dbname(year),
-            format!("OBJECTSTORE_{}", year)
+            "SELECT OBJCONTENT, ORIGINALSIZE, COMPRESSEDSIZE, METADATA, LMTS
+             FROM {db_name}.dbo.OBJECTSTORE_{year}
+             WHERE BUCKET = @P1 AND OBJECTID = @P2"
         );
-        println!("Sql_sentence: {}", sql_sentence);
 
         let mut query = Query::new(sql_sentence);
         query.bind(bucket.to_string());
@@ -71,49 +111,143 @@ impl MssqlStore {
             //.map_err(ObjectStoreError::from)
             .ctx("MsSqlStore : get : stream")?;
 
-        println!("number of rows: {}", rows.len());
         if rows.len() > 1 {
             return Err(ObjectStoreError::MultipleRecordsFound(
                 bucket.to_string(),
                 key.to_string(),
             ));
         }
-        if rows.len() == 0 {
+        if rows.is_empty() {
             return Err(ObjectStoreError::NoRecordFound(
                 bucket.to_string(),
                 key.to_string(),
             ));
         }
 
-        let row = &rows[0];
-        let object_content = row
-            .get::<&[u8], _>(0)
-            .ok_or_else(|| ObjectStoreError::MissingField("missing object_content".to_string()))?;
-        let original_size = row
-            .get::<i32, _>(1)
-            .ok_or_else(|| ObjectStoreError::MissingField("missing original_size".to_string()))?;
-        let compressed_size = row
-            .get::<i32, _>(2)
-            .ok_or_else(|| ObjectStoreError::MissingField("missing compressed_size".to_string()))?;
-
-        Ok(ObjectStoreRecord {
-            bucket: bucket.to_string(),
-            object_id: key.to_string(),
-            metadata: vec![],
-            objcontent: object_content.to_vec().into(),
-            original_size: original_size,
-            compressed_size: compressed_size,
-            lmts: chrono::Local::now().naive_local(),
-        })
+        record_from_row(&rows[0], bucket, key)
     }
-    pub async fn object_exists(
+
+    /// One `IN` query for the chunk, rows are matched back to `keys` by `rows_per_key`
+    async fn get_batch(
+        &self,
+        bucket: &str,
+        keys: &[String],
+        year: &str,
+    ) -> Result<Vec<Result<ObjectStoreRecord, ObjectStoreError>>, ObjectStoreError> {
+        let mut unique: Vec<&str> = keys.iter().map(String::as_str).collect();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.is_empty() {
+            return Ok(vec![]);
+        }
+        let (pool, db_name, year) = self
+            .route(year)
+            .await
+            .ctx("MsSqlStore : get_batch : route")?;
+        let mut conn = pool
+            .get()
+            .await
+            .ctx("MsSqlStore : get_batch : get conn from pool")?;
+
+        let params = (2..unique.len() + 2)
+            .map(|n| format!("@P{n}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql_sentence = format!(
+            "SELECT OBJECTID, OBJCONTENT, ORIGINALSIZE, COMPRESSEDSIZE, METADATA, LMTS
+             FROM {db_name}.dbo.OBJECTSTORE_{year}
+             WHERE BUCKET = @P1 AND OBJECTID IN ({params})"
+        );
+        let mut query = Query::new(sql_sentence);
+        query.bind(bucket.to_string());
+        for key in &unique {
+            query.bind(key.to_string());
+        }
+        let rows = query
+            .query(&mut *conn)
+            .await
+            .ctx("MsSqlStore : get_batch : query")?
+            .into_first_result()
+            .await
+            .ctx("MsSqlStore : get_batch : stream")?;
+
+        let object_ids = rows
+            .iter()
+            .map(|row| {
+                row.get::<&str, _>("OBJECTID")
+                    .map(|object_id| (object_id, row))
+                    .ok_or_else(|| ObjectStoreError::MissingField("missing object_id".to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys
+            .iter()
+            .zip(rows_per_key(keys, &object_ids))
+            .map(|(key, rows)| match rows.as_slice() {
+                [row] => record_from_row(row, bucket, key),
+                [] => Err(ObjectStoreError::NoRecordFound(
+                    bucket.to_string(),
+                    key.to_string(),
+                )),
+                _ => Err(ObjectStoreError::MultipleRecordsFound(
+                    bucket.to_string(),
+                    key.to_string(),
+                )),
+            })
+            .collect())
+    }
+
+    async fn head(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+    ) -> Result<ObjectStoreMeta, ObjectStoreError> {
+        let (pool, db_name, year) = self.route(year).await.ctx("MsSqlStore : head : route")?;
+        let mut conn = pool
+            .get()
+            .await
+            .ctx("MsSqlStore : head : get conn from pool")?;
+
+        let sql_sentence = format!(
+            "SELECT ORIGINALSIZE, COMPRESSEDSIZE, METADATA, LMTS
+             FROM {db_name}.dbo.OBJECTSTORE_{year}
+             WHERE BUCKET = @P1 AND OBJECTID = @P2"
+        );
+        let mut query = Query::new(sql_sentence);
+        query.bind(bucket.to_string());
+        query.bind(key.to_string());
+        let rows = query
+            .query(&mut *conn)
+            .await
+            .ctx("MsSqlStore : head : query")?
+            .into_first_result()
+            .await
+            .ctx("MsSqlStore : head : stream")?;
+
+        match rows.as_slice() {
+            [row] => meta_from_row(row, bucket, key),
+            [] => Err(ObjectStoreError::NoRecordFound(
+                bucket.to_string(),
+                key.to_string(),
+            )),
+            _ => Err(ObjectStoreError::MultipleRecordsFound(
+                bucket.to_string(),
+                key.to_string(),
+            )),
+        }
+    }
+
+    async fn object_exists(
         &self,
         bucket: &str,
         key: &str,
         year: &str,
     ) -> Result<bool, ObjectStoreError> {
-        let mut conn = self
-            .object_store_conn_pool
+        let (pool, db_name, year) = self
+            .route(year)
+            .await
+            .ctx("MsSqlStore : object_exists : route")?;
+        let mut conn = pool
             .get()
             .await
             //.map_err(ObjectStoreError::from)
@@ -121,10 +255,8 @@ impl MssqlStore {
 
         let sql_sentence = format!(
             "SELECT TOP 1 OBJECTID
-            FROM {}.dbo.{}
-            WHERE BUCKET = @P1 AND OBJECTID = @P2",
-            self.get_dbname(year),
-            format!("OBJECTSTORE_{}", year)
+            FROM {db_name}.dbo.OBJECTSTORE_{year}
+            WHERE BUCKET = @P1 AND OBJECTID = @P2"
         );
         let mut query = Query::new(sql_sentence);
         query.bind(bucket.to_string());
@@ -141,11 +273,188 @@ impl MssqlStore {
             //.map_err(ObjectStoreError::from)
             .ctx("MsSqlStore : get : stream")?;
 
-        println!("number of rows: {}", rows.len());
-        if rows.len() > 0 { Ok(true) } else { Ok(false) }
+        Ok(!rows.is_empty())
+    }
+
+    async fn put(
+        &self,
+        bucket: &str,
+        key: &str,
+        year: &str,
+        data: Vec<u8>,
+    ) -> Result<(), ObjectStoreError> {
+        let (content, original_size, compressed_size) = prepare_object(key, data).await?;
+        let (pool, db_name, year) = self.route(year).await.ctx("MsSqlStore : put : route")?;
+        let mut conn = pool
+            .get()
+            .await
+            .ctx("MsSqlStore : put : get conn from pool")?;
+
+        // Upsert, HOLDLOCK keeps two writers of the same key from both inserting.
+        // `put` carries no metadata, METADATA is cleared so `head` / `get` never return
+        // what an older version of the object had, like the fs and minio stores.
+        let sql_sentence = format!(
+            "MERGE {db_name}.dbo.OBJECTSTORE_{year} WITH (HOLDLOCK) AS t
+             USING (SELECT @P1 AS BUCKET, @P2 AS OBJECTID) AS s
+                ON t.BUCKET = s.BUCKET AND t.OBJECTID = s.OBJECTID
+             WHEN MATCHED THEN
+                UPDATE SET OBJCONTENT = @P3, ORIGINALSIZE = @P4, COMPRESSEDSIZE = @P5,
+                           METADATA = NULL, LMTS = GETDATE()
+             WHEN NOT MATCHED THEN
+                INSERT (BUCKET, OBJECTID, OBJCONTENT, ORIGINALSIZE, COMPRESSEDSIZE, METADATA, LMTS)
+                VALUES (@P1, @P2, @P3, @P4, @P5, NULL, GETDATE());"
+        );
+        let mut query = Query::new(sql_sentence);
+        query.bind(bucket.to_string());
+        query.bind(key.to_string());
+        query.bind(content);
+        query.bind(original_size);
+        query.bind(compressed_size);
+        query
+            .execute(&mut *conn)
+            .await
+            .ctx("MsSqlStore : put : execute")?;
+        Ok(())
+    }
+
+    async fn delete(&self, bucket: &str, key: &str, year: &str) -> Result<bool, ObjectStoreError> {
+        let (pool, db_name, year) = self.route(year).await.ctx("MsSqlStore : delete : route")?;
+        let mut conn = pool
+            .get()
+            .await
+            .ctx("MsSqlStore : delete : get conn from pool")?;
+
+        let sql_sentence = format!(
+            "DELETE FROM {db_name}.dbo.OBJECTSTORE_{year}
+             WHERE BUCKET = @P1 AND OBJECTID = @P2"
+        );
+        let mut query = Query::new(sql_sentence);
+        query.bind(bucket.to_string());
+        query.bind(key.to_string());
+        let result = query
+            .execute(&mut *conn)
+            .await
+            .ctx("MsSqlStore : delete : execute")?;
+        Ok(result.total() > 0)
+    }
+
+    async fn list(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        year: &str,
+    ) -> Result<Vec<String>, ObjectStoreError> {
+        let (pool, db_name, year) = self.route(year).await.ctx("MsSqlStore : list : route")?;
+        let mut conn = pool
+            .get()
+            .await
+            .ctx("MsSqlStore : list : get conn from pool")?;
+
+        let sql_sentence = format!(
+            "SELECT OBJECTID
+             FROM {db_name}.dbo.OBJECTSTORE_{year}
+             WHERE BUCKET = @P1 AND OBJECTID LIKE @P2 ESCAPE '\\'
+             ORDER BY OBJECTID"
+        );
+        let mut query = Query::new(sql_sentence);
+        query.bind(bucket.to_string());
+        query.bind(like_prefix(prefix));
+        let rows = query
+            .query(&mut *conn)
+            .await
+            .ctx("MsSqlStore : list : query")?
+            .into_first_result()
+            .await
+            .ctx("MsSqlStore : list : stream")?;
+
+        rows.iter()
+            .map(|row| {
+                row.get::<&str, _>(0)
+                    .map(str::to_string)
+                    .ok_or_else(|| ObjectStoreError::MissingField("missing object_id".to_string()))
+            })
+            .collect()
+    }
+}
+
+/// The rows of each of `keys` from `(OBJECTID, row)` pairs, compared the way the
+/// column collation does: case-insensitive, trailing spaces ignored
+pub fn rows_per_key<R: Copy>(keys: &[String], rows: &[(&str, R)]) -> Vec<Vec<R>> {
+    let collation_key = |object_id: &str| object_id.trim_end_matches(' ').to_lowercase();
+    let mut found: HashMap<String, Vec<R>> = HashMap::new();
+    for (object_id, row) in rows {
+        found
+            .entry(collation_key(object_id))
+            .or_default()
+            .push(*row);
     }
+    keys.iter()
+        .map(|key| found.get(&collation_key(key)).cloned().unwrap_or_default())
+        .collect()
+}
+
+/// OBJCONTENT and the `meta_from_row` columns of `row`
+fn record_from_row(
+    row: &tiberius::Row,
+    bucket: &str,
+    key: &str,
+) -> Result<ObjectStoreRecord, ObjectStoreError> {
+    let object_content = row
+        .get::<&[u8], _>("OBJCONTENT")
+        .ok_or_else(|| ObjectStoreError::MissingField("missing object_content".to_string()))?;
+    let meta = meta_from_row(row, bucket, key)?;
+
+    Ok(ObjectStoreRecord {
+        bucket: meta.bucket,
+        object_id: meta.object_id,
+        metadata: meta.metadata,
+        objcontent: object_content.to_vec().into(),
+        original_size: meta.original_size,
+        compressed_size: meta.compressed_size,
+        lmts: meta.lmts,
+    })
+}
 
-    fn get_dbname(&self, year: &str) -> String {
-        format!("EFaturaDB01_{}", year)
+/// ORIGINALSIZE, COMPRESSEDSIZE, METADATA and LMTS columns of `row`.
+/// METADATA may be NULL, binary or text.
+fn meta_from_row(
+    row: &tiberius::Row,
+    bucket: &str,
+    key: &str,
+) -> Result<ObjectStoreMeta, ObjectStoreError> {
+    let missing = |field: &str| ObjectStoreError::MissingField(format!("missing {field}"));
+    let metadata = match row.try_get::<&[u8], _>("METADATA") {
+        Ok(bytes) => bytes.map(<[u8]>::to_vec),
+        Err(_) => row
+            .try_get::<&str, _>("METADATA")
+            .ctx("MsSqlStore : METADATA")?
+            .map(|text| text.as_bytes().to_vec()),
+    };
+    Ok(ObjectStoreMeta {
+        bucket: bucket.to_string(),
+        object_id: key.to_string(),
+        metadata: metadata.unwrap_or_default(),
+        original_size: row
+            .get::<i32, _>("ORIGINALSIZE")
+            .ok_or_else(|| missing("original_size"))?,
+        compressed_size: row
+            .get::<i32, _>("COMPRESSEDSIZE")
+            .ok_or_else(|| missing("compressed_size"))?,
+        lmts: row
+            .get::<NaiveDateTime, _>("LMTS")
+            .ok_or_else(|| missing("lmts"))?,
+    })
+}
+
+/// LIKE pattern matching keys that start with `prefix`, its wildcards escaped with `\`
+pub fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '%' | '_' | '[' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
     }
+    pattern.push('%');
+    pattern
 }
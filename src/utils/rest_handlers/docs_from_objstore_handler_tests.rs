@@ -1,85 +1,192 @@
-use crate::utils::appstate::appstate::{AppState, create_app};
-use crate::utils::database_manager;
-use crate::utils::object_store::object_store::Store;
-use crate::utils::object_store::opendal_mssql_wrapper::MssqlStore;
-
-use crate::utils::common::download_types_and_formats::{
-    DownloadFormat, DownloadType, FilenameInZipMode,
+use crate::utils::appstate::appstate::{create_app, init_app_state};
+use crate::utils::common::target_types_and_formats::{
+    FilenameInZipMode, TargetCompressionType, TargetType,
 };
-use crate::utils::rest_handlers::docs_from_objstore_handler::{
-    DocRequestItem, DocsFromObjStoreReq,
+use crate::utils::config::app_config::{AppConfig, ObjectStoreBackend, Secret};
+use crate::utils::rest_handlers::get_invoices_handler::{
+    RequestInvoiceItemForConversion, RequestInvoicesForConversion, ResponseInvoicesForConversion,
 };
 use axum_test::TestServer;
-use std::sync::Arc;
+use std::io::{Cursor, Read};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/objstore");
+
+fn object_id(n: u64) -> String {
+    format!("2025-gelen-1950031086-fixture-INVOICE-SATIS-FXT202500000000{n}.xml.xz")
+}
+
+/// Real startup path on the fs object store, no MSSQL needed
+async fn server(tmp: &tempfile::TempDir) -> TestServer {
+    let mut config = AppConfig::default();
+    config.database.host = "unused".to_string();
+    config.database.database = "uut_25_1".to_string();
+    config.database.user = "unused".to_string();
+    config.database.password = Secret::new("unused");
+    config.object_store.backend = ObjectStoreBackend::Fs;
+    config.object_store.dir = FIXTURES.to_string();
+    config.xslt_disk_cache.dir = tmp.path().join("xslt").to_string_lossy().to_string();
+    config.jobs.dir = tmp.path().join("jobs").to_string_lossy().to_string();
+    config.validate().unwrap();
+    TestServer::new(create_app(init_app_state(&config).await.unwrap())).unwrap()
+}
+
+fn request(object_ids: Vec<String>) -> RequestInvoicesForConversion {
+    RequestInvoicesForConversion {
+        target_type: TargetType::Html,
+        target_compression_type: TargetCompressionType::Zip,
+        year: "2025".to_string(),
+        filename_in_zip: FilenameInZipMode::UseSiraNo,
+        request_id: "fixture-1".to_string(),
+        client_id: None,
+        xslt_engine: None,
+        continue_on_error: true,
+        desanitize: false,
+        incoming_invoice: None,
+        items: object_ids
+            .into_iter()
+            .zip(1..)
+            .map(|(object_id, sira_no)| RequestInvoiceItemForConversion {
+                object_id,
+                sira_no: Some(sira_no),
+                invoice_no: None,
+            })
+            .collect(),
+    }
+}
 
 #[tokio::test]
-async fn test2() {
-    let db_pools = match database_manager::init_database::init_db_connection_pools().await {
-        Ok(pools) => {
-            println!("✅ All DB pools initialized successfully.");
-            pools
-        }
+async fn fixture_invoices_convert_to_html() {
+    let tmp = tempfile::tempdir().unwrap();
+    let server = server(&tmp).await;
 
-        Err(err) => {
-            eprintln!("❌ Database initialization failed: {err}");
-            //for cause in anyhow::Chain::new(&err) {
-            //    eprintln!("  caused by: {cause}");
-            //}
-            std::process::exit(1);
-        }
-    };
-    let object_store = Store::Mssql(
-        MssqlStore::new_mssql()
-            .await
-            .expect("Failed to init MSSQL store"),
-    );
+    let response = server
+        .get("/api/v1/docs_from_objstore")
+        .json(&request(vec![object_id(1), object_id(2)]))
+        .await;
+    response.assert_status_ok();
 
-    let app_state = Arc::new(AppState {
-        db_pools,
-        object_store,
-    });
+    let body: ResponseInvoicesForConversion = response.json();
+    assert_eq!(body.docs_count, 2);
+    assert!(body.request_fully_completed);
+    assert!(body.item_statuses.iter().all(|s| s.error_code == 0));
 
-    let server = TestServer::new(create_app(app_state)).unwrap();
+    let mut zip = zip::ZipArchive::new(Cursor::new(body.data)).unwrap();
+    let mut html = String::new();
+    for i in 0..zip.len() {
+        zip.by_index(i).unwrap().read_to_string(&mut html).unwrap();
+    }
+    assert!(html.contains("FXT2025000000001"), "{html}");
+    assert!(html.contains("FXT2025000000002"));
+    assert!(html.contains("200.50"));
+}
 
-    let request_body = DocsFromObjStoreReq {
-        target_type: DownloadType::Html,
-        target_format: DownloadFormat::Zip,
-        year: "2025".to_string(),
-        filename_in_zip: FilenameInZipMode::StartFromInvoiceOne,
-        items: vec![
-            DocRequestItem {
-                object_id: "2025-gelen-1950031086-2025-10-09-9C05F392-C508-4887-8497-68FBBEBC6D61-INVOICE-SATIS-AAA2025000000038.xml.xz".to_string(),
-                sira_no: Some(1),
-                invoice_no: Some("INV-001".to_string()),
-            },
-            DocRequestItem {
-                object_id: "2025-gelen-1950031086-2025-10-09-9C05F392-C508-4887-8497-68FBBEBC6D61-INVOICE-SATIS-AAA2025000000038.xml.xz".to_string(),
-                sira_no: Some(2),
-                invoice_no: Some("INV-002".to_string()),
-            },
-        ],
-    };
+#[tokio::test]
+async fn ubl_xslt_separate_bundles_the_stored_xml() {
+    let tmp = tempfile::tempdir().unwrap();
+    let server = server(&tmp).await;
+
+    let mut request = request(vec![object_id(3)]);
+    request.target_type = TargetType::UblXsltSeparate;
+    let response = server
+        .get("/api/v1/docs_from_objstore")
+        .json(&request)
+        .await;
+    response.assert_status_ok();
+
+    let body: ResponseInvoicesForConversion = response.json();
+    let mut zip = zip::ZipArchive::new(Cursor::new(body.data)).unwrap();
+    let mut xml = String::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).unwrap();
+        if entry.name().ends_with(".xml") {
+            entry.read_to_string(&mut xml).unwrap();
+        }
+    }
+    assert!(xml.contains("<?xml-stylesheet"), "{xml}");
+    // Not the sanitized copy the transforms get
+    assert!(xml.contains("Satir&#x1F;sonu"), "{xml}");
+}
+
+#[tokio::test]
+async fn missing_fixture_is_reported_per_item() {
+    let tmp = tempfile::tempdir().unwrap();
+    let server = server(&tmp).await;
 
     let response = server
-        .get("/api/v1/docs_from_objstore") // Note: POST request and full path
-        .json(&request_body) // Send JSON body
+        .get("/api/v1/docs_from_objstore")
+        .json(&request(vec![
+            object_id(1),
+            "no-such-invoice.xml.xz".to_string(),
+        ]))
         .await;
 
-    // 5. Assertions
+    let body: ResponseInvoicesForConversion = response.json();
+    assert_eq!(body.docs_count, 1);
+    let failed = &body.item_statuses[1];
+    assert_eq!(failed.object_id, "no-such-invoice.xml.xz");
+    assert_eq!(failed.error_code, 2003);
+}
+
+#[tokio::test]
+async fn prefetched_items_keep_request_order() {
+    let tmp = tempfile::tempdir().unwrap();
+    let server = server(&tmp).await;
 
-    // First, assert the status code
-    response.assert_status_ok(); // Assert status is 200 OK
+    // More items than the default prefetch depth of 8
+    let mut object_ids: Vec<String> = (0..20).map(|n| object_id(n % 2 + 1)).collect();
+    object_ids[13] = "no-such-invoice.xml.xz".to_string();
+    let response = server
+        .get("/api/v1/docs_from_objstore")
+        .json(&request(object_ids.clone()))
+        .await;
 
-    /*
-    // Then, assert the JSON body
-    let json_response: DocsFromObjStoreResponse = response.json(); // Deserialize response body
+    let body: ResponseInvoicesForConversion = response.json();
+    assert_eq!(body.docs_count, 19);
+    let statuses: Vec<(&str, i32)> = body
+        .item_statuses
+        .iter()
+        .map(|s| (s.object_id.as_str(), s.error_code))
+        .collect();
+    let expected: Vec<(&str, i32)> = object_ids
+        .iter()
+        .enumerate()
+        .map(|(n, id)| (id.as_str(), if n == 13 { 2003 } else { 0 }))
+        .collect();
+    assert_eq!(statuses, expected);
+    assert_eq!(
+        body.item_statuses
+            .iter()
+            .map(|s| s.sira_no)
+            .collect::<Vec<_>>(),
+        (1..=20).map(Some).collect::<Vec<_>>()
+    );
+}
 
-    // Now you can assert individual fields of the response struct
-    assert_eq!(json_response.docs_count, 2);
-    assert_eq!(json_response.size, 1024);
-    assert_eq!(json_response.request_fully_completed, true);
-    assert_eq!(json_response.last_processed_sira_no, Some(12345));
-    // You might also want to assert the content of `data` if it's predictable
-    assert_eq!(json_response.data, b"some_base64_encoded_zip_data".to_vec());
-    */
+#[tokio::test]
+async fn transform_workers_return_their_permits() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = AppConfig::default();
+    config.database.host = "unused".to_string();
+    config.database.database = "uut_25_1".to_string();
+    config.database.user = "unused".to_string();
+    config.database.password = Secret::new("unused");
+    config.object_store.backend = ObjectStoreBackend::Fs;
+    config.object_store.dir = FIXTURES.to_string();
+    config.xslt_disk_cache.dir = tmp.path().join("xslt").to_string_lossy().to_string();
+    config.jobs.dir = tmp.path().join("jobs").to_string_lossy().to_string();
+    // The request's permit plus one extra transform worker, not the configured 4
+    config.server.max_blocking_tasks = 2;
+    config.validate().unwrap();
+    let state = init_app_state(&config).await.unwrap();
+    let server = TestServer::new(create_app(state.clone())).unwrap();
+
+    let object_ids: Vec<String> = (0..10).map(|n| object_id(n % 2 + 1)).collect();
+    let response = server
+        .get("/api/v1/docs_from_objstore")
+        .json(&request(object_ids))
+        .await;
+    response.assert_status_ok();
+    let body: ResponseInvoicesForConversion = response.json();
+    assert_eq!(body.docs_count, 10);
+    assert_eq!(state.blocking_limiter.available_permits(), 2);
 }
@@ -0,0 +1,90 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionError, InvoiceConversionResult, convert_invoices,
+};
+use crate::utils::download_request::download_request_types::DownloadDocRequest;
+use crate::utils::errors::download_request_errors::{
+    DownloadRequestError, ErrCtx as DownloadRequestErrCtx,
+};
+use crate::utils::rest_handlers::stream_invoices_handler::header_value;
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tokio_util::sync::CancellationToken;
+
+impl IntoResponse for DownloadRequestError {
+    fn into_response(self) -> Response {
+        let body = Json(InvoiceConversionError {
+            error_code: self.error_code(),
+            error_msg: self.to_string(),
+        });
+        (self.http_status(), body).into_response()
+    }
+}
+
+/// `GET /api/v1/download_docs`: converts the invoices received by `source_vkntckn` after
+/// `after_this` and answers with the archive itself (206 when it was cut short).
+/// Continue with `x-last-processed-sira-no` as the next `after_this`.
+pub async fn download_docs_handler(
+    State(state): State<SharedState>,
+    Json(request): Json<DownloadDocRequest>,
+) -> Result<Response, DownloadRequestError> {
+    let db_name = request
+        .extract_dbname(&state.tenant_databases)
+        .ctx("download_docs_handler")?;
+
+    let token = CancellationToken::new();
+    let _cancel_on_drop = token.clone().drop_guard();
+
+    let conversion_request = request.into_conversion_request(db_name);
+    // Waits for this client's turn, ServerBusyError when the queue is full or times out
+    let permit = state
+        .scheduler
+        .acquire(conversion_request.client_id.as_deref())
+        .await?;
+    let request_id = conversion_request.request_id.clone();
+    let result = convert_invoices(state.clone(), conversion_request, permit, token).await?;
+
+    Ok(archive_response(result, &request_id))
+}
+
+/// The archive as the body, the paging state in `x-` headers.
+/// `x-last-processed-sira-no` is left out when nothing was written (empty page).
+pub(crate) fn archive_response(result: InvoiceConversionResult, request_id: &str) -> Response {
+    let status = if result.request_fully_completed {
+        StatusCode::OK
+    } else {
+        StatusCode::PARTIAL_CONTENT
+    };
+    let compression_type = result.compression_type;
+    let mut response = (status, result.data).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(compression_type.content_type()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        header_value(&format!(
+            "attachment; filename=\"{}.{}\"",
+            request_id,
+            compression_type.file_extension()
+        )),
+    );
+    headers.insert("x-docs-count", HeaderValue::from(result.docs_count));
+    if let Some(sira_no) = result.last_processed_sira_no {
+        headers.insert("x-last-processed-sira-no", HeaderValue::from(sira_no));
+    }
+    headers.insert(
+        "x-request-fully-completed",
+        HeaderValue::from_static(if result.request_fully_completed {
+            "true"
+        } else {
+            "false"
+        }),
+    );
+    response
+}
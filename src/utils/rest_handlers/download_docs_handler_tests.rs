@@ -0,0 +1,34 @@
+use super::download_docs_handler::archive_response;
+use crate::utils::common::target_types_and_formats::TargetCompressionType;
+use crate::utils::convert_invoices::invoice_conversion_manager::InvoiceConversionResult;
+use axum::http::StatusCode;
+
+#[test]
+fn empty_page_has_no_cursor_header() {
+    let result = InvoiceConversionResult {
+        request_fully_completed: true,
+        compression_type: TargetCompressionType::Zip,
+        ..Default::default()
+    };
+    let response = archive_response(result, "1950031086_25000");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["x-docs-count"], "0");
+    assert_eq!(headers["x-request-fully-completed"], "true");
+    assert!(!headers.contains_key("x-last-processed-sira-no"));
+}
+
+#[test]
+fn cursor_header_follows_the_last_written_document() {
+    let result = InvoiceConversionResult {
+        docs_count: 2,
+        last_processed_sira_no: Some(25002),
+        request_fully_completed: false,
+        ..Default::default()
+    };
+    let response = archive_response(result, "1950031086_25000");
+
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["x-last-processed-sira-no"], "25002");
+}
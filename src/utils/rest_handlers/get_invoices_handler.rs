@@ -2,11 +2,13 @@ use crate::utils::appstate::appstate::SharedState;
 use crate::utils::common::target_types_and_formats::{
     FilenameInZipMode, TargetCompressionType, TargetType,
 };
+use crate::utils::convert_invoices::incoming_invoice_page::IncomingInvoicePage;
 use crate::utils::convert_invoices::invoice_conversion_manager::{
-    InvoiceConversionError, InvoiceConversionResult, InvoiceItemForConversion,
-    InvoicesForConversion, convert_invoices,
+    ConvertedDocument, InvoiceConversionError, InvoiceConversionResult, InvoiceItemForConversion,
+    InvoiceItemStatus, InvoicesForConversion, convert_invoices,
 };
 use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::xslt_engine::XsltEngineKind;
 use axum::{
     Json,
     extract::State,
@@ -38,13 +40,28 @@ impl From<RequestInvoiceItemForConversion> for InvoiceItemForConversion {
 pub struct RequestInvoicesForConversion {
     pub target_type: TargetType,
     pub target_compression_type: TargetCompressionType,
+    /// Not needed with `incoming_invoice`, the year comes from the records
+    #[serde(default)]
     pub year: String,
     #[serde(default)]
     pub filename_in_zip: FilenameInZipMode,
     pub request_id: String,
     pub client_id: Option<String>,
+    /// "xrust" or "libxslt", tried first; the server chain remains as fallback
+    #[serde(default)]
+    pub xslt_engine: Option<XsltEngineKind>,
+    /// Skip failing invoices and report them in `item_statuses`
+    #[serde(default)]
+    pub continue_on_error: bool,
+    /// Ubl, UblXsltSeparate: restore the `-sanitized-…--` markers of the stored XML
+    #[serde(default)]
+    pub desanitize: bool,
+    /// Server side paging over INCOMING_INVOICE instead of `items`
+    #[serde(default)]
+    pub incoming_invoice: Option<IncomingInvoicePage>,
 
     /// Items to fetch/process
+    #[serde(default)]
     pub items: Vec<RequestInvoiceItemForConversion>,
 }
 impl From<RequestInvoicesForConversion> for InvoicesForConversion {
@@ -56,6 +73,10 @@ impl From<RequestInvoicesForConversion> for InvoicesForConversion {
             filename_in_zip: req.filename_in_zip,
             request_id: req.request_id,
             client_id: req.client_id,
+            xslt_engine: req.xslt_engine,
+            continue_on_error: req.continue_on_error,
+            desanitize: req.desanitize,
+            incoming_invoice: req.incoming_invoice,
             items: req
                 .items
                 .into_iter()
@@ -74,13 +95,22 @@ pub struct ResponseInvoicesForConversion {
     pub data: Vec<u8>,
 
     /// Number of docs included
-    pub docs_count: u8,
+    pub docs_count: u32,
 
     /// Total byte size of the ZIP (or payload) in bytes
     pub size: u64,
 
     pub last_processed_sira_no: Option<u64>,
     pub request_fully_completed: bool,
+
+    /// Archive extension of `data`: "zip", "tar.xz" or "tar.gz"
+    pub file_extension: String,
+    /// Content type of `data`, e.g. "application/zip"
+    pub content_type: String,
+    /// Documents in the archive and the XSLT engine that produced each
+    pub documents: Vec<ConvertedDocument>,
+    /// Every processed item with its error_code (0 = converted)
+    pub item_statuses: Vec<InvoiceItemStatus>,
 }
 impl From<InvoiceConversionResult> for ResponseInvoicesForConversion {
     fn from(response: InvoiceConversionResult) -> Self {
@@ -90,6 +120,10 @@ impl From<InvoiceConversionResult> for ResponseInvoicesForConversion {
             size: response.size,
             last_processed_sira_no: response.last_processed_sira_no,
             request_fully_completed: response.request_fully_completed,
+            file_extension: response.compression_type.file_extension().to_string(),
+            content_type: response.compression_type.content_type().to_string(),
+            documents: response.documents,
+            item_statuses: response.item_statuses,
         }
     }
 }
@@ -107,16 +141,11 @@ pub async fn get_invoices_handler(
     State(state): State<SharedState>,
     Json(request): Json<RequestInvoicesForConversion>,
 ) -> Result<(StatusCode, Json<ResponseInvoicesForConversion>), InvConvError> {
-    // Try to acquire without waiting; fail fast if saturated.
+    // Waits for this client's turn, ServerBusyError when the queue is full or times out
     let permit = state
-        .blocking_limiter
-        .clone()
-        .try_acquire_owned()
-        .map_err(|_| {
-            InvConvError::ServerBusyError(
-                "Server is handling the maximum number of heavy tasks. Please retry.".to_string(),
-            )
-        })?;
+        .scheduler
+        .acquire(request.client_id.as_deref())
+        .await?;
 
     let token = CancellationToken::new();
     let _cancel_on_drop = token.clone().drop_guard(); // guard borrows a clone
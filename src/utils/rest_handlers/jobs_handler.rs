@@ -0,0 +1,90 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionError, InvoicesForConversion,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::errors::job_errors::JobError;
+use crate::utils::jobs::conversion_jobs::JobStatus;
+use crate::utils::rest_handlers::get_invoices_handler::RequestInvoicesForConversion;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tokio_util::io::ReaderStream;
+
+impl IntoResponse for JobError {
+    fn into_response(self) -> Response {
+        let body = Json(InvoiceConversionError {
+            error_code: self.error_code(),
+            error_msg: self.to_string(),
+        });
+        (self.http_status(), body).into_response()
+    }
+}
+
+/// `POST /api/v1/jobs`: same body as `/docs_from_objstore`, answers 202 with the job status
+/// at once, 429 when `jobs.max_queued_jobs` are waiting. The job goes on when the client disconnects.
+pub async fn submit_job_handler(
+    State(state): State<SharedState>,
+    Json(request): Json<RequestInvoicesForConversion>,
+) -> Result<(StatusCode, Json<JobStatus>), InvConvError> {
+    let request: InvoicesForConversion = request.into();
+    let status = state.jobs.submit(state.clone(), request)?;
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// `GET /api/v1/jobs/{id}`
+pub async fn job_status_handler(
+    State(state): State<SharedState>,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobStatus>, JobError> {
+    Ok(Json(state.jobs.status(&job_id)?))
+}
+
+/// `GET /api/v1/jobs/{id}/result`: the archive, streamed from disk.
+pub async fn job_result_handler(
+    State(state): State<SharedState>,
+    Path(job_id): Path<String>,
+) -> Result<Response, JobError> {
+    let result = state.jobs.result(&job_id)?;
+    let file = tokio::fs::File::open(&result.path)
+        .await
+        .map_err(|_| JobError::NoResult(job_id.clone()))?;
+    let len = file.metadata().await?.len();
+
+    let mut response = Body::from_stream(ReaderStream::new(file)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(result.compression_type.content_type()),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"",
+        result.request_id,
+        result.compression_type.file_extension()
+    );
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    headers.insert(
+        "x-request-fully-completed",
+        HeaderValue::from_static(if result.request_fully_completed {
+            "true"
+        } else {
+            "false"
+        }),
+    );
+    Ok(response)
+}
+
+/// `DELETE /api/v1/jobs/{id}`: cancels a queued or running job, 409 for a finished one.
+pub async fn cancel_job_handler(
+    State(state): State<SharedState>,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobStatus>, JobError> {
+    Ok(Json(state.jobs.cancel(&job_id)?))
+}
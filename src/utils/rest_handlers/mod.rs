@@ -1,4 +1,12 @@
+pub mod download_docs_handler;
 pub mod get_invoices_handler;
+pub mod jobs_handler;
+pub mod stream_invoices_handler;
+pub mod xslt_cache_stats_handler;
 
-//#[cfg(test)]
-//mod docs_from_objstore_handler_tests;
+#[cfg(test)]
+mod docs_from_objstore_handler_tests;
+#[cfg(test)]
+mod download_docs_handler_tests;
+#[cfg(test)]
+mod stream_invoices_handler_tests;
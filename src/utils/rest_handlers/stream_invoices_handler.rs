@@ -0,0 +1,230 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::convert_invoices::invoice_conversion_manager::{
+    InvoiceConversionResult, InvoicesForConversion, convert_invoices_streamed,
+};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::rest_handlers::get_invoices_handler::RequestInvoicesForConversion;
+use axum::{
+    Json,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, HeaderValue, Version, header},
+    response::{IntoResponse, Response},
+};
+use http_body::Frame;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::bytes::Bytes;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Archive chunks buffered between the worker and the socket
+const STREAM_CHANNEL_CAPACITY: usize = 4;
+
+/// Trailers sent after the last archive byte
+const TRAILER_NAMES: &str =
+    "x-docs-count, x-last-processed-sira-no, x-request-fully-completed, x-error-code, x-error-msg";
+
+type ConversionOutcome = Result<InvoiceConversionResult, InvConvError>;
+
+/// Same request as `/docs_from_objstore`, but the archive itself is the
+/// response body and is sent while the invoices are still converted.
+///
+/// Errors before the first archive byte get the usual JSON error response.
+/// After that the status is 200 and `manifest.json` in the archive has the outcome.
+/// Clients that send `TE: trailers` (and every HTTP/2 client) also get it in the
+/// trailers (`x-request-fully-completed`, or `x-error-code` / `x-error-msg` when the
+/// archive was cut short). hyper drops trailers for other HTTP/1.1 clients, so for them
+/// a failure after the first byte aborts the body instead of ending it cleanly: the 200
+/// is already sent, and the only sign of the error is the incomplete transfer.
+pub async fn stream_invoices_handler(
+    State(state): State<SharedState>,
+    version: Version,
+    request_headers: HeaderMap,
+    Json(request): Json<RequestInvoicesForConversion>,
+) -> Result<Response, InvConvError> {
+    let trailers = accepts_trailers(version, &request_headers);
+    // Waits for this client's turn, ServerBusyError when the queue is full or times out
+    let permit = state
+        .scheduler
+        .acquire(request.client_id.as_deref())
+        .await?;
+
+    let token = CancellationToken::new();
+    // Moves into the body: cancels the conversion when the client goes away
+    let cancel_on_drop = token.clone().drop_guard();
+
+    let request: InvoicesForConversion = request.into();
+    let request_id = request.request_id.clone();
+    let compression_type = request.target_compression_type;
+
+    let (chunk_tx, mut chunk_rx) = mpsc::channel::<Bytes>(STREAM_CHANNEL_CAPACITY);
+    let (result_tx, mut result_rx) = oneshot::channel::<ConversionOutcome>();
+    tokio::spawn(async move {
+        let outcome =
+            convert_invoices_streamed(state, request, permit, token, chunk_tx, None).await;
+        let _ = result_tx.send(outcome);
+    });
+
+    // Wait for the first chunk so early failures still get a proper status code
+    let mut first_chunk: Option<Bytes> = None;
+    let mut early_result: Option<ConversionOutcome> = None;
+    tokio::select! {
+        biased;
+        chunk = chunk_rx.recv() => first_chunk = chunk,
+        outcome = &mut result_rx => {
+            early_result = Some(outcome.unwrap_or_else(|_| Err(worker_gone())));
+        }
+    }
+    if first_chunk.is_none() && early_result.is_none() {
+        // Channel closed without data, the outcome tells why
+        early_result = Some(
+            (&mut result_rx)
+                .await
+                .unwrap_or_else(|_| Err(worker_gone())),
+        );
+    }
+    let outcome = match early_result {
+        Some(Err(e)) if first_chunk.is_none() => return Err(e),
+        Some(outcome) => {
+            let (tx, rx) = oneshot::channel();
+            let _ = tx.send(outcome);
+            rx
+        }
+        None => result_rx,
+    };
+
+    let body = ArchiveStreamBody {
+        first_chunk,
+        chunks: chunk_rx,
+        outcome: Some(outcome),
+        trailers,
+        _cancel_on_drop: cancel_on_drop,
+    };
+
+    let mut response = Body::new(body).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(compression_type.content_type()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        header_value(&format!(
+            "attachment; filename=\"{}.{}\"",
+            request_id,
+            compression_type.file_extension()
+        )),
+    );
+    headers.insert("x-request-id", header_value(&request_id));
+    if trailers {
+        headers.insert(header::TRAILER, HeaderValue::from_static(TRAILER_NAMES));
+    }
+    Ok(response)
+}
+
+/// hyper only sends trailers on HTTP/1.1 when the request has `TE: trailers`
+pub(crate) fn accepts_trailers(version: Version, headers: &HeaderMap) -> bool {
+    version >= Version::HTTP_2
+        || headers
+            .get_all(header::TE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|coding| {
+                let name = coding.split(';').next().unwrap_or_default();
+                name.trim().eq_ignore_ascii_case("trailers")
+            })
+}
+
+/// Archive chunks as data frames, then the conversion outcome as trailers
+/// or, without trailers, an aborted body when the conversion failed.
+pub(crate) struct ArchiveStreamBody {
+    pub(crate) first_chunk: Option<Bytes>,
+    pub(crate) chunks: mpsc::Receiver<Bytes>,
+    pub(crate) outcome: Option<oneshot::Receiver<ConversionOutcome>>,
+    /// The client reads trailers, see `accepts_trailers`
+    pub(crate) trailers: bool,
+    pub(crate) _cancel_on_drop: DropGuard,
+}
+
+impl http_body::Body for ArchiveStreamBody {
+    type Data = Bytes;
+    type Error = InvConvError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, InvConvError>>> {
+        let this = self.get_mut();
+        if let Some(chunk) = this.first_chunk.take() {
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+        if let Some(chunk) = ready!(this.chunks.poll_recv(cx)) {
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+        // Archive done (or cut short), finish with the outcome
+        let Some(outcome_rx) = this.outcome.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let outcome = ready!(Pin::new(outcome_rx).poll(cx)).unwrap_or_else(|_| Err(worker_gone()));
+        this.outcome = None;
+        match outcome {
+            _ if this.trailers => {
+                Poll::Ready(Some(Ok(Frame::trailers(outcome_trailers(&outcome)))))
+            }
+            // hyper closes the connection, the client sees an incomplete transfer
+            Err(e) => Poll::Ready(Some(Err(e))),
+            Ok(_) => Poll::Ready(None),
+        }
+    }
+}
+
+fn outcome_trailers(outcome: &ConversionOutcome) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    match outcome {
+        Ok(result) => {
+            trailers.insert("x-docs-count", HeaderValue::from(result.docs_count));
+            if let Some(sira_no) = result.last_processed_sira_no {
+                trailers.insert("x-last-processed-sira-no", HeaderValue::from(sira_no));
+            }
+            trailers.insert(
+                "x-request-fully-completed",
+                HeaderValue::from_static(if result.request_fully_completed {
+                    "true"
+                } else {
+                    "false"
+                }),
+            );
+        }
+        Err(e) => {
+            trailers.insert(
+                "x-request-fully-completed",
+                HeaderValue::from_static("false"),
+            );
+            trailers.insert("x-error-code", HeaderValue::from(e.error_code()));
+            trailers.insert("x-error-msg", header_value(&e.to_string()));
+        }
+    }
+    trailers
+}
+
+/// Header values must be visible ASCII, anything else becomes '?'.
+pub(crate) fn header_value(s: &str) -> HeaderValue {
+    let cleaned: String = s
+        .chars()
+        .map(|c| {
+            if c == ' ' || c.is_ascii_graphic() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    HeaderValue::from_str(&cleaned).unwrap_or_else(|_| HeaderValue::from_static("?"))
+}
+
+fn worker_gone() -> InvConvError {
+    InvConvError::TaskJoinError("Conversion task ended without a result".to_string())
+}
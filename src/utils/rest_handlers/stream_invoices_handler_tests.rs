@@ -0,0 +1,80 @@
+use super::stream_invoices_handler::{ArchiveStreamBody, accepts_trailers};
+use crate::utils::convert_invoices::invoice_conversion_manager::InvoiceConversionResult;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use axum::http::{HeaderMap, HeaderValue, Version, header};
+use http_body::{Body, Frame};
+use std::pin::Pin;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::bytes::Bytes;
+use tokio_util::sync::CancellationToken;
+
+fn te(value: &'static str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::TE, HeaderValue::from_static(value));
+    headers
+}
+
+#[test]
+fn trailers_need_te_on_http1() {
+    assert!(!accepts_trailers(Version::HTTP_11, &HeaderMap::new()));
+    assert!(!accepts_trailers(Version::HTTP_11, &te("gzip")));
+    assert!(accepts_trailers(Version::HTTP_11, &te("trailers")));
+    assert!(accepts_trailers(
+        Version::HTTP_11,
+        &te("gzip;q=0.5, Trailers")
+    ));
+    assert!(accepts_trailers(Version::HTTP_2, &HeaderMap::new()));
+}
+
+/// Frames of a body over one chunk that ends with `outcome`
+async fn frames(
+    trailers: bool,
+    outcome: Result<InvoiceConversionResult, InvConvError>,
+) -> Vec<Result<Frame<Bytes>, InvConvError>> {
+    let (chunk_tx, chunks) = mpsc::channel(1);
+    chunk_tx.send(Bytes::from_static(b"PK")).await.unwrap();
+    drop(chunk_tx);
+    let (outcome_tx, outcome_rx) = oneshot::channel();
+    outcome_tx.send(outcome).unwrap();
+
+    let mut body = ArchiveStreamBody {
+        first_chunk: None,
+        chunks,
+        outcome: Some(outcome_rx),
+        trailers,
+        _cancel_on_drop: CancellationToken::new().drop_guard(),
+    };
+    let mut frames = vec![];
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        frames.push(frame);
+    }
+    frames
+}
+
+fn failed() -> Result<InvoiceConversionResult, InvConvError> {
+    Err(InvConvError::TaskJoinError("worker panicked".to_string()))
+}
+
+#[tokio::test]
+async fn failure_goes_into_the_trailers_when_asked_for() {
+    let frames = frames(true, failed()).await;
+    assert_eq!(frames.len(), 2);
+    let trailers = frames[1].as_ref().unwrap().trailers_ref().unwrap();
+    assert_eq!(trailers["x-request-fully-completed"], "false");
+    assert_eq!(trailers["x-error-code"], "1003");
+}
+
+#[tokio::test]
+async fn failure_aborts_the_body_without_trailers() {
+    let frames = frames(false, failed()).await;
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].as_ref().unwrap().is_data());
+    assert!(matches!(frames[1], Err(InvConvError::TaskJoinError(_))));
+}
+
+#[tokio::test]
+async fn success_ends_cleanly_without_trailers() {
+    let frames = frames(false, Ok(InvoiceConversionResult::default())).await;
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].as_ref().unwrap().is_data());
+}
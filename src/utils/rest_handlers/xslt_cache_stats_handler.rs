@@ -0,0 +1,8 @@
+use crate::utils::appstate::appstate::SharedState;
+use crate::utils::common::xslt_cache::XsltCacheStats;
+use axum::{Json, extract::State};
+
+/// Hit/miss counters and current size of the process-wide XSLT cache.
+pub async fn xslt_cache_stats_handler(State(state): State<SharedState>) -> Json<XsltCacheStats> {
+    Json(state.xslt_cache.stats())
+}
@@ -0,0 +1,362 @@
+use crate::utils::config::app_config::secs;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, oneshot};
+
+/// Requests without `client_id` share this client
+pub const ANONYMOUS_CLIENT: &str = "";
+
+/// Who gets the next `blocking_limiter` permit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// Conversions one client may run at once, 0 for no limit
+    pub max_running_per_client: usize,
+    /// Requests waiting for a permit, more are refused with `ServerBusyError`; jobs do not count
+    pub queue_capacity: usize,
+    /// Longest wait for a permit before `ServerBusyError`
+    #[serde(rename = "queue_timeout_secs", with = "secs")]
+    pub queue_timeout: Duration,
+    /// Share of the permits under contention, clients not listed weigh 1
+    pub weights: HashMap<String, u32>,
+    /// Per client `max_running_per_client`
+    pub quotas: HashMap<String, usize>,
+}
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_running_per_client: 16,
+            queue_capacity: 256,
+            queue_timeout: Duration::from_secs(30),
+            weights: HashMap::new(),
+            quotas: HashMap::new(),
+        }
+    }
+}
+
+/// Hands out the permits of `blocking_limiter` fairly between clients.
+///
+/// A free permit goes to the waiting client with the fewest running conversions per
+/// unit of weight whose quota allows one more, ties to the longest waiting request.
+/// Requests wait in a bounded queue up to `queue_timeout` instead of failing at once.
+#[derive(Debug, Clone)]
+pub struct FairScheduler {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    semaphore: Arc<Semaphore>,
+    config: SchedulerConfig,
+    state: Mutex<SchedulerState>,
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    clients: HashMap<String, ClientState>,
+    next_ticket: u64,
+    /// Waiters that count against `queue_capacity`
+    queued: usize,
+}
+
+#[derive(Debug, Default)]
+struct ClientState {
+    running: usize,
+    waiters: VecDeque<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    bounded: bool,
+    tx: oneshot::Sender<ConversionPermit>,
+}
+
+/// A `blocking_limiter` permit held for `client`, the next waiter is served on drop.
+#[derive(Debug)]
+pub struct ConversionPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    client: String,
+    inner: Arc<Inner>,
+}
+
+impl FairScheduler {
+    /// `semaphore` is the global budget, `blocking_limiter`
+    pub fn new(semaphore: Arc<Semaphore>, config: SchedulerConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                semaphore,
+                config,
+                state: Mutex::new(SchedulerState::default()),
+            }),
+        }
+    }
+
+    /// Waits in the bounded queue up to `queue_timeout`.
+    /// `ServerBusyError` when the queue is full or the wait times out.
+    pub async fn acquire(&self, client_id: Option<&str>) -> Result<ConversionPermit, InvConvError> {
+        let client = client_id.unwrap_or(ANONYMOUS_CLIENT);
+        let (ticket, mut rx) = self.enqueue(client, true);
+        if let Ok(permit) = rx.try_recv() {
+            return Ok(permit);
+        }
+        {
+            let mut state = self.inner.lock();
+            if state.queued > self.inner.config.queue_capacity {
+                state.remove_waiter(client, ticket);
+                drop(state);
+                // Served between the check and the removal
+                return rx.try_recv().map_err(|_| {
+                    InvConvError::ServerBusyError(format!(
+                        "Server is handling the maximum number of heavy tasks and {} queued requests. Please retry.",
+                        self.inner.config.queue_capacity
+                    ))
+                });
+            }
+        }
+
+        let mut waiting = WaitGuard {
+            inner: &self.inner,
+            client,
+            ticket,
+        };
+        match tokio::time::timeout(self.inner.config.queue_timeout, &mut rx).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(InvConvError::ServerBusyError(
+                "Scheduler dropped the request. Please retry.".to_string(),
+            )),
+            Err(_) => {
+                waiting.cancel();
+                rx.try_recv().map_err(|_| {
+                    InvConvError::ServerBusyError(format!(
+                        "No conversion slot within {}s. Please retry.",
+                        self.inner.config.queue_timeout.as_secs()
+                    ))
+                })
+            }
+        }
+    }
+
+    /// Waits as long as it takes, outside `queue_capacity`; for detached jobs.
+    /// Dropping the future leaves the queue.
+    pub async fn acquire_queued(
+        &self,
+        client_id: Option<&str>,
+    ) -> Result<ConversionPermit, InvConvError> {
+        let client = client_id.unwrap_or(ANONYMOUS_CLIENT);
+        let (ticket, rx) = self.enqueue(client, false);
+        let _waiting = WaitGuard {
+            inner: &self.inner,
+            client,
+            ticket,
+        };
+        rx.await.map_err(|_| {
+            InvConvError::ServerBusyError(
+                "Scheduler dropped the request. Please retry.".to_string(),
+            )
+        })
+    }
+
+    /// Up to `wanted` more permits for a running conversion of the client, only free ones
+    /// within its quota and only while nobody waits
+    pub fn try_acquire_extra(
+        &self,
+        client_id: Option<&str>,
+        wanted: usize,
+    ) -> Vec<ConversionPermit> {
+        let client = client_id.unwrap_or(ANONYMOUS_CLIENT);
+        let mut state = self.inner.lock();
+        if state.clients.values().any(|c| !c.waiters.is_empty()) {
+            return vec![];
+        }
+        let quota = self.inner.quota(client);
+        let mut permits = Vec::with_capacity(wanted);
+        while permits.len() < wanted {
+            let entry = state.clients.entry(client.to_string()).or_default();
+            if entry.running >= quota {
+                break;
+            }
+            let Ok(permit) = self.inner.semaphore.clone().try_acquire_owned() else {
+                break;
+            };
+            entry.running += 1;
+            permits.push(ConversionPermit {
+                permit: Some(permit),
+                client: client.to_string(),
+                inner: self.inner.clone(),
+            });
+        }
+        state.forget_if_idle(client);
+        permits
+    }
+
+    /// Conversions running for `client_id`
+    pub fn running(&self, client_id: Option<&str>) -> usize {
+        let client = client_id.unwrap_or(ANONYMOUS_CLIENT);
+        self.inner
+            .lock()
+            .clients
+            .get(client)
+            .map_or(0, |c| c.running)
+    }
+
+    /// Requests waiting for a permit, jobs included
+    pub fn waiting(&self) -> usize {
+        self.inner
+            .lock()
+            .clients
+            .values()
+            .map(|c| c.waiters.len())
+            .sum()
+    }
+
+    /// Adds a waiter and serves whoever can run now, possibly this one
+    fn enqueue(&self, client: &str, bounded: bool) -> (u64, oneshot::Receiver<ConversionPermit>) {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.inner.lock();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queued += usize::from(bounded);
+        state
+            .clients
+            .entry(client.to_string())
+            .or_default()
+            .waiters
+            .push_back(Waiter {
+                ticket,
+                bounded,
+                tx,
+            });
+        self.inner.dispatch(&mut state);
+        (ticket, rx)
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        // The state stays consistent between statements, a panic elsewhere does not spoil it
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn quota(&self, client: &str) -> usize {
+        match self
+            .config
+            .quotas
+            .get(client)
+            .copied()
+            .unwrap_or(self.config.max_running_per_client)
+        {
+            0 => usize::MAX,
+            quota => quota,
+        }
+    }
+
+    fn weight(&self, client: &str) -> u64 {
+        self.config.weights.get(client).copied().unwrap_or(1).max(1) as u64
+    }
+
+    /// Client whose turn it is: fewest running per weight, then the oldest request
+    fn next_client(&self, state: &SchedulerState) -> Option<String> {
+        state
+            .clients
+            .iter()
+            .filter(|(client, c)| !c.waiters.is_empty() && c.running < self.quota(client))
+            .min_by(|(a, ca), (b, cb)| {
+                // running_a / weight_a against running_b / weight_b, without floats
+                let share_a = ca.running as u64 * self.weight(b);
+                let share_b = cb.running as u64 * self.weight(a);
+                share_a
+                    .cmp(&share_b)
+                    .then(ca.waiters[0].ticket.cmp(&cb.waiters[0].ticket))
+            })
+            .map(|(client, _)| client.clone())
+    }
+
+    /// Hands free permits to waiters in fairness order
+    fn dispatch(self: &Arc<Self>, state: &mut SchedulerState) {
+        while let Some(client) = self.next_client(state) {
+            let Ok(permit) = self.semaphore.clone().try_acquire_owned() else {
+                return;
+            };
+            let Some(entry) = state.clients.get_mut(&client) else {
+                return;
+            };
+            let Some(waiter) = entry.waiters.pop_front() else {
+                continue;
+            };
+            entry.running += 1;
+            state.queued -= usize::from(waiter.bounded);
+
+            let granted = ConversionPermit {
+                permit: Some(permit),
+                client: client.clone(),
+                inner: self.clone(),
+            };
+            if let Err(mut unsent) = waiter.tx.send(granted) {
+                // Waiter gone, the permit goes back without re-entering `dispatch`
+                unsent.permit = None;
+                if let Some(entry) = state.clients.get_mut(&client) {
+                    entry.running -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl SchedulerState {
+    fn remove_waiter(&mut self, client: &str, ticket: u64) {
+        if let Some(entry) = self.clients.get_mut(client)
+            && let Some(pos) = entry.waiters.iter().position(|w| w.ticket == ticket)
+            && let Some(waiter) = entry.waiters.remove(pos)
+        {
+            self.queued -= usize::from(waiter.bounded);
+        }
+        self.forget_if_idle(client);
+    }
+
+    fn forget_if_idle(&mut self, client: &str) {
+        if self
+            .clients
+            .get(client)
+            .is_some_and(|c| c.running == 0 && c.waiters.is_empty())
+        {
+            self.clients.remove(client);
+        }
+    }
+}
+
+impl Drop for ConversionPermit {
+    fn drop(&mut self) {
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+        let mut state = self.inner.lock();
+        if let Some(entry) = state.clients.get_mut(&self.client) {
+            entry.running = entry.running.saturating_sub(1);
+        }
+        state.forget_if_idle(&self.client);
+        drop(permit);
+        self.inner.dispatch(&mut state);
+    }
+}
+
+/// Leaves the queue when the waiting future is dropped or times out
+struct WaitGuard<'a> {
+    inner: &'a Arc<Inner>,
+    client: &'a str,
+    ticket: u64,
+}
+impl WaitGuard<'_> {
+    fn cancel(&mut self) {
+        self.inner.lock().remove_waiter(self.client, self.ticket);
+    }
+}
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        // No-op once served or cancelled, the ticket is gone
+        self.cancel();
+    }
+}
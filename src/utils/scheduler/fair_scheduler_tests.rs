@@ -0,0 +1,177 @@
+use super::fair_scheduler::{ConversionPermit, FairScheduler, SchedulerConfig};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Semaphore, mpsc};
+
+fn scheduler(permits: usize, config: SchedulerConfig) -> (FairScheduler, Arc<Semaphore>) {
+    let semaphore = Arc::new(Semaphore::new(permits));
+    (FairScheduler::new(semaphore.clone(), config), semaphore)
+}
+
+/// Queues `count` requests of `client`, their permits arrive on `tx` in grant order
+async fn queue(
+    scheduler: &FairScheduler,
+    client: &'static str,
+    count: usize,
+    tx: &mpsc::UnboundedSender<(&'static str, ConversionPermit)>,
+) {
+    for _ in 0..count {
+        let (waiter, tx) = (scheduler.clone(), tx.clone());
+        let before = scheduler.waiting();
+        tokio::spawn(async move {
+            let permit = waiter.acquire(Some(client)).await.unwrap();
+            let _ = tx.send((client, permit));
+        });
+        while scheduler.waiting() == before {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn free_permits_are_granted_at_once() {
+    let (scheduler, semaphore) = scheduler(2, SchedulerConfig::default());
+    let first = scheduler.acquire(Some("acme")).await.unwrap();
+    let second = scheduler.acquire(None).await.unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+    assert_eq!(scheduler.running(Some("acme")), 1);
+    assert_eq!(scheduler.running(None), 1);
+
+    drop((first, second));
+    assert_eq!(semaphore.available_permits(), 2);
+    assert_eq!(scheduler.running(Some("acme")), 0);
+}
+
+#[tokio::test]
+async fn client_quota_lets_other_clients_through() {
+    let config = SchedulerConfig {
+        max_running_per_client: 1,
+        ..Default::default()
+    };
+    let (scheduler, _) = scheduler(4, config);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let held = scheduler.acquire(Some("acme")).await.unwrap();
+    queue(&scheduler, "acme", 1, &tx).await;
+    assert_eq!(scheduler.running(Some("acme")), 1);
+
+    // Free permits but acme is at its quota, globex gets one
+    let other = scheduler.acquire(Some("globex")).await.unwrap();
+    assert_eq!(scheduler.waiting(), 1);
+
+    drop(held);
+    let (client, _permit) = rx.recv().await.unwrap();
+    assert_eq!(client, "acme");
+    assert_eq!(scheduler.waiting(), 0);
+    drop(other);
+}
+
+#[tokio::test]
+async fn weights_decide_the_share_under_contention() {
+    let mut config = SchedulerConfig {
+        max_running_per_client: 0,
+        ..Default::default()
+    };
+    config.weights.insert("gold".to_string(), 2);
+    let (scheduler, _) = scheduler(3, config);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut held = vec![
+        scheduler.acquire(Some("bronze")).await.unwrap(),
+        scheduler.acquire(Some("bronze")).await.unwrap(),
+        scheduler.acquire(Some("bronze")).await.unwrap(),
+    ];
+    queue(&scheduler, "bronze", 3, &tx).await;
+    queue(&scheduler, "gold", 3, &tx).await;
+
+    // bronze releases its permits one by one
+    let mut order = vec![];
+    let mut granted = vec![];
+    for _ in 0..3 {
+        held.pop();
+        let (client, permit) = rx.recv().await.unwrap();
+        order.push(client);
+        granted.push(permit);
+    }
+    // gold (weight 2) ends up with twice the running conversions of bronze
+    assert_eq!(order, ["gold", "gold", "bronze"]);
+    assert_eq!(scheduler.running(Some("gold")), 2);
+    assert_eq!(scheduler.running(Some("bronze")), 1);
+}
+
+#[tokio::test]
+async fn full_queue_is_refused_at_once() {
+    let config = SchedulerConfig {
+        queue_capacity: 1,
+        ..Default::default()
+    };
+    let (scheduler, _) = scheduler(1, config);
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let _held = scheduler.acquire(Some("acme")).await.unwrap();
+    queue(&scheduler, "globex", 1, &tx).await;
+    let err = scheduler.acquire(Some("initech")).await.unwrap_err();
+    assert!(matches!(err, InvConvError::ServerBusyError(_)));
+    assert_eq!(err.error_code(), 1002);
+    assert_eq!(scheduler.waiting(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn waiting_times_out_and_leaves_the_queue() {
+    let config = SchedulerConfig {
+        queue_timeout: Duration::from_secs(5),
+        ..Default::default()
+    };
+    let (scheduler, semaphore) = scheduler(1, config);
+
+    let held = scheduler.acquire(Some("acme")).await.unwrap();
+    let err = scheduler.acquire(Some("globex")).await.unwrap_err();
+    assert!(matches!(err, InvConvError::ServerBusyError(_)));
+    assert_eq!(scheduler.waiting(), 0);
+
+    drop(held);
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+#[tokio::test]
+async fn dropped_waiter_is_skipped() {
+    let (scheduler, semaphore) = scheduler(1, SchedulerConfig::default());
+    let held = scheduler.acquire(Some("acme")).await.unwrap();
+
+    let waiting = {
+        let scheduler = scheduler.clone();
+        tokio::spawn(async move { scheduler.acquire_queued(Some("globex")).await })
+    };
+    while scheduler.waiting() == 0 {
+        tokio::task::yield_now().await;
+    }
+    waiting.abort();
+    let _ = waiting.await;
+    assert_eq!(scheduler.waiting(), 0);
+
+    drop(held);
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+#[tokio::test]
+async fn extra_permits_only_when_nobody_waits() {
+    let config = SchedulerConfig {
+        max_running_per_client: 3,
+        ..Default::default()
+    };
+    let (scheduler, _) = scheduler(4, config);
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let _own = scheduler.acquire(Some("acme")).await.unwrap();
+    // Quota of 3: two more
+    let extra = scheduler.try_acquire_extra(Some("acme"), 5);
+    assert_eq!(extra.len(), 2);
+    drop(extra);
+
+    let _globex = scheduler.acquire(Some("globex")).await.unwrap();
+    let _globex_too = scheduler.acquire(Some("globex")).await.unwrap();
+    let _initech = scheduler.acquire(Some("initech")).await.unwrap();
+    queue(&scheduler, "initech", 1, &tx).await;
+    assert!(scheduler.try_acquire_extra(Some("acme"), 1).is_empty());
+}
@@ -0,0 +1,4 @@
+pub mod fair_scheduler;
+
+#[cfg(test)]
+mod fair_scheduler_tests;
@@ -0,0 +1,145 @@
+use crate::utils::common::xslt_cache::XsltCache;
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use crate::utils::xslt_engine::libxslt_engine::LibXsltEngine;
+use crate::utils::xslt_engine::xrust_engine::XrustEngine;
+use crate::utils::xslt_engine::xslt_engine::{XsltEngine, XsltEngineKind};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use tokio_util::bytes::Bytes;
+
+/// One engine of the chain with its own compiled stylesheet cache.
+///
+/// Compile failures are cached too, so later documents with the same
+/// stylesheet skip straight to the next engine.
+struct EngineSlot<E: XsltEngine> {
+    engine: E,
+    compiled: HashMap<String, Result<E::Compiled, String>>,
+}
+
+impl<E> EngineSlot<E>
+where
+    E: XsltEngine<Error = InvConvError>,
+{
+    fn new(engine: E) -> Self {
+        Self {
+            engine,
+            compiled: HashMap::with_capacity(4),
+        }
+    }
+
+    /// `compile` is only called on a local cache miss.
+    fn transform<F>(
+        &mut self,
+        xslt_key: &str,
+        xslt: &Bytes,
+        xml: &Bytes,
+        compile: F,
+    ) -> Result<Bytes, String>
+    where
+        F: FnOnce(&E, &Bytes) -> Result<E::Compiled, InvConvError>,
+    {
+        let compiled = match self.compiled.entry(xslt_key.to_string()) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(compile(&self.engine, xslt).map_err(|e| e.to_string())),
+        };
+        match compiled {
+            Ok(compiled) => self
+                .engine
+                .transform(compiled, xml)
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+enum ChainedEngine {
+    Xrust(EngineSlot<XrustEngine>),
+    LibXslt(EngineSlot<LibXsltEngine>),
+}
+
+impl ChainedEngine {
+    fn new(kind: XsltEngineKind) -> Self {
+        match kind {
+            XsltEngineKind::Xrust => ChainedEngine::Xrust(EngineSlot::new(XrustEngine::new())),
+            XsltEngineKind::LibXslt => {
+                ChainedEngine::LibXslt(EngineSlot::new(LibXsltEngine::new()))
+            }
+        }
+    }
+
+    fn kind(&self) -> XsltEngineKind {
+        match self {
+            ChainedEngine::Xrust(_) => XsltEngineKind::Xrust,
+            ChainedEngine::LibXslt(_) => XsltEngineKind::LibXslt,
+        }
+    }
+
+    fn transform(
+        &mut self,
+        xslt_key: &str,
+        xslt: &Bytes,
+        xml: &Bytes,
+        shared: Option<&XsltCache>,
+    ) -> Result<Bytes, String> {
+        match self {
+            ChainedEngine::Xrust(slot) => slot.transform(xslt_key, xslt, xml, |e, x| e.compile(x)),
+            // libxslt stylesheets are Send + Sync, reuse the process-wide compiled one
+            ChainedEngine::LibXslt(slot) => {
+                slot.transform(xslt_key, xslt, xml, |e, x| match shared {
+                    Some(cache) => cache.get_or_compile_libxslt(xslt_key, x, e),
+                    None => e.compile(x),
+                })
+            }
+        }
+    }
+}
+
+/// Ordered engines tried one after another for the same document.
+///
+/// The first engine that both compiles the stylesheet and transforms the
+/// document wins; its kind is returned so callers can record it.
+pub struct XsltEngineChain {
+    engines: Vec<ChainedEngine>,
+    shared: Option<Arc<XsltCache>>,
+}
+
+impl XsltEngineChain {
+    /// `shared` is the process-wide cache; `None` keeps compiled stylesheets local.
+    pub fn new(kinds: &[XsltEngineKind], shared: Option<Arc<XsltCache>>) -> Self {
+        let mut engines: Vec<ChainedEngine> = Vec::with_capacity(kinds.len());
+        for kind in kinds {
+            if !engines.iter().any(|e| e.kind() == *kind) {
+                engines.push(ChainedEngine::new(*kind));
+            }
+        }
+        if engines.is_empty() {
+            engines.push(ChainedEngine::new(XsltEngineKind::default()));
+        }
+        Self { engines, shared }
+    }
+
+    pub fn kinds(&self) -> Vec<XsltEngineKind> {
+        self.engines.iter().map(|e| e.kind()).collect()
+    }
+
+    /// Transform `xml`, falling back to the next engine on any compile/transform error.
+    pub fn transform(
+        &mut self,
+        xslt_key: &str,
+        xslt: &Bytes,
+        xml: &Bytes,
+    ) -> Result<(Bytes, XsltEngineKind), InvConvError> {
+        let mut failures: Vec<String> = Vec::with_capacity(self.engines.len());
+        for engine in self.engines.iter_mut() {
+            match engine.transform(xslt_key, xslt, xml, self.shared.as_deref()) {
+                Ok(out) => return Ok((out, engine.kind())),
+                Err(e) => failures.push(format!("{}: {e}", engine.kind())),
+            }
+        }
+        Err(InvConvError::XsltEnginesExhausted {
+            xslt_key: xslt_key.to_string(),
+            errors: failures.join(" | "),
+        })
+    }
+}
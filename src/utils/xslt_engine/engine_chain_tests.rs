@@ -0,0 +1,78 @@
+use super::engine_chain::XsltEngineChain;
+use super::xslt_engine::XsltEngineKind;
+use tokio_util::bytes::Bytes;
+
+const XML: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?><Invoice><ID>ABC2025000000001</ID><Amount>1234.5</Amount></Invoice>"#;
+
+const SIMPLE_XSLT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+  <xsl:template match="/"><p><xsl:value-of select="/Invoice/ID"/></p></xsl:template>
+</xsl:stylesheet>"#;
+
+// unparsed-entity-uri() is not implemented by xrust, libxslt handles it.
+const UNSUPPORTED_BY_XRUST_XSLT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+  <xsl:template match="/"><p><xsl:value-of select="unparsed-entity-uri('logo')"/><xsl:value-of select="/Invoice/ID"/></p></xsl:template>
+</xsl:stylesheet>"#;
+
+fn transform(
+    chain: &mut XsltEngineChain,
+    key: &str,
+    xslt: &'static [u8],
+) -> (String, XsltEngineKind) {
+    let (out, kind) = chain
+        .transform(key, &Bytes::from_static(xslt), &Bytes::from_static(XML))
+        .expect("chain failed");
+    (String::from_utf8(out.to_vec()).unwrap(), kind)
+}
+
+#[test]
+fn first_engine_wins_when_it_succeeds() {
+    let mut chain = XsltEngineChain::new(&[XsltEngineKind::LibXslt, XsltEngineKind::Xrust], None);
+    let (out, kind) = transform(&mut chain, "simple", SIMPLE_XSLT);
+    assert!(out.contains("ABC2025000000001"));
+    assert_eq!(kind, XsltEngineKind::LibXslt);
+}
+
+#[test]
+fn falls_back_to_next_engine() {
+    let mut chain = XsltEngineChain::new(&[XsltEngineKind::Xrust, XsltEngineKind::LibXslt], None);
+    let (out, kind) = transform(&mut chain, "unsupported", UNSUPPORTED_BY_XRUST_XSLT);
+    assert!(out.contains("<p>ABC2025000000001</p>"), "{out}");
+    assert_eq!(kind, XsltEngineKind::LibXslt);
+
+    // Same key again: still served by the fallback
+    let (_, kind) = transform(&mut chain, "unsupported", UNSUPPORTED_BY_XRUST_XSLT);
+    assert_eq!(kind, XsltEngineKind::LibXslt);
+}
+
+#[test]
+fn fails_only_when_every_engine_fails() {
+    let mut chain = XsltEngineChain::new(&[XsltEngineKind::Xrust, XsltEngineKind::LibXslt], None);
+    let err = chain
+        .transform(
+            "broken",
+            &Bytes::from_static(b"<not-a-stylesheet/>"),
+            &Bytes::from_static(XML),
+        )
+        .unwrap_err();
+    assert_eq!(err.error_code(), 2017);
+    let msg = err.to_string();
+    assert!(msg.contains("xrust:") && msg.contains("libxslt:"), "{msg}");
+}
+
+#[test]
+fn duplicate_engines_are_dropped() {
+    let chain = XsltEngineChain::new(
+        &[
+            XsltEngineKind::Xrust,
+            XsltEngineKind::Xrust,
+            XsltEngineKind::LibXslt,
+        ],
+        None,
+    );
+    assert_eq!(
+        chain.kinds(),
+        vec![XsltEngineKind::Xrust, XsltEngineKind::LibXslt]
+    );
+}
@@ -1,10 +1,45 @@
 use crate::utils::errors::invoice_conversion_errors::{ErrCtx, InvConvError};
 use crate::utils::xslt_engine::xslt_engine::XsltEngine;
 use libxml::parser::Parser;
-use libxslt::parser::parse_file;
-use libxslt::stylesheet::Stylesheet;
+use libxslt::bindings::{
+    xmlChar, xmlCopyDoc, xmlFree, xmlFreeDoc, xsltApplyStylesheet, xsltFreeStylesheet,
+    xsltParseStylesheetDoc, xsltSaveResultToString, xsltStylesheetPtr,
+};
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::Arc;
 use tokio_util::bytes::Bytes;
 
+/// What we store in the cache: shared libxslt stylesheet, freed with the last clone.
+///
+/// `libxslt::stylesheet::Stylesheet` neither frees its pointer nor honours
+/// `xsl:output` when serializing, so the engine talks to the bindings directly.
+#[derive(Clone)]
+pub struct LibXsltCompiledStylesheet {
+    inner: Arc<StylesheetPtr>,
+}
+
+impl LibXsltCompiledStylesheet {
+    fn ptr(&self) -> xsltStylesheetPtr {
+        self.inner.0
+    }
+}
+
+struct StylesheetPtr(xsltStylesheetPtr);
+
+// A compiled stylesheet is read-only after xsltParseStylesheetDoc; libxslt
+// allows concurrent xsltApplyStylesheet calls on it (each call builds its own
+// transform context). This is what lets the process-wide cache share it.
+unsafe impl Send for StylesheetPtr {}
+unsafe impl Sync for StylesheetPtr {}
+
+impl Drop for StylesheetPtr {
+    fn drop(&mut self) {
+        // The stylesheet owns (and frees) the document it was parsed from.
+        unsafe { xsltFreeStylesheet(self.0) }
+    }
+}
+
 pub struct LibXsltEngine;
 
 impl LibXsltEngine {
@@ -13,19 +48,89 @@ impl LibXsltEngine {
     }
 }
 
+impl Default for LibXsltEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl XsltEngine for LibXsltEngine {
-    type Compiled = Stylesheet;
+    type Compiled = LibXsltCompiledStylesheet;
     type Error = InvConvError;
 
-    fn compile(&self, _xslt: &Bytes) -> Result<Self::Compiled, Self::Error> {
-        Err(InvConvError::XRustXsltError(
-            "LibXsltEngine: compile not implemented".to_string(),
-        ))
+    fn compile(&self, xslt: &Bytes) -> Result<Self::Compiled, Self::Error> {
+        // Parser::default() also runs xmlInitParser once per process.
+        let style_doc = Parser::default()
+            .parse_string(xslt.as_ref())
+            .map_err(|e| InvConvError::LibXsltError(format!("{e:?}")))
+            .ctx("LibXsltEngine:compile:style_doc")?;
+
+        unsafe {
+            // The stylesheet takes ownership of its document, give it a private copy
+            // so `style_doc` can still be dropped by libxml.
+            let doc_copy = xmlCopyDoc(style_doc.doc_ptr(), 1);
+            if doc_copy.is_null() {
+                return Err(InvConvError::LibXsltError(
+                    "xmlCopyDoc returned NULL".to_string(),
+                ))
+                .ctx("LibXsltEngine:compile:copy_doc");
+            }
+
+            let ptr = xsltParseStylesheetDoc(doc_copy);
+            if ptr.is_null() {
+                xmlFreeDoc(doc_copy);
+                return Err(InvConvError::LibXsltError(
+                    "document is not a valid XSLT stylesheet".to_string(),
+                ))
+                .ctx("LibXsltEngine:compile:parse_stylesheet");
+            }
+
+            Ok(LibXsltCompiledStylesheet {
+                inner: Arc::new(StylesheetPtr(ptr)),
+            })
+        }
     }
 
-    fn transform(&self, _compiled: &Self::Compiled, xml: &Bytes) -> Result<Bytes, Self::Error> {
-        Err(InvConvError::XRustXsltError(
-            "LibXsltEngine: compile not implemented".to_string(),
-        ))
+    fn transform(&self, compiled: &Self::Compiled, xml: &Bytes) -> Result<Bytes, Self::Error> {
+        let src_doc = Parser::default()
+            .parse_string(xml.as_ref())
+            .map_err(|e| InvConvError::LibXsltError(format!("{e:?}")))
+            .ctx("LibXsltEngine:transform:src_doc")?;
+
+        unsafe {
+            let result_doc =
+                xsltApplyStylesheet(compiled.ptr(), src_doc.doc_ptr(), ptr::null_mut());
+            if result_doc.is_null() {
+                return Err(InvConvError::LibXsltError(
+                    "xsltApplyStylesheet failed".to_string(),
+                ))
+                .ctx("LibXsltEngine:transform:apply");
+            }
+
+            // Serialize honouring <xsl:output method="html" .../>
+            let mut out_ptr: *mut xmlChar = ptr::null_mut();
+            let mut out_len: c_int = 0;
+            let rc = xsltSaveResultToString(&mut out_ptr, &mut out_len, result_doc, compiled.ptr());
+            xmlFreeDoc(result_doc);
+
+            let out = if out_ptr.is_null() {
+                Vec::new()
+            } else {
+                let out = std::slice::from_raw_parts(out_ptr, out_len.max(0) as usize).to_vec();
+                if let Some(free) = xmlFree {
+                    free(out_ptr as *mut c_void);
+                }
+                out
+            };
+
+            if rc != 0 {
+                return Err(InvConvError::LibXsltError(format!(
+                    "xsltSaveResultToString returned {rc}"
+                )))
+                .ctx("LibXsltEngine:transform:save_result");
+            }
+
+            Ok(Bytes::from(out))
+        }
     }
 }
@@ -1,3 +1,10 @@
+pub mod engine_chain;
 pub mod libxslt_engine;
 pub mod xrust_engine;
+#[allow(clippy::module_inception)]
 pub mod xslt_engine;
+
+#[cfg(test)]
+mod engine_chain_tests;
+#[cfg(test)]
+mod xslt_engine_tests;
@@ -17,6 +17,7 @@ use crate::utils::xslt_engine::xslt_engine::XsltEngine;
 /// What we store in the cache: compiled stylesheet context.
 pub type XrustCompiledStylesheet = Context<RNode>;
 
+#[derive(Default)]
 pub struct XrustEngine;
 
 impl XrustEngine {
@@ -44,16 +45,16 @@ impl XsltEngine for XrustEngine {
 
         // Parse stylesheet XML into a document tree
         let style_doc = Self::parse_xml(xslt_str)
-            .map_err(|e| InvConvError::XRustXsltError((e.to_string())))
+            .map_err(|e| InvConvError::XRustXsltError(e.to_string()))
             .ctx("XrustEngine:compile:style_doc")?;
 
         // Compile stylesheet into a Context<RNode>.
         // from_document will resolve xsl:include/import using the parser you give it.
         let ctx = from_document(
             style_doc,
-            None,                   // base URI
-            |s| Self::parse_xml(s), // parser for included stylesheets
-            |_| Ok(String::new()),  // loader for external resources (if needed)
+            None,                  // base URI
+            Self::parse_xml,       // parser for included stylesheets
+            |_| Ok(String::new()), // loader for external resources (if needed)
         )
         .map_err(|e| InvConvError::XRustXsltError(e.to_string()))
         .ctx("XrustEngine:compile:ctx from_document")?;
@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 use tokio_util::bytes::Bytes;
 
 pub trait XsltEngine {
@@ -12,3 +15,66 @@ pub trait XsltEngine {
     /// Transform XML using a compiled stylesheet.
     fn transform(&self, compiled: &Self::Compiled, xml: &Bytes) -> Result<Bytes, Self::Error>;
 }
+
+/// Which `XsltEngine` implementation the worker runs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum XsltEngineKind {
+    #[default]
+    Xrust,
+    LibXslt,
+}
+impl fmt::Display for XsltEngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Xrust => write!(f, "xrust"),
+            Self::LibXslt => write!(f, "libxslt"),
+        }
+    }
+}
+impl FromStr for XsltEngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "xrust" => Ok(Self::Xrust),
+            "libxslt" => Ok(Self::LibXslt),
+            other => Err(format!("unknown xslt engine '{other}'")),
+        }
+    }
+}
+
+/// Parse an ordered engine chain like "xrust,libxslt". Duplicates are dropped.
+pub fn parse_engine_chain(s: &str) -> Result<Vec<XsltEngineKind>, String> {
+    let mut chain: Vec<XsltEngineKind> = Vec::new();
+    for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+        let kind: XsltEngineKind = part.parse()?;
+        if !chain.contains(&kind) {
+            chain.push(kind);
+        }
+    }
+    if chain.is_empty() {
+        return Err("empty xslt engine chain".to_string());
+    }
+    Ok(chain)
+}
+
+/// Server default chain: xrust first, libxslt when xrust cannot handle a stylesheet.
+pub fn default_engine_chain() -> Vec<XsltEngineKind> {
+    vec![XsltEngineKind::Xrust, XsltEngineKind::LibXslt]
+}
+
+/// Engine chain of the server, requests may still move their preferred engine to the front.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XsltConfig {
+    /// Tried in order, the next one when the previous fails
+    pub engines: Vec<XsltEngineKind>,
+}
+impl Default for XsltConfig {
+    fn default() -> Self {
+        Self {
+            engines: default_engine_chain(),
+        }
+    }
+}
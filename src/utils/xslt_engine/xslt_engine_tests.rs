@@ -0,0 +1,52 @@
+use super::libxslt_engine::LibXsltEngine;
+use super::xrust_engine::XrustEngine;
+use super::xslt_engine::{XsltEngine, XsltEngineKind};
+use crate::utils::errors::invoice_conversion_errors::InvConvError;
+use tokio_util::bytes::Bytes;
+
+const XSLT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xsl:stylesheet version="1.0" xmlns:xsl="http://www.w3.org/1999/XSL/Transform">
+  <xsl:output method="html"/>
+  <xsl:template match="/">
+    <html><body><p><xsl:value-of select="/Invoice/ID"/></p></body></html>
+  </xsl:template>
+</xsl:stylesheet>"#;
+
+const XML: &str =
+    r#"<?xml version="1.0" encoding="UTF-8"?><Invoice><ID>ABC2025000000001</ID></Invoice>"#;
+
+fn run<E: XsltEngine<Error = InvConvError>>(engine: E) -> String {
+    let compiled = engine
+        .compile(&Bytes::from_static(XSLT.as_bytes()))
+        .expect("compile failed");
+    let out = engine
+        .transform(&compiled, &Bytes::from_static(XML.as_bytes()))
+        .expect("transform failed");
+    String::from_utf8(out.to_vec()).unwrap()
+}
+
+#[test]
+fn libxslt_engine_transforms() {
+    let html = run(LibXsltEngine::new());
+    assert!(html.contains("<p>ABC2025000000001</p>"), "{html}");
+}
+
+#[test]
+fn libxslt_engine_rejects_invalid_stylesheet() {
+    let engine = LibXsltEngine::new();
+    assert!(engine.compile(&Bytes::from_static(b"<not-xslt/>")).is_err());
+    assert!(engine.compile(&Bytes::from_static(b"<broken")).is_err());
+}
+
+#[test]
+fn xrust_engine_transforms() {
+    let html = run(XrustEngine::new());
+    assert!(html.contains("ABC2025000000001"), "{html}");
+}
+
+#[test]
+fn engine_kind_parses_from_str() {
+    assert_eq!("libxslt".parse(), Ok(XsltEngineKind::LibXslt));
+    assert_eq!(" XRust ".parse(), Ok(XsltEngineKind::Xrust));
+    assert!("saxon".parse::<XsltEngineKind>().is_err());
+}